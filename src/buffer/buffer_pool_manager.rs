@@ -1,63 +1,384 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::fmt::{self, Write};
+use std::io;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
 
+use log::warn;
 use tokio::sync::oneshot;
 
-use super::lru_k_replacer::LRUKReplacer;
-use crate::common::config::{FrameId, PageId, LRUK_REPLACER_K};
-use crate::storage::disk::disk_manager::DiskManager;
-use crate::storage::disk::disk_scheduler::{DiskRequest, DiskScheduler};
-use crate::storage::page::page::Page;
+use super::replacer::{AccessType, FrameDebugInfo, Replacer, ReplacerType};
+use crate::common::config::{file_id_of, FileId, FrameId, Lsn, PageId, MAIN_FILE_ID};
+use crate::storage::disk::disk_manager::DiskManagerTrait;
+use crate::storage::disk::disk_scheduler::{DiskRequest, DiskScheduler, PageBuf};
+use crate::storage::page::page::{lsn_of, Page};
 use crate::storage::page::page_guard::{BasicPageGuard, ReadPageGuard, WritePageGuard};
 
+/// Why a buffer pool operation failed.
+#[derive(Debug)]
+pub enum BufferError {
+    /// All frames are pinned, so no frame can be freed for another page.
+    NoFreeFrame,
+    /// The page is not in the buffer pool.
+    PageNotFound,
+    /// The page is pinned and cannot be deleted.
+    PagePinned,
+    /// The page's pin count is already 0, so it cannot be unpinned.
+    PageNotPinned,
+    /// Reading or writing the database file failed, or the disk scheduler is
+    /// gone.
+    Io(io::Error),
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferError::NoFreeFrame => write!(f, "all frames are pinned"),
+            BufferError::PageNotFound => write!(f, "page is not in the buffer pool"),
+            BufferError::PagePinned => write!(f, "page is pinned"),
+            BufferError::PageNotPinned => write!(f, "page is not pinned"),
+            BufferError::Io(e) => write!(f, "disk I/O failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BufferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BufferError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BufferError {
+    fn from(e: io::Error) -> Self {
+        BufferError::Io(e)
+    }
+}
+
+/// Counters describing how a buffer pool has been used so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// fetch_page calls that found the page in the pool.
+    pub cache_hits: u64,
+    /// fetch_page calls that had to go to disk, or failed to find a frame.
+    pub cache_misses: u64,
+    /// Pages evicted by the replacer to make room for another page.
+    pub pages_evicted: u64,
+    /// Evicted pages that were dirty and written back before reusing the frame.
+    pub dirty_writebacks: u64,
+    /// Pages created by new_page.
+    pub pages_created: u64,
+    /// Pages removed from the pool by delete_page.
+    pub pages_deleted: u64,
+}
+
+impl AddAssign for BufferPoolStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.cache_hits += rhs.cache_hits;
+        self.cache_misses += rhs.cache_misses;
+        self.pages_evicted += rhs.pages_evicted;
+        self.dirty_writebacks += rhs.dirty_writebacks;
+        self.pages_created += rhs.pages_created;
+        self.pages_deleted += rhs.pages_deleted;
+    }
+}
+
+/// A snapshot of the state of a page in the buffer pool, see inspect_page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageInfo {
+    /// The frame holding the page.
+    pub frame_id: FrameId,
+    /// How many users currently have the page pinned.
+    pub pin_count: i32,
+    /// Whether the page was modified since it was last written to disk.
+    pub is_dirty: bool,
+    /// Whether the replacer may pick the frame as a victim, i.e. the page is
+    /// not pinned.
+    pub is_evictable: bool,
+}
+
+/// What happened to a page deleted by delete_page or delete_page_flush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteOutcome {
+    /// The page was not in the buffer pool, only its id was deallocated.
+    NotResident,
+    /// The page was in the buffer pool and had no unwritten changes.
+    Clean,
+    /// The page was dirty and its changes were thrown away.
+    DiscardedDirty,
+    /// The page was dirty and its changes were written back before it was
+    /// deleted.
+    Flushed,
+}
+
+/// The live counters behind BufferPoolStats.
+#[derive(Default)]
+struct StatsCounters {
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    pages_evicted: AtomicU64,
+    dirty_writebacks: AtomicU64,
+    pages_created: AtomicU64,
+    pages_deleted: AtomicU64,
+}
+
+impl StatsCounters {
+    fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            pages_evicted: self.pages_evicted.load(Ordering::Relaxed),
+            dirty_writebacks: self.dirty_writebacks.load(Ordering::Relaxed),
+            pages_created: self.pages_created.load(Ordering::Relaxed),
+            pages_deleted: self.pages_deleted.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.cache_hits,
+            &self.cache_misses,
+            &self.pages_evicted,
+            &self.dirty_writebacks,
+            &self.pages_created,
+            &self.pages_deleted,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Handle of the thread started by `BufferPoolManager::start_background_flush`.
+struct BackgroundFlusher {
+    /// Dropping or sending on it tells the thread to exit.
+    stop: mpsc::Sender<()>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl BackgroundFlusher {
+    fn stop(self) {
+        drop(self.stop);
+        // The flusher may be the one dropping the BPM, don't wait for ourselves
+        if self.handle.thread().id() != thread::current().id() && self.handle.join().is_err() {
+            warn!("background flusher exited with a panic");
+        }
+    }
+}
+
+/// Writes scheduled by `BufferPoolManager::flush_dirty_pages_async`.
+///
+/// The pages stay pinned until their writes are waited for, by `join` or
+/// when the handle is dropped.
+pub struct FlushHandle<'a> {
+    bpm: &'a BufferPoolManager,
+    writes: Vec<(PageId, Page, oneshot::Receiver<io::Result<()>>)>,
+}
+
+impl FlushHandle<'_> {
+    /// @brief Return the number of writes that were scheduled.
+    pub fn num_scheduled(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// @brief Wait for all the writes to complete.
+    /// @return the number of pages written, or the first error if any write
+    /// failed. Pages that could not be written are dirty again.
+    pub fn join(mut self) -> Result<usize, BufferError> {
+        self.wait()
+    }
+
+    fn wait(&mut self) -> Result<usize, BufferError> {
+        let mut written = 0;
+        let mut first_err = None;
+        for (page_id, page, rx) in self.writes.drain(..) {
+            match BufferPoolManager::wait_for_disk(rx) {
+                Ok(()) => written += 1,
+                Err(e) => {
+                    page.set_dirty(true);
+                    first_err.get_or_insert(e);
+                }
+            }
+            // pinned by flush_dirty_pages_async, so this cannot fail
+            let _ = self.bpm.unpin_page(page_id, false);
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(written),
+        }
+    }
+}
+
+impl Drop for FlushHandle<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.wait() {
+            warn!("flushing dirty pages failed: {}", e);
+        }
+    }
+}
+
 /// BufferPoolManager reads disk pages to and from its internal buffer pool.
 pub struct BufferPoolManager {
     /// Number of pages in the buffer pool.
     pool_size: usize,
-    /// The next page id to be allocated
-    next_page_id: AtomicUsize,
-
+    /// Number of instances in the parallel BPM this one belongs to, 1 if it
+    /// stands alone.
+    num_instances: usize,
+    /// Index of this instance in the parallel BPM, 0 if it stands alone.
+    instance_index: usize,
     /// Array of buffer pool pages.
     pages: Vec<Page>,
-    /// Pointer to the disk scheduler.
-    disk_scheduler: DiskScheduler,
+    /// Pointer to the disk scheduler, shared by all instances of a parallel
+    /// BPM.
+    disk_scheduler: Arc<DiskScheduler>,
     /// Pointer to the log manager. Please ignore this for P1.
     // log_manager: Option<Arc<LogManager>>,
-    /// Page table for keeping track of buffer pool pages.
+    /// Page table for keeping track of buffer pool pages. Only held to look
+    /// up or change a mapping, never while waiting for anything else.
     page_table: Mutex<HashMap<PageId, FrameId>>,
+    /// One latch per frame. It is held while the frame changes hands, while
+    /// its page is read from or written back to disk, and whenever the pin
+    /// count of its page changes. A page table lookup is only trusted once
+    /// the frame's latch is held and the frame still holds the page, see
+    /// latch_page.
+    ///
+    /// Lock order: frame latch, then page table, eviction latch, replacer and
+    /// free list.
+    /// No frame latch is taken while holding the page table. A thread only
+    /// holds several frame latches for frames it is reading pages into,
+    /// which are pinned, so nobody can be waiting for them to be evicted.
+    /// The data latch of a page (see Page::rlatch) is only taken while
+    /// holding the frame latch if the page is unpinned or being read in, so
+    /// that nobody can hold it. Whoever latches a page has it pinned, and may
+    /// fetch or unpin pages while holding the data latch.
+    frame_latches: Vec<Mutex<()>>,
     /// Replacer to find unpinned pages for replacement.
-    replacer: LRUKReplacer,
+    replacer: Box<dyn Replacer>,
+    /// Held while the replacer picks a victim and while a pinned frame is
+    /// tracked as non-evictable, see track_pinned. Taken after the frame
+    /// latch.
+    eviction_latch: Mutex<()>,
     /// List of free frames that don't have any pages on them.
     free_list: Mutex<Vec<FrameId>>,
+    /// Usage statistics, see get_stats.
+    stats: StatsCounters,
+    /// The background flusher thread, if one is running.
+    flusher: Mutex<Option<BackgroundFlusher>>,
+    /// The largest LSN of the page snapshots handed to the disk so far. Once
+    /// there is a log manager, the log has to be flushed up to a page's LSN
+    /// before the page is written (the WAL rule).
+    max_written_lsn: AtomicU64,
 }
 
 impl BufferPoolManager {
     /// @brief Creates a new BufferPoolManager.
     /// @param pool_size the size of the buffer pool
     /// @param disk_manager the disk manager
-    /// @param replacer_k the LookBack constant k for the LRU-K replacer
+    /// @param replacer_type the replacement policy used to pick victim frames
     /// @param log_manager the log manager (for testing only: nullptr = disable
     /// logging). Please ignore this for P1.
     pub fn new(
         pool_size: usize,
-        disk_manager: DiskManager,
-        replacer_k: usize,
+        disk_manager: Box<dyn DiskManagerTrait>,
+        replacer_type: ReplacerType,
         // log_manager: Option<Arc<LogManager>>,
     ) -> BufferPoolManager {
+        Self::new_instance(
+            pool_size,
+            1,
+            0,
+            Arc::new(DiskScheduler::new(disk_manager)),
+            replacer_type,
+        )
+    }
+
+    /// @brief Creates a new BufferPoolManager as one instance of a parallel
+    /// BPM. The instance only allocates page ids `p` with
+    /// `p % num_instances == instance_index`.
+    /// @param pool_size the size of the buffer pool of this instance
+    /// @param num_instances total number of instances in the parallel BPM
+    /// @param instance_index index of this instance in the parallel BPM
+    /// @param disk_scheduler the disk scheduler shared by all instances
+    /// @param replacer_type the replacement policy used to pick victim frames
+    pub fn new_instance(
+        pool_size: usize,
+        num_instances: usize,
+        instance_index: usize,
+        disk_scheduler: Arc<DiskScheduler>,
+        replacer_type: ReplacerType,
+    ) -> BufferPoolManager {
+        Self::new_instance_with_replacer(
+            pool_size,
+            num_instances,
+            instance_index,
+            disk_scheduler,
+            replacer_type.create(pool_size),
+        )
+    }
+
+    /// @brief Creates a new BufferPoolManager that picks victim frames with
+    /// the given replacer, e.g. a policy that is not one of ReplacerType.
+    /// @param pool_size the size of the buffer pool
+    /// @param disk_manager the disk manager
+    /// @param replacer the replacer, for frame ids below pool_size
+    pub fn with_replacer(
+        pool_size: usize,
+        disk_manager: Box<dyn DiskManagerTrait>,
+        replacer: Box<dyn Replacer>,
+    ) -> BufferPoolManager {
+        Self::new_instance_with_replacer(
+            pool_size,
+            1,
+            0,
+            Arc::new(DiskScheduler::new(disk_manager)),
+            replacer,
+        )
+    }
+
+    /// @brief Shared by new_instance and with_replacer.
+    fn new_instance_with_replacer(
+        pool_size: usize,
+        num_instances: usize,
+        instance_index: usize,
+        disk_scheduler: Arc<DiskScheduler>,
+        replacer: Box<dyn Replacer>,
+    ) -> BufferPoolManager {
+        assert!(num_instances > 0, "a BPM needs at least one instance");
+        assert!(
+            instance_index < num_instances,
+            "instance index {} out of range for {} instances",
+            instance_index,
+            num_instances
+        );
         let mut free_list = Vec::with_capacity(pool_size);
         for i in (0..pool_size).rev() {
             free_list.push(i as FrameId);
         }
         Self {
             pool_size,
-            next_page_id: AtomicUsize::new(0),
+            num_instances,
+            instance_index,
             pages: (0..pool_size).map(|_| Page::new()).collect(),
-            disk_scheduler: DiskScheduler::new(disk_manager),
+            disk_scheduler,
             // log_manager,
             page_table: Mutex::new(HashMap::new()),
-            replacer: LRUKReplacer::new(replacer_k, LRUK_REPLACER_K),
+            frame_latches: (0..pool_size).map(|_| Mutex::new(())).collect(),
+            replacer,
+            eviction_latch: Mutex::new(()),
             free_list: Mutex::new(free_list),
+            stats: StatsCounters::default(),
+            flusher: Mutex::new(None),
+            max_written_lsn: AtomicU64::new(0),
         }
     }
 
@@ -71,11 +392,98 @@ impl BufferPoolManager {
         &self.pages
     }
 
+    /// @brief Return the disk manager backing the buffer pool.
+    pub fn get_disk_manager(&self) -> &dyn DiskManagerTrait {
+        self.disk_scheduler.get_disk_manager()
+    }
+
+    /// @brief Return the largest LSN of the pages written back so far, as
+    /// captured when their data was copied for the write.
+    pub fn get_max_written_lsn(&self) -> Lsn {
+        self.max_written_lsn.load(Ordering::Relaxed)
+    }
+
+    /// @brief Return the usage statistics gathered since the buffer pool was
+    /// created or reset_stats was last called.
+    pub fn get_stats(&self) -> BufferPoolStats {
+        self.stats.snapshot()
+    }
+
+    /// @brief Reset all usage statistics to zero.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// @brief Look up the state of a page without fetching it. Unlike
+    /// fetch_page, this neither pins the page nor records an access, so the
+    /// replacer's history and the usage statistics are left untouched.
+    ///
+    /// Waits for a read or write-back of the page that is in progress.
+    ///
+    /// @param page_id id of page to look up
+    /// @return the state of the page, or none if it is not in the buffer pool
+    pub fn inspect_page(&self, page_id: PageId) -> Option<PageInfo> {
+        let (frame_id, _latch) = self.latch_page(page_id)?;
+        let page = &self.pages[frame_id];
+        let pin_count = page.get_pin_count();
+        Some(PageInfo {
+            frame_id,
+            pin_count,
+            is_dirty: page.is_dirty(),
+            is_evictable: pin_count == 0,
+        })
+    }
+
+    /// @brief Check whether a page is in the buffer pool, without fetching it.
+    /// @param page_id id of page to look up
+    /// @return true if the page is in the buffer pool
+    pub fn contains_page(&self, page_id: PageId) -> bool {
+        self.page_table.lock().unwrap().contains_key(&page_id)
+    }
+
+    /// @brief Describe the frames that hold a page, for debugging eviction
+    /// decisions. Each line has the page id, pin count and dirty flag of a
+    /// frame next to what the replacer knows about it, see Replacer::dump.
+    /// Frames are listed in the order of the replacer's dump, followed by
+    /// the frames it does not describe. Free frames are left out.
+    /// @return a table with one frame per line
+    pub fn debug_dump(&self) -> String {
+        let mut infos: HashMap<FrameId, FrameDebugInfo> = HashMap::new();
+        let mut frame_ids = Vec::with_capacity(self.pool_size);
+        for info in self.replacer.dump() {
+            frame_ids.push(info.frame_id);
+            infos.insert(info.frame_id, info);
+        }
+        frame_ids.extend((0..self.pool_size).filter(|frame_id| !infos.contains_key(frame_id)));
+
+        let mut out = String::from("frame page pins dirty evictable scan  distance history\n");
+        for frame_id in frame_ids {
+            let _latch = self.frame_latches[frame_id].lock().unwrap();
+            let page = &self.pages[frame_id];
+            let Some(page_id) = page.get_page_id() else {
+                continue;
+            };
+            let _ = write!(
+                out,
+                "{:>5} {:>4} {:>4} {:<5}",
+                frame_id,
+                page_id,
+                page.get_pin_count(),
+                page.is_dirty()
+            );
+            let _ = match infos.get(&frame_id) {
+                Some(info) => writeln!(out, " {}", info),
+                None => writeln!(out),
+            };
+        }
+        out
+    }
+
     /// TODO(P1): Add implementation
     ///
-    /// @brief Create a new page in the buffer pool. Set page_id to the new
-    /// page's id, or none if all frames are currently in use and not
-    /// evictable (in another word, pinned).
+    /// @brief Create a new page in the buffer pool. Fails with NoFreeFrame if
+    /// all frames are currently in use and not evictable (in another word,
+    /// pinned).
     ///
     /// You should pick the replacement frame from either the free list or the
     /// replacer (always find from the free list first), and then call the
@@ -88,59 +496,54 @@ impl BufferPoolManager {
     /// buffer pool manager "Unpin"s it. Also, remember to record the access
     /// history of the frame in the replacer for the lru-k algorithm to work.
     ///
-    /// @return none if no new pages could be created, otherwise pointer to
-    /// new page
-    pub fn new_page(&self) -> Option<Page> {
-        let frame_id = if let Some(frame_id) = self.free_list.lock().unwrap().pop() {
-            frame_id
-        } else if let Some(frame_id) = self.replacer.evict() {
-            let page = &self.pages[frame_id];
-            if page.is_dirty() {
-                let (tx, rx) = oneshot::channel();
-                self.disk_scheduler.schedule(DiskRequest::Write {
-                    page: page.clone(),
-                    callback: tx,
-                });
-                rx.blocking_recv().unwrap();
+    /// @return the new page, NoFreeFrame if all frames are pinned, or Io if
+    /// the victim could not be written back or no page id could be allocated
+    pub fn new_page(&self) -> Result<Page, BufferError> {
+        self.new_page_in(MAIN_FILE_ID)
+    }
+
+    /// @brief Create a new page in the data file file_id, see new_page.
+    /// @return the new page, or the error of new_page
+    pub fn new_page_in(&self, file_id: FileId) -> Result<Page, BufferError> {
+        let (frame_id, _latch) = self.find_frame()?;
+
+        let page_id = match self.allocate_page(file_id) {
+            Ok(page_id) => page_id,
+            Err(e) => {
+                self.free_list.lock().unwrap().push(frame_id);
+                return Err(e);
             }
-            self.page_table
-                .lock()
-                .unwrap()
-                .remove(&page.get_page_id().unwrap());
-            frame_id
-        } else {
-            return None;
         };
-
-        let page_id = self.allocate_page();
-        let page = &self.pages[frame_id];
-        page.set_page_id(page_id);
-        page.pin();
         self.page_table.lock().unwrap().insert(page_id, frame_id);
-        self.replacer.record_access(frame_id);
-        self.replacer.set_evictable(frame_id, false);
+        let page = self.reserve_frame(frame_id, page_id, AccessType::Unknown);
+        StatsCounters::incr(&self.stats.pages_created);
 
-        Some(page.clone())
+        Ok(page)
     }
 
-    /// TODO(P2): Add implementation
-    ///
     /// @brief PageGuard wrapper for NewPage
     ///
     /// Functionality should be the same as NewPage, except that
     /// instead of returning a pointer to a page, you return a
     /// BasicPageGuard structure.
     ///
-    /// @return BasicPageGuard holding a new page
-    pub fn new_page_guarded(self: Arc<Self>) -> Option<BasicPageGuard> {
-        unimplemented!()
+    /// @param[out] page_id set to the id of the new page, or none
+    /// @return BasicPageGuard holding a new page, or the error of NewPage
+    pub fn new_page_guarded(
+        self: Arc<Self>,
+        page_id: &mut Option<PageId>,
+    ) -> Result<BasicPageGuard, BufferError> {
+        let page = self.new_page();
+        *page_id = page.as_ref().ok().and_then(|p| p.get_page_id());
+        Ok(BasicPageGuard::new(self, page?))
     }
 
     /// TODO(P1): Add implementation
     ///
-    /// @brief Fetch the requested page from the buffer pool. Return none if
-    /// page_id needs to be fetched from the disk but all frames are
-    /// currently in use and not evictable (in another word, pinned).
+    /// @brief Fetch the requested page from the buffer pool. Fails with
+    /// NoFreeFrame if page_id needs to be fetched from the disk but all
+    /// frames are currently in use and not evictable (in another word,
+    /// pinned).
     ///
     /// First search for page_id in the buffer pool. If not found, pick a
     /// replacement frame from either the free list or the replacer (always
@@ -153,56 +556,88 @@ impl BufferPoolManager {
     /// In addition, remember to disable eviction and record the access history
     /// of the frame like you did for NewPage().
     ///
+    /// Concurrent fetches of a page that is not resident read it only once:
+    /// the first one puts it into the page table, the others wait on the
+    /// frame latch for its read.
+    ///
     /// @param page_id id of page to be fetched
-    /// @return nullptr if page_id cannot be fetched,
-    /// otherwise pointer to the requested page
-    pub fn fetch_page(&self, page_id: PageId) -> Option<Page> {
-        if let Some(frame_id) = self.page_table.lock().unwrap().get(&page_id) {
-            let page = &self.pages[*frame_id];
-            page.pin();
-            self.replacer.record_access(*frame_id);
-            return Some(page.clone());
-        }
+    /// @param access_type type of access to the page, only needed for
+    /// leaderboard tests.
+    /// @return the requested page, NoFreeFrame if all frames are pinned, or Io
+    /// if the page could not be read or the victim could not be written back
+    pub fn fetch_page(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<Page, BufferError> {
+        loop {
+            if let Some((frame_id, _latch)) = self.latch_page(page_id) {
+                StatsCounters::incr(&self.stats.cache_hits);
+                let page = &self.pages[frame_id];
+                page.pin();
+                self.track_pinned(frame_id, access_type);
+                return Ok(page.clone());
+            }
 
-        let frame_id = if let Some(frame_id) = self.free_list.lock().unwrap().pop() {
-            frame_id
-        } else if let Some(frame_id) = self.replacer.evict() {
-            let page = &self.pages[frame_id];
-            if page.is_dirty() {
-                let (tx, rx) = oneshot::channel();
-                self.disk_scheduler.schedule(DiskRequest::Write {
-                    page: page.clone(),
-                    callback: tx,
-                });
-                rx.blocking_recv().unwrap();
+            let (frame_id, _latch) = match self.find_frame() {
+                Ok(frame) => frame,
+                Err(e) => {
+                    StatsCounters::incr(&self.stats.cache_misses);
+                    return Err(e);
+                }
+            };
+            if !self.map_page(page_id, frame_id) {
+                // somebody else brought the page in while we were looking for
+                // a frame
+                self.free_list.lock().unwrap().push(frame_id);
+                continue;
             }
-            self.page_table
-                .lock()
-                .unwrap()
-                .remove(&page.get_page_id().unwrap());
-            frame_id
-        } else {
-            return None;
-        };
+            StatsCounters::incr(&self.stats.cache_misses);
+            let page = self.reserve_frame(frame_id, page_id, access_type);
 
-        let page = &self.pages[frame_id];
-        page.set_page_id(page_id);
-        page.pin();
-        let (tx, rx) = oneshot::channel();
-        self.disk_scheduler.schedule(DiskRequest::Read {
-            page: page.clone(),
-            callback: tx,
-        });
-        rx.blocking_recv().unwrap();
-        self.page_table.lock().unwrap().insert(page_id, frame_id);
-        self.replacer.record_access(frame_id);
-        self.replacer.set_evictable(frame_id, false);
+            // the caller waits for the page, so the read goes ahead of
+            // write-backs and prefetches
+            let rx = self.disk_scheduler.schedule_urgent_read(page_id);
+            self.finish_load(page_id, frame_id, Self::wait_for_disk(rx))?;
+            return Ok(page);
+        }
+    }
 
-        Some(page.clone())
+    /// @brief Bring the requested pages into the buffer pool ahead of time.
+    ///
+    /// Frames are picked for all pages that are not resident yet and every
+    /// read is scheduled before waiting on any of them, so the reads overlap.
+    /// Prefetched pages are left unpinned and are recorded as scan accesses.
+    /// Pages that cannot get a frame or cannot be read are skipped.
+    ///
+    /// @param page_ids ids of the pages to prefetch
+    /// @return the number of pages read into the buffer pool
+    pub fn prefetch_pages(&self, page_ids: &[PageId]) -> usize {
+        self.load_pages(page_ids, AccessType::Scan, false)
     }
 
-    /// TODO(P2): Add implementation
+    /// @brief Fill the free frames of a cold buffer pool, e.g. with the pages
+    /// returned by resident_page_ids before the last shutdown.
     ///
+    /// Like prefetch_pages, all reads are in flight at the same time and the
+    /// pages are left unpinned. Unlike it, no page is evicted to make room,
+    /// so loading stops once the free list is empty, and the pages are
+    /// recorded as regular accesses.
+    ///
+    /// @param page_ids ids of the pages to load, most important first
+    /// @return the number of pages read into the buffer pool
+    pub fn warm_up(&self, page_ids: &[PageId]) -> usize {
+        self.load_pages(page_ids, AccessType::Unknown, true)
+    }
+
+    /// @brief Return the ids of all pages currently in the buffer pool, in
+    /// ascending order. Pages still being read from disk are included.
+    pub fn resident_page_ids(&self) -> Vec<PageId> {
+        let mut page_ids: Vec<PageId> = self.page_table.lock().unwrap().keys().copied().collect();
+        page_ids.sort_unstable();
+        page_ids
+    }
+
     /// @brief PageGuard wrappers for FetchPage
     ///
     /// Functionality should be the same as FetchPage, except
@@ -211,21 +646,30 @@ impl BufferPoolManager {
     /// the returned page already has a read or write latch held, respectively.
     ///
     /// @param page_id, the id of the page to fetch
-    /// @return PageGuard holding the fetched page
-    pub fn fetch_page_basic(self: Arc<Self>, page_id: PageId) -> Option<BasicPageGuard> {
-        unimplemented!()
+    /// @return PageGuard holding the fetched page, or the error of FetchPage
+    pub fn fetch_page_basic(
+        self: Arc<Self>,
+        page_id: PageId,
+    ) -> Result<BasicPageGuard, BufferError> {
+        let page = self.fetch_page(page_id, AccessType::Unknown)?;
+        Ok(BasicPageGuard::new(self, page))
     }
-    pub fn fetch_page_read(self: Arc<Self>, page_id: PageId) -> Option<ReadPageGuard> {
-        unimplemented!()
+    pub fn fetch_page_read(self: Arc<Self>, page_id: PageId) -> Result<ReadPageGuard, BufferError> {
+        let page = self.fetch_page(page_id, AccessType::Unknown)?;
+        Ok(ReadPageGuard::new(self, page))
     }
-    pub fn fetch_page_write(self: Arc<Self>, page_id: PageId) -> Option<WritePageGuard> {
-        unimplemented!()
+    pub fn fetch_page_write(
+        self: Arc<Self>,
+        page_id: PageId,
+    ) -> Result<WritePageGuard, BufferError> {
+        let page = self.fetch_page(page_id, AccessType::Unknown)?;
+        Ok(WritePageGuard::new(self, page))
     }
 
     /// TODO(P1): Add implementation
     ///
-    /// @brief Unpin the target page from the buffer pool. If page_id is not in
-    /// the buffer pool or its pin count is already 0, return false.
+    /// @brief Unpin the target page from the buffer pool. Fails if page_id is
+    /// not in the buffer pool or its pin count is already 0.
     ///
     /// Decrement the pin count of a page. If the pin count reaches 0, the frame
     /// should be evictable by the replacer. Also, set the dirty flag on the
@@ -233,23 +677,23 @@ impl BufferPoolManager {
     ///
     /// @param page_id id of page to be unpinned
     /// @param is_dirty true if the page should be marked as dirty, false
-    /// otherwise @return false if the page is not in the page
-    /// table or its pin count is <= 0 before this call, true otherwise
-    pub fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> bool {
-        if let Some(frame_id) = self.page_table.lock().unwrap().get(&page_id) {
-            let page = &self.pages[*frame_id];
-            if page.get_pin_count() <= 0 {
-                return false;
-            }
-            page.set_dirty(is_dirty);
-            page.unpin();
-            if page.get_pin_count() == 0 {
-                self.replacer.set_evictable(*frame_id, true);
-            }
-            true
-        } else {
-            false
+    /// otherwise @return PageNotFound if the page is not in the page table,
+    /// PageNotPinned if its pin count is <= 0 before this call
+    pub fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> Result<(), BufferError> {
+        let (frame_id, _latch) = self.latch_page(page_id).ok_or(BufferError::PageNotFound)?;
+        let page = &self.pages[frame_id];
+        if page.get_pin_count() <= 0 {
+            return Err(BufferError::PageNotPinned);
+        }
+        // never clear the flag here, another user may have dirtied it
+        if is_dirty {
+            page.set_dirty(true);
+        }
+        page.unpin();
+        if page.get_pin_count() == 0 {
+            self.replacer.set_evictable(frame_id, true);
         }
+        Ok(())
     }
 
     /// TODO(P1): Add implementation
@@ -261,35 +705,165 @@ impl BufferPoolManager {
     /// flushing.
     ///
     /// @param page_id id of page to be flushed, cannot be INVALID_PAGE_ID
-    /// @return false if the page could not be found in the page table, true
-    /// otherwise
-    pub fn flush_page(&self, page_id: PageId) -> bool {
-        if let Some(frame_id) = self.page_table.lock().unwrap().get(&page_id) {
-            let page = &self.pages[*frame_id];
-            let (tx, rx) = oneshot::channel();
-            self.disk_scheduler.schedule(DiskRequest::Write {
-                page: page.clone(),
-                callback: tx,
-            });
-            rx.blocking_recv().unwrap();
-            true
-        } else {
-            false
-        }
+    /// @return PageNotFound if the page could not be found in the page table,
+    /// Io if the write failed, in which case the page stays dirty
+    pub fn flush_page(&self, page_id: PageId) -> Result<(), BufferError> {
+        // The latch makes sure a page still being read in is never written
+        // out. It is only held to pin the page, which keeps the frame from
+        // being reused while the page is copied and written, as in
+        // flush_unpinned_pages.
+        let frame_id = {
+            let (frame_id, _latch) = self.latch_page(page_id).ok_or(BufferError::PageNotFound)?;
+            self.pin_for_flush(frame_id);
+            frame_id
+        };
+        let page = &self.pages[frame_id];
+        page.set_dirty(false);
+        let rx = self.schedule_page_write(page_id, page);
+        let res = Self::wait_for_disk(rx);
+        // we pinned the page above, so unpinning it cannot fail
+        let _ = self.unpin_page(page_id, res.is_err());
+        res
+    }
+
+    /// @brief Make every page written back so far durable, whatever the sync
+    /// policy of the disk manager. Call it after flush_page or
+    /// flush_all_pages to be sure the pages survive a crash.
+    /// @return Io if the sync failed
+    pub fn sync(&self) -> Result<(), BufferError> {
+        Self::wait_for_disk(self.disk_scheduler.schedule_sync())
     }
 
     /// TODO(P1): Add implementation
     ///
-    /// @brief Flush all the pages in the buffer pool to disk.
-    pub fn flush_all_pages(&mut self) {
+    /// @brief Flush all the dirty pages in the buffer pool to disk, pinned or
+    /// not, and unset their dirty flags. Stops at the first write that fails.
+    pub fn flush_all_pages(&mut self) -> Result<(), BufferError> {
         for page in self.pages.iter() {
-            if page.is_dirty() {
-                let (tx, rx) = oneshot::channel();
-                self.disk_scheduler.schedule(DiskRequest::Write {
-                    page: page.clone(),
-                    callback: tx,
-                });
-                rx.blocking_recv().unwrap();
+            if let Some(page_id) = page.get_page_id().filter(|_| page.is_dirty()) {
+                let rx = self.schedule_page_write(page_id, page);
+                Self::wait_for_disk(rx)?;
+                page.set_dirty(false);
+            }
+        }
+        Ok(())
+    }
+
+    /// @brief Write back all the dirty pages in the buffer pool, pinned or
+    /// not, e.g. for a checkpoint.
+    ///
+    /// Unlike flush_all_pages, all the writes are scheduled before waiting for
+    /// any of them, so they overlap.
+    ///
+    /// @return the number of pages written, or the first error if any write
+    /// failed
+    pub fn flush_dirty_pages(&self) -> Result<usize, BufferError> {
+        self.flush_dirty_pages_async().join()
+    }
+
+    /// @brief Schedule writes for all the dirty pages in the buffer pool
+    /// without waiting for them. The writes are scheduled as one batch, see
+    /// DiskManagerTrait::write_pages.
+    ///
+    /// Each page is pinned until its write is waited for, so that its frame
+    /// cannot be reused in between. As in the background flusher, the dirty
+    /// flag is cleared before the write is scheduled, so that an update that
+    /// misses the write marks the page dirty again when it is unpinned.
+    ///
+    /// @return a handle to wait for the writes with
+    pub fn flush_dirty_pages_async(&self) -> FlushHandle<'_> {
+        let mut batch = Vec::new();
+        let mut pages = Vec::new();
+        for (frame_id, page) in self.pages.iter().enumerate() {
+            let page_id = {
+                let _latch = self.frame_latches[frame_id].lock().unwrap();
+                match page.get_page_id() {
+                    Some(page_id) if page.is_dirty() => {
+                        self.pin_for_flush(frame_id);
+                        page_id
+                    }
+                    _ => continue,
+                }
+            };
+            page.set_dirty(false);
+            batch.push((page_id, self.snapshot_for_write(page)));
+            pages.push((page_id, page.clone()));
+        }
+        let receivers = self.disk_scheduler.schedule_write_batch(batch);
+        let writes = pages
+            .into_iter()
+            .zip(receivers)
+            .map(|((page_id, page), rx)| (page_id, page, rx))
+            .collect();
+        FlushHandle { bpm: self, writes }
+    }
+
+    /// @brief Start a background thread that writes back dirty pages every
+    /// `interval`, so modifications reach the disk before the pages are
+    /// evicted. A flusher that is already running is replaced.
+    ///
+    /// Only unpinned pages are written, and the writes take the page latch in
+    /// shared mode, so a page is never written while someone is in the middle
+    /// of updating it. The thread exits when stop_background_flush is called
+    /// or the buffer pool is dropped.
+    pub fn start_background_flush(self: &Arc<Self>, interval: Duration) {
+        let (stop, stop_rx) = mpsc::channel();
+        let bpm = Arc::downgrade(self);
+        let handle = thread::spawn(move || Self::background_flush(bpm, stop_rx, interval));
+        let old = self
+            .flusher
+            .lock()
+            .unwrap()
+            .replace(BackgroundFlusher { stop, handle });
+        if let Some(old) = old {
+            old.stop();
+        }
+    }
+
+    /// @brief Stop the background flusher, waiting for a pass in progress to
+    /// finish. Does nothing if no flusher is running.
+    pub fn stop_background_flush(&self) {
+        let flusher = self.flusher.lock().unwrap().take();
+        if let Some(flusher) = flusher {
+            flusher.stop();
+        }
+    }
+
+    fn background_flush(bpm: Weak<Self>, stop: mpsc::Receiver<()>, interval: Duration) {
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            match bpm.upgrade() {
+                Some(bpm) => bpm.flush_unpinned_pages(),
+                None => break,
+            }
+        }
+    }
+
+    /// @brief Write back every dirty page that is not pinned.
+    ///
+    /// Each page is pinned while it is written so that it cannot be evicted
+    /// in between. Its dirty flag is cleared before the write is scheduled: an
+    /// update that happens after the write has copied the data marks the page
+    /// dirty again when it is unpinned.
+    fn flush_unpinned_pages(&self) {
+        for (frame_id, page) in self.pages.iter().enumerate() {
+            let page_id = {
+                let _latch = self.frame_latches[frame_id].lock().unwrap();
+                match page.get_page_id() {
+                    Some(page_id) if page.get_pin_count() == 0 && page.is_dirty() => {
+                        self.pin_for_flush(frame_id);
+                        page_id
+                    }
+                    _ => continue,
+                }
+            };
+            page.set_dirty(false);
+            let rx = self.schedule_page_write(page_id, page);
+            let res = Self::wait_for_disk(rx);
+            // we pinned the page above, so unpinning it cannot fail
+            let _ = self.unpin_page(page_id, res.is_err());
+            if let Err(e) = res {
+                warn!("background flush stopped: {}", e);
+                return;
             }
         }
     }
@@ -297,188 +871,2062 @@ impl BufferPoolManager {
     /// TODO(P1): Add implementation
     ///
     /// @brief Delete a page from the buffer pool. If page_id is not in the
-    /// buffer pool, do nothing and return true. If the page is pinned and
-    /// cannot be deleted, return false immediately.
+    /// buffer pool, only deallocate it on disk and return true. If the page
+    /// is pinned and cannot be deleted, return false immediately.
     ///
     /// After deleting the page from the page table, stop tracking the frame in
     /// the replacer and add the frame back to the free list. Also, reset
     /// the page's memory and metadata. Finally, you should call
     /// DeallocatePage() to imitate freeing the page on the disk.
     ///
+    /// Changes of a dirty page are thrown away, use delete_page_flush to keep
+    /// them on disk. Either way the page id is handed out again by a later
+    /// new_page.
+    ///
     /// @param page_id id of page to be deleted
-    /// @return false if the page exists but could not be deleted, true if the
-    /// page didn't exist or deletion succeeded
-    pub fn delete_page(&self, page_id: PageId) -> bool {
-        if let Some(frame_id) = self.page_table.lock().unwrap().get(&page_id) {
-            let page = &self.pages[*frame_id];
+    /// @return PagePinned if the page exists but could not be deleted, Io if
+    /// the page id could not be deallocated on disk, otherwise whether the
+    /// page was resident and whether dirty changes were discarded
+    pub fn delete_page(&self, page_id: PageId) -> Result<DeleteOutcome, BufferError> {
+        self.delete_page_impl(page_id, false)
+    }
+
+    /// @brief Delete a page from the buffer pool like delete_page, but write
+    /// a dirty page back before it is deleted. Useful when the page moves
+    /// elsewhere and its last contents must survive on disk.
+    ///
+    /// @param page_id id of page to be deleted
+    /// @return PagePinned if the page exists but could not be deleted, Io if
+    /// the write-back failed, in which case the page stays in the buffer pool
+    /// and dirty, or if the page id could not be deallocated on disk
+    pub fn delete_page_flush(&self, page_id: PageId) -> Result<DeleteOutcome, BufferError> {
+        self.delete_page_impl(page_id, true)
+    }
+
+    /// @brief Shared implementation of delete_page and delete_page_flush.
+    /// @param flush whether a dirty page is written back before deleting it
+    fn delete_page_impl(&self, page_id: PageId, flush: bool) -> Result<DeleteOutcome, BufferError> {
+        let mut outcome = DeleteOutcome::NotResident;
+        if let Some((frame_id, _latch)) = self.latch_page(page_id) {
+            let page = &self.pages[frame_id];
             if page.get_pin_count() > 0 {
-                return false;
+                return Err(BufferError::PagePinned);
             }
-            self.page_table.lock().unwrap().remove(&page_id);
-            self.replacer.remove(*frame_id);
-            self.free_list.lock().unwrap().push(*frame_id);
-            page.reset();
-            self.deallocate_page(page_id);
-            true
-        } else {
-            true
+            outcome = match (page.is_dirty(), flush) {
+                (false, _) => DeleteOutcome::Clean,
+                (true, false) => DeleteOutcome::DiscardedDirty,
+                (true, true) => {
+                    let rx = self.schedule_page_write(page_id, page);
+                    Self::wait_for_disk(rx)?;
+                    DeleteOutcome::Flushed
+                }
+            };
+            self.discard_frame(page_id, frame_id);
+            StatsCounters::incr(&self.stats.pages_deleted);
         }
+        self.deallocate_page(page_id)?;
+        Ok(outcome)
     }
 
-    /// @brief Allocate a page on disk. Caller should acquire the latch before
-    /// calling this function. @return the id of the allocated page
-    fn allocate_page(&self) -> PageId {
-        self.next_page_id.fetch_add(1, Ordering::SeqCst) as PageId
+    /// @brief Create a new, empty data file, to create pages in with
+    /// new_page_in.
+    /// @return the id of the file, or Io if it could not be created
+    pub fn create_file(&self) -> Result<FileId, BufferError> {
+        let (tx, rx) = DiskScheduler::create_promise();
+        self.disk_scheduler
+            .schedule(DiskRequest::CreateFile { callback: tx });
+        Self::wait_for_disk(rx)
     }
 
-    /// @brief Deallocate a page on disk. Caller should acquire the latch before
-    /// calling this function. @param page_id id of the page to deallocate
-    fn deallocate_page(&self, page_id: PageId) {
-        // This is a no-nop right now without a more complex data structure to
-        // track deallocated pages
+    /// @brief Delete a data file with all its pages, e.g. for DROP TABLE. The
+    /// pages of the file in the buffer pool are dropped without being written
+    /// back, and the space of the file is released on disk.
+    ///
+    /// @param file_id id of the file, cannot be the main file
+    /// @return PagePinned if a page of the file is pinned, in which case the
+    /// file is not deleted, or Io if it could not be deleted on disk
+    pub fn delete_file(&self, file_id: FileId) -> Result<(), BufferError> {
+        let page_ids: Vec<PageId> = self
+            .page_table
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .filter(|&page_id| file_id_of(page_id) == file_id)
+            .collect();
+        // check every page before dropping any, so nothing is lost if one
+        // of them turns out to be pinned
+        let mut latched = Vec::with_capacity(page_ids.len());
+        for page_id in page_ids {
+            if let Some((frame_id, latch)) = self.latch_page(page_id) {
+                if self.pages[frame_id].get_pin_count() > 0 {
+                    return Err(BufferError::PagePinned);
+                }
+                latched.push((page_id, frame_id, latch));
+            }
+        }
+        for (page_id, frame_id, _latch) in latched {
+            self.discard_frame(page_id, frame_id);
+        }
+        let (tx, rx) = DiskScheduler::create_promise();
+        self.disk_scheduler.schedule(DiskRequest::DeleteFile {
+            file_id,
+            callback: tx,
+        });
+        Self::wait_for_disk(rx)
     }
 
-    // TODO(student): You may add additional private members and helper functions
-}
-
-mod tests {
-    use rand::distributions::{Distribution, Uniform};
-    use tempdir::TempDir;
-
-    use super::*;
-    use crate::buffer::buffer_pool_manager::BufferPoolManager;
-    use crate::storage::disk::disk_manager::DiskManager;
+    /// @brief Drop the unpinned page page_id from its latched frame and put
+    /// the frame on the free list.
+    fn discard_frame(&self, page_id: PageId, frame_id: FrameId) {
+        self.page_table.lock().unwrap().remove(&page_id);
+        self.replacer.remove(frame_id);
+        self.free_list.lock().unwrap().push(frame_id);
+        self.pages[frame_id].reset();
+    }
 
-    const BUSTUB_PAGE_SIZE: usize = 4096; // Placeholder for actual page size
+    /// @brief Latch the frame holding page_id, waiting for a read or
+    /// write-back of the page that is in progress.
+    /// @return the frame and its latch, or none if the page is not in the
+    /// buffer pool
+    fn latch_page(&self, page_id: PageId) -> Option<(FrameId, MutexGuard<'_, ()>)> {
+        loop {
+            let frame_id = *self.page_table.lock().unwrap().get(&page_id)?;
+            let latch = self.frame_latches[frame_id].lock().unwrap();
+            // the page may have left the frame before we got the latch
+            if self.pages[frame_id].get_page_id() == Some(page_id) {
+                return Some((frame_id, latch));
+            }
+        }
+    }
 
-    #[test]
-    fn test_buffer_pool_manager_binary_data() {
-        let dir = TempDir::new("test").unwrap();
-        let db_name = dir.path().join("test.db");
-        let buffer_pool_size = 10;
-        let k = 5;
+    /// @brief Put page_id into the page table at frame_id, unless another
+    /// frame holds it already.
+    /// @return whether the page was put into the page table
+    fn map_page(&self, page_id: PageId, frame_id: FrameId) -> bool {
+        match self.page_table.lock().unwrap().entry(page_id) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(frame_id);
+                true
+            }
+        }
+    }
 
-        let mut rng = rand::thread_rng();
-        let uniform_dist = Uniform::from(std::u8::MIN..=std::u8::MAX);
+    /// @brief Take a frame off the free list.
+    /// @return the frame and its latch, or none if the free list is empty
+    fn take_free_frame(&self) -> Option<(FrameId, MutexGuard<'_, ()>)> {
+        let frame_id = self.free_list.lock().unwrap().pop()?;
+        Some((frame_id, self.frame_latches[frame_id].lock().unwrap()))
+    }
 
-        let disk_manager = DiskManager::new(db_name.to_str().unwrap());
-        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, k);
+    /// @brief Find a frame to hold a new page, from the free list first and
+    /// from the replacer otherwise. A page evicted by the replacer is written
+    /// back if it is dirty and removed from the page table, and its frame is
+    /// reset. The frame is returned latched, so that the caller can put it to
+    /// use before anyone else sees it.
+    ///
+    /// The replacer gives up a frame before its latch is taken, so the frame
+    /// may have changed in between: it may have been pinned, in which case
+    /// whoever pinned it tracks it in the replacer again, or deleted and put
+    /// on the free list. Either way it is skipped.
+    ///
+    /// If the write-back fails the victim is put back: it stays dirty and in
+    /// the page table, and is tracked by the replacer again, as if it had
+    /// just been accessed.
+    /// @return the frame and its latch, NoFreeFrame if all frames are pinned,
+    /// Io if the evicted page could not be written back
+    fn find_frame(&self) -> Result<(FrameId, MutexGuard<'_, ()>), BufferError> {
+        loop {
+            if let Some(frame) = self.take_free_frame() {
+                return Ok(frame);
+            }
+            let frame_id = {
+                let _eviction = self.eviction_latch.lock().unwrap();
+                self.replacer.evict().ok_or(BufferError::NoFreeFrame)?
+            };
+            assert!(
+                frame_id < self.pool_size,
+                "Replacer evicted frame {}, the pool has {} frames",
+                frame_id,
+                self.pool_size
+            );
+            let latch = self.frame_latches[frame_id].lock().unwrap();
+            let page = &self.pages[frame_id];
+            let page_id = match page.get_page_id() {
+                Some(page_id) if page.get_pin_count() == 0 => page_id,
+                _ => continue,
+            };
+            // it may have been pinned and unpinned in between, which tracks it
+            // in the replacer again
+            self.replacer.remove(frame_id);
+            if page.is_dirty() {
+                let rx = self.schedule_page_write(page_id, page);
+                if let Err(e) = Self::wait_for_disk(rx) {
+                    // the page is unpinned, so it goes back in as evictable
+                    self.replacer.bind_page(frame_id, page_id);
+                    self.replacer.record_access(frame_id, AccessType::Unknown);
+                    return Err(e);
+                }
+                StatsCounters::incr(&self.stats.dirty_writebacks);
+            }
+            StatsCounters::incr(&self.stats.pages_evicted);
+            self.page_table.lock().unwrap().remove(&page_id);
+            page.reset();
+            return Ok((frame_id, latch));
+        }
+    }
 
-        let page0 = bpm.new_page();
+    /// @brief Put page_id into a latched frame that is not in use and pin it.
+    /// The page table has to map page_id to the frame already.
+    /// @return the page of the frame
+    fn reserve_frame(&self, frame_id: FrameId, page_id: PageId, access_type: AccessType) -> Page {
+        let page = &self.pages[frame_id];
+        page.set_page_id(page_id);
+        page.pin();
+        self.replacer.bind_page(frame_id, page_id);
+        self.track_pinned(frame_id, access_type);
+        page.clone()
+    }
 
-        // Scenario: The buffer pool is empty. We should be able to create a new page.
-        assert!(page0.is_some());
+    /// @brief Record an access to a latched frame whose page has just been
+    /// pinned and mark it non-evictable. A frame that is not tracked yet
+    /// starts out evictable, so both steps happen under the eviction latch:
+    /// otherwise the replacer could give the frame up in between.
+    fn track_pinned(&self, frame_id: FrameId, access_type: AccessType) {
+        let _eviction = self.eviction_latch.lock().unwrap();
+        self.replacer.record_access(frame_id, access_type);
+        self.replacer.set_evictable(frame_id, false);
+    }
 
-        // Generate random binary data
-        let mut random_binary_data: Vec<u8> = (0..BUSTUB_PAGE_SIZE)
-            .map(|_| uniform_dist.sample(&mut rng))
-            .collect();
+    /// @brief Pin the page of a latched frame for writing it back.
+    fn pin_for_flush(&self, frame_id: FrameId) {
+        self.pages[frame_id].pin();
+        // A scan access leaves the history alone. It also tracks the frame
+        // again if the replacer has just given it up to an evictor that is
+        // still waiting for the latch.
+        self.track_pinned(frame_id, AccessType::Scan);
+    }
 
-        // Insert terminal characters both in the middle and at end
-        random_binary_data[BUSTUB_PAGE_SIZE / 2] = 0;
-        random_binary_data[BUSTUB_PAGE_SIZE - 1] = 0;
+    /// @brief Finish the read of a page reserved by reserve_frame, while
+    /// still holding the frame latch: copy the data that was read into the
+    /// frame. If the read failed the page is removed from the page table and
+    /// the frame goes back to the free list.
+    /// @param res the result of the read
+    fn finish_load(
+        &self,
+        page_id: PageId,
+        frame_id: FrameId,
+        res: Result<PageBuf, BufferError>,
+    ) -> Result<(), BufferError> {
+        match res {
+            Ok(data) => {
+                self.pages[frame_id].get_data_mut().copy_from_slice(&*data);
+                Ok(())
+            }
+            Err(e) => {
+                self.page_table.lock().unwrap().remove(&page_id);
+                self.replacer.set_evictable(frame_id, true);
+                self.replacer.remove(frame_id);
+                self.pages[frame_id].reset();
+                self.free_list.lock().unwrap().push(frame_id);
+                Err(e)
+            }
+        }
+    }
 
-        // Scenario: Once we have a page, we should be able to read and write content.
-        let page0 = page0.unwrap();
-        page0.get_data_mut()[..random_binary_data.len()].copy_from_slice(&random_binary_data);
-        assert_eq!(
-            random_binary_data,
-            page0.get_data()[..random_binary_data.len()]
-        );
+    /// @brief Schedule writing a page back. It is written from a snapshot, so
+    /// the page latch is only held for the copy and not during the write.
+    fn schedule_page_write(
+        &self,
+        page_id: PageId,
+        page: &Page,
+    ) -> oneshot::Receiver<io::Result<()>> {
+        let data = self.snapshot_for_write(page);
+        self.disk_scheduler.schedule_write(page_id, data)
+    }
 
-        // Scenario: We should be able to create new pages until we fill up the buffer
-        // pool.
-        for _i in 1..buffer_pool_size {
-            assert!(bpm.new_page().is_some());
-        }
+    /// @brief Take a snapshot of a page that is about to be written back, and
+    /// record its LSN, see max_written_lsn.
+    fn snapshot_for_write(&self, page: &Page) -> PageBuf {
+        let data = page.snapshot();
+        // TODO: flush the log up to the LSN first once there is a log manager
+        self.max_written_lsn
+            .fetch_max(lsn_of(&data), Ordering::Relaxed);
+        data
+    }
 
-        // Scenario: Once the buffer pool is full, we should not be able to create any
-        // new pages.
-        for _i in buffer_pool_size..buffer_pool_size * 2 {
-            assert!(bpm.new_page().is_none());
+    /// @brief Read the pages that are not resident yet into the buffer pool
+    /// and leave them unpinned. Frames are reserved for all pages and every
+    /// read is scheduled before waiting on any of them. Pages that cannot be
+    /// read are skipped.
+    /// @param access_type the access recorded for the loaded pages
+    /// @param free_frames_only whether to stop once the free list is empty,
+    /// instead of evicting pages to make room
+    /// @return the number of pages read into the buffer pool
+    fn load_pages(
+        &self,
+        page_ids: &[PageId],
+        access_type: AccessType,
+        free_frames_only: bool,
+    ) -> usize {
+        let mut reads = Vec::new();
+        for &page_id in page_ids {
+            if self.contains_page(page_id) {
+                continue;
+            }
+            let frame = if free_frames_only {
+                self.take_free_frame()
+            } else {
+                self.find_frame().ok()
+            };
+            let Some((frame_id, latch)) = frame else {
+                break;
+            };
+            if !self.map_page(page_id, frame_id) {
+                self.free_list.lock().unwrap().push(frame_id);
+                continue;
+            }
+            self.reserve_frame(frame_id, page_id, access_type);
+            let rx = self.disk_scheduler.schedule_read(page_id);
+            reads.push((page_id, frame_id, latch, rx));
         }
 
-        // Scenario: After unpinning pages {0, 1, 2, 3, 4}, we should be able to create
-        // 5 new pages
-        for i in 0..5 {
-            assert!(bpm.unpin_page(i, true));
-            bpm.flush_page(i);
-        }
-        for _i in 0..5 {
-            let page = bpm.new_page();
-            assert!(page.is_some());
-            // Unpin the page here to allow future fetching
-            bpm.unpin_page(page.unwrap().get_page_id().unwrap(), false);
+        let mut loaded = 0;
+        for (page_id, frame_id, latch, rx) in reads {
+            let res = self.finish_load(page_id, frame_id, Self::wait_for_disk(rx));
+            drop(latch);
+            if let Err(e) = res {
+                warn!("failed to load page {}: {}", page_id, e);
+                continue;
+            }
+            // the reservation pinned the page, loading leaves it unpinned
+            self.unpin_page(page_id, false).unwrap();
+            loaded += 1;
         }
+        loaded
+    }
 
-        // Scenario: We should be able to fetch the data we wrote a while ago.
-        let page0 = bpm.fetch_page(0);
-        assert!(page0.is_some());
-        let page0 = page0.unwrap();
-        assert_eq!(*page0.get_data(), random_binary_data.as_slice());
-        assert!(bpm.unpin_page(0, true));
+    /// @brief Allocate a page on disk in the data file file_id. Caller should
+    /// acquire the latch before calling this function.
+    /// @return the id of the allocated page
+    fn allocate_page(&self, file_id: FileId) -> Result<PageId, BufferError> {
+        let (tx, rx) = DiskScheduler::create_promise();
+        self.disk_scheduler.schedule(DiskRequest::Allocate {
+            file_id,
+            num_instances: self.num_instances,
+            instance_index: self.instance_index,
+            callback: tx,
+        });
+        let page_id = Self::wait_for_disk(rx)?;
+        self.validate_page_id(page_id);
+        Ok(page_id)
+    }
 
-        // Shutdown the disk manager and remove the temporary file we created.
-        drop(bpm);
+    /// @brief Check that page_id belongs to this instance.
+    fn validate_page_id(&self, page_id: PageId) {
+        assert_eq!(
+            page_id as usize % self.num_instances,
+            self.instance_index,
+            "page {} does not belong to this instance",
+            page_id
+        );
+    }
+
+    /// @brief Deallocate a page on disk. Caller should acquire the latch before
+    /// calling this function. @param page_id id of the page to deallocate
+    fn deallocate_page(&self, page_id: PageId) -> Result<(), BufferError> {
+        let (tx, rx) = DiskScheduler::create_promise();
+        self.disk_scheduler.schedule(DiskRequest::Deallocate {
+            page_id,
+            callback: tx,
+        });
+        Self::wait_for_disk(rx)
+    }
+
+    /// @brief Wait for a request scheduled on the disk scheduler to complete.
+    /// A callback that is dropped without an answer means the scheduler's
+    /// worker is gone.
+    fn wait_for_disk<T>(rx: oneshot::Receiver<io::Result<T>>) -> Result<T, BufferError> {
+        match rx.blocking_recv() {
+            Ok(res) => Ok(res?),
+            Err(_) => Err(BufferError::Io(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "disk scheduler is gone",
+            ))),
+        }
+    }
+
+    // TODO(student): You may add additional private members and helper functions
+}
+
+impl Drop for BufferPoolManager {
+    /// Writes back every page that is still dirty, so dropping the pool does
+    /// not lose modifications. This is best effort: once a write fails the
+    /// remaining pages are skipped.
+    fn drop(&mut self) {
+        self.stop_background_flush();
+        for page in self.pages.iter() {
+            let Some(page_id) = page.get_page_id().filter(|_| page.is_dirty()) else {
+                continue;
+            };
+            let rx = self.schedule_page_write(page_id, page);
+            if let Err(e) = Self::wait_for_disk(rx) {
+                warn!("dirty pages are not written back: {}", e);
+                return;
+            }
+            page.set_dirty(false);
+        }
+    }
+}
+
+mod tests {
+    use rand::distributions::{Distribution, Uniform};
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::lru_k_replacer::LRUKReplacer;
+    use crate::storage::disk::disk_manager::{DiskManager, DiskManagerOptions};
+    use crate::storage::disk::disk_manager_memory::DiskManagerMemory;
+    use crate::storage::page::page::SIZE_PAGE_HEADER;
+
+    const BUSTUB_PAGE_SIZE: usize = 4096; // Placeholder for actual page size
+
+    /// Runs test once on a file-backed and once on an in-memory disk manager.
+    fn for_each_disk_manager(test: impl Fn(Box<dyn DiskManagerTrait>)) {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        test(Box::new(
+            DiskManager::new(db_name.to_str().unwrap()).unwrap(),
+        ));
+        test(Box::new(DiskManagerMemory::new()));
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_binary_data() {
+        for_each_disk_manager(|disk_manager| {
+            let buffer_pool_size = 10;
+            let k = 5;
+
+            let mut rng = rand::thread_rng();
+            let uniform_dist = Uniform::from(std::u8::MIN..=std::u8::MAX);
+
+            let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+
+            let page0 = bpm.new_page();
+
+            // Scenario: The buffer pool is empty. We should be able to create a new page.
+            assert!(page0.is_ok());
+
+            // Generate random binary data
+            let mut random_binary_data: Vec<u8> = (0..BUSTUB_PAGE_SIZE)
+                .map(|_| uniform_dist.sample(&mut rng))
+                .collect();
+
+            // Insert terminal characters both in the middle and at end
+            random_binary_data[BUSTUB_PAGE_SIZE / 2] = 0;
+            random_binary_data[BUSTUB_PAGE_SIZE - 1] = 0;
+
+            // Scenario: Once we have a page, we should be able to read and write content.
+            let page0 = page0.unwrap();
+            page0.get_data_mut()[..random_binary_data.len()].copy_from_slice(&random_binary_data);
+            assert_eq!(
+                random_binary_data,
+                page0.get_data()[..random_binary_data.len()]
+            );
+
+            // Scenario: We should be able to create new pages until we fill up the buffer
+            // pool.
+            for _i in 1..buffer_pool_size {
+                assert!(bpm.new_page().is_ok());
+            }
+
+            // Scenario: Once the buffer pool is full, we should not be able to create any
+            // new pages.
+            for _i in buffer_pool_size..buffer_pool_size * 2 {
+                assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+            }
+
+            // Scenario: After unpinning pages {0, 1, 2, 3, 4}, we should be able to create
+            // 5 new pages
+            for i in 0..5 {
+                assert!(bpm.unpin_page(i, true).is_ok());
+                bpm.flush_page(i).unwrap();
+            }
+            for _i in 0..5 {
+                let page = bpm.new_page();
+                assert!(page.is_ok());
+                // Unpin the page here to allow future fetching
+                bpm.unpin_page(page.unwrap().get_page_id().unwrap(), false)
+                    .unwrap();
+            }
+
+            // Scenario: We should be able to fetch the data we wrote a while ago.
+            let page0 = bpm.fetch_page(0, AccessType::Unknown);
+            assert!(page0.is_ok());
+            let page0 = page0.unwrap();
+            assert_eq!(*page0.get_data(), random_binary_data.as_slice());
+            assert!(bpm.unpin_page(0, true).is_ok());
+
+            // Shutdown the disk manager and remove the temporary file we created.
+            drop(bpm);
+        });
     }
 
     #[test]
     fn test_buffer_pool_manager_sample() {
-        let dir = TempDir::new("test.db").unwrap();
+        for_each_disk_manager(|disk_manager| {
+            let buffer_pool_size = 10;
+            let k = 5;
+
+            let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+
+            let page0 = bpm.new_page();
+
+            // Scenario: The buffer pool is empty. We should be able to create a new page.
+            assert!(page0.is_ok());
+            assert_eq!(0, page0.as_ref().unwrap().get_page_id().unwrap());
+
+            // Scenario: Once we have a page, we should be able to read and write content.
+            let page0 = page0.unwrap();
+            let data = "Hello".as_bytes();
+            page0.get_data_mut()[..data.len()].copy_from_slice(data);
+            assert_eq!(data, &(page0.get_data())[..data.len()]);
+
+            // Scenario: We should be able to create new pages until we fill up the buffer
+            // pool.
+            for i in 1..buffer_pool_size {
+                assert!(bpm.new_page().is_ok());
+            }
+
+            // Scenario: Once the buffer pool is full, we should not be able to create any
+            // new pages.
+            for _i in buffer_pool_size..buffer_pool_size * 2 {
+                assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+            }
+
+            // Scenario: After unpinning pages {0, 1, 2, 3, 4} and pinning another 4 new
+            // pages, there would still be one buffer page left for reading page 0.
+            for i in 0..5 {
+                assert!(bpm.unpin_page(i as PageId, true).is_ok());
+            }
+            for _i in 0..4 {
+                assert!(bpm.new_page().is_ok());
+            }
+
+            // Scenario: We should be able to fetch the data we wrote a while ago.
+            let page0 = bpm.fetch_page(0, AccessType::Unknown);
+            assert!(page0.is_ok());
+            let page0 = page0.unwrap();
+            assert_eq!(data, &(page0.get_data())[..data.len()]);
+
+            // Scenario: If we unpin page 0 and then make a new page, all the buffer pages
+            // should now be pinned. Fetching page 0 again should fail.
+            assert!(bpm.unpin_page(0, true).is_ok());
+            assert!(bpm.new_page().is_ok());
+            assert!(matches!(
+                bpm.fetch_page(0, AccessType::Unknown),
+                Err(BufferError::NoFreeFrame)
+            ));
+
+            // Shutdown the disk manager and remove the temporary file we created.
+            // Replace this with the actual method to shut down the disk manager.
+            drop(bpm);
+        });
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_new_page_guarded() {
+        let dir = TempDir::new("test").unwrap();
         let db_name = dir.path().join("test.db");
         let buffer_pool_size = 10;
         let k = 5;
 
-        let disk_manager = DiskManager::new(db_name.to_str().unwrap());
-        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, k);
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            buffer_pool_size,
+            disk_manager,
+            ReplacerType::LruK(k),
+        ));
+
+        let mut guards = Vec::new();
+        for i in 0..buffer_pool_size {
+            let mut page_id = None;
+            let mut guard = bpm.clone().new_page_guarded(&mut page_id).unwrap();
+            assert_eq!(Some(i as PageId), page_id);
+            assert_eq!(i as PageId, guard.page_id());
+            guard.body_mut()[0] = i as u8 + 1;
+            guards.push(guard);
+        }
+
+        // Scenario: every frame is pinned by a guard.
+        let mut page_id = Some(0);
+        assert!(matches!(
+            bpm.clone().new_page_guarded(&mut page_id),
+            Err(BufferError::NoFreeFrame)
+        ));
+        assert_eq!(None, page_id);
 
-        let page0 = bpm.new_page();
+        // Scenario: once the guards are gone the frames are evictable again, and
+        // the pages were unpinned as dirty so their data survives eviction.
+        drop(guards);
+        for page in bpm.get_pages() {
+            assert_eq!(0, page.get_pin_count());
+            assert!(page.is_dirty());
+        }
+        for _ in 0..buffer_pool_size {
+            let mut page_id = None;
+            assert!(bpm.clone().new_page_guarded(&mut page_id).is_ok());
+        }
+        for i in 0..buffer_pool_size {
+            let guard = bpm.clone().fetch_page_basic(i as PageId).unwrap();
+            assert_eq!(i as u8 + 1, guard.body()[0]);
+        }
+    }
 
-        // Scenario: The buffer pool is empty. We should be able to create a new page.
-        assert!(page0.is_some());
-        assert_eq!(0, page0.as_ref().unwrap().get_page_id().unwrap());
+    #[test]
+    fn test_buffer_pool_manager_stats() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 3;
+        let k = 2;
 
-        // Scenario: Once we have a page, we should be able to read and write content.
-        let page0 = page0.unwrap();
-        let data = "Hello".as_bytes();
-        page0.get_data_mut()[..data.len()].copy_from_slice(data);
-        assert_eq!(data, &(page0.get_data())[..data.len()]);
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        assert_eq!(BufferPoolStats::default(), bpm.get_stats());
 
-        // Scenario: We should be able to create new pages until we fill up the buffer
-        // pool.
-        for i in 1..buffer_pool_size {
-            assert!(bpm.new_page().is_some());
+        // Scenario: pages 0 and 1 are unpinned, 0 as dirty, 2 stays pinned.
+        for _ in 0..buffer_pool_size {
+            bpm.new_page().unwrap();
         }
+        bpm.fetch_page(0, AccessType::Unknown).unwrap().body_mut()[0] = 1;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.fetch_page(1, AccessType::Unknown).is_ok());
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert_eq!(
+            BufferPoolStats {
+                cache_hits: 2,
+                pages_created: 3,
+                ..Default::default()
+            },
+            bpm.get_stats()
+        );
 
-        // Scenario: Once the buffer pool is full, we should not be able to create any
-        // new pages.
-        for _i in buffer_pool_size..buffer_pool_size * 2 {
-            assert!(bpm.new_page().is_none());
+        // Scenario: two new pages evict pages 0 and 1, only page 0 is written
+        // back.
+        assert_eq!(Some(3), bpm.new_page().unwrap().get_page_id());
+        assert_eq!(Some(4), bpm.new_page().unwrap().get_page_id());
+        assert_eq!(
+            BufferPoolStats {
+                cache_hits: 2,
+                pages_evicted: 2,
+                dirty_writebacks: 1,
+                pages_created: 5,
+                ..Default::default()
+            },
+            bpm.get_stats()
+        );
+
+        // Scenario: a miss is counted even if no frame can be found for it.
+        assert!(matches!(
+            bpm.fetch_page(0, AccessType::Unknown),
+            Err(BufferError::NoFreeFrame)
+        ));
+        assert!(bpm.unpin_page(3, false).is_ok());
+        assert_eq!(1, bpm.fetch_page(0, AccessType::Unknown).unwrap().body()[0]);
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert_eq!(DeleteOutcome::Clean, bpm.delete_page(0).unwrap());
+        // page 3 is not in the pool anymore, nothing gets deleted
+        assert_eq!(DeleteOutcome::NotResident, bpm.delete_page(3).unwrap());
+        assert_eq!(
+            BufferPoolStats {
+                cache_hits: 2,
+                cache_misses: 2,
+                pages_evicted: 3,
+                dirty_writebacks: 1,
+                pages_created: 5,
+                pages_deleted: 1,
+            },
+            bpm.get_stats()
+        );
+
+        bpm.reset_stats();
+        assert_eq!(BufferPoolStats::default(), bpm.get_stats());
+        assert!(bpm.fetch_page(2, AccessType::Unknown).is_ok());
+        assert_eq!(1, bpm.get_stats().cache_hits);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_inspect_page() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 3;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+
+        // Scenario: a freshly created page is pinned and clean.
+        for _ in 0..buffer_pool_size {
+            bpm.new_page().unwrap();
         }
+        assert_eq!(
+            Some(PageInfo {
+                frame_id: 0,
+                pin_count: 1,
+                is_dirty: false,
+                is_evictable: false,
+            }),
+            bpm.inspect_page(0)
+        );
 
-        // Scenario: After unpinning pages {0, 1, 2, 3, 4} and pinning another 4 new
-        // pages, there would still be one buffer page left for reading page 0.
-        for i in 0..5 {
-            assert_eq!(true, bpm.unpin_page(i as PageId, true));
+        // Scenario: page 0 is unpinned as dirty, page 1 is fetched again and
+        // unpinned, page 2 is unpinned. Page 1 now has two recorded accesses,
+        // so LRU-K evicts 0 and then 2 before it.
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.fetch_page(1, AccessType::Unknown).is_ok());
+        assert_eq!(2, bpm.inspect_page(1).unwrap().pin_count);
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert!(bpm.unpin_page(2, false).is_ok());
+        assert_eq!(
+            Some(PageInfo {
+                frame_id: 0,
+                pin_count: 0,
+                is_dirty: true,
+                is_evictable: true,
+            }),
+            bpm.inspect_page(0)
+        );
+        assert_eq!(None, bpm.inspect_page(3));
+        assert!(!bpm.contains_page(3));
+
+        // Scenario: inspecting pages over and over records no accesses, so it
+        // changes neither the statistics nor the eviction order.
+        let stats = bpm.get_stats();
+        for _ in 0..10 {
+            for page_id in 0..buffer_pool_size as PageId {
+                assert!(bpm.contains_page(page_id));
+                assert!(bpm.inspect_page(page_id).unwrap().is_evictable);
+            }
         }
-        for _i in 0..4 {
-            assert!(bpm.new_page().is_some());
+        assert_eq!(stats, bpm.get_stats());
+
+        assert_eq!(Some(3), bpm.new_page().unwrap().get_page_id());
+        assert!(!bpm.contains_page(0));
+        assert_eq!(None, bpm.inspect_page(0));
+        assert_eq!(0, bpm.inspect_page(3).unwrap().frame_id);
+        assert_eq!(Some(4), bpm.new_page().unwrap().get_page_id());
+        assert!(!bpm.contains_page(2));
+        assert!(bpm.contains_page(1));
+        assert_eq!(2, bpm.inspect_page(4).unwrap().frame_id);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_background_flush() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 10;
+        let k = 5;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            buffer_pool_size,
+            disk_manager,
+            ReplacerType::LruK(k),
+        ));
+        for i in 0..buffer_pool_size {
+            let page = bpm.new_page().unwrap();
+            page.body_mut()[0] = i as u8 + 1;
+            // the last page stays pinned, it must not be flushed
+            if i + 1 < buffer_pool_size {
+                assert!(bpm.unpin_page(i as PageId, true).is_ok());
+            } else {
+                page.set_dirty(true);
+            }
+        }
+
+        bpm.start_background_flush(Duration::from_millis(10));
+        let pages = bpm.get_pages();
+        for _ in 0..500 {
+            if pages.iter().filter(|page| page.is_dirty()).count() == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let pinned = &pages[buffer_pool_size - 1];
+        assert!(pinned.is_dirty());
+        assert_eq!(1, pinned.get_pin_count());
+        for page in pages[..buffer_pool_size - 1].iter() {
+            assert!(!page.is_dirty());
+            assert_eq!(0, page.get_pin_count());
+        }
+
+        // The disk manager of the pool holds the file lock, so read the file
+        // directly to check what reached the disk. Page i is stored after the
+        // header page.
+        let data = std::fs::read(&db_name).unwrap();
+        for i in 0..buffer_pool_size - 1 {
+            assert_eq!(
+                i as u8 + 1,
+                data[(i + 1) * BUSTUB_PAGE_SIZE + SIZE_PAGE_HEADER]
+            );
+        }
+        // the file grows in extents, the pinned page's slot is still zeroed
+        assert_eq!(
+            0,
+            data[buffer_pool_size * BUSTUB_PAGE_SIZE + SIZE_PAGE_HEADER]
+        );
+
+        // Scenario: a page dirtied again is picked up by the next pass.
+        let page = bpm.fetch_page(0, AccessType::Unknown).unwrap();
+        page.body_mut()[0] = 42;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        // the dirty flag is cleared before the write is done, wait for both
+        let written =
+            || std::fs::read(&db_name).unwrap()[BUSTUB_PAGE_SIZE + SIZE_PAGE_HEADER] == 42;
+        for _ in 0..500 {
+            if !page.is_dirty() && written() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!page.is_dirty());
+        assert!(written());
+
+        // Scenario: dropping the pool stops the flusher.
+        bpm.stop_background_flush();
+        bpm.start_background_flush(Duration::from_millis(1));
+        drop(bpm);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_prefetch_pages() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let num_pages = 100;
+        let k = 5;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let mut data = [0; BUSTUB_PAGE_SIZE];
+        for i in 0..num_pages {
+            data[SIZE_PAGE_HEADER] = i as u8 + 1;
+            disk_manager.write_page(i as PageId, &data).unwrap();
+        }
+
+        let bpm = BufferPoolManager::new(num_pages, disk_manager, ReplacerType::LruK(k));
+        let page_ids: Vec<PageId> = (0..num_pages as PageId).collect();
+        assert_eq!(num_pages, bpm.prefetch_pages(&page_ids));
+        for page in bpm.get_pages() {
+            assert_eq!(0, page.get_pin_count());
+        }
+
+        // Scenario: a sequential scan after the prefetch never goes to disk.
+        bpm.reset_stats();
+        for &page_id in page_ids.iter() {
+            let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+            assert_eq!(page_id as u8 + 1, page.body()[0]);
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        let stats = bpm.get_stats();
+        assert_eq!(num_pages as u64, stats.cache_hits);
+        assert_eq!(0, stats.cache_misses);
+
+        // Scenario: resident pages are not prefetched again.
+        assert_eq!(0, bpm.prefetch_pages(&page_ids[..10]));
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_warm_up() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let num_pages = 20;
+        let k = 2;
+
+        // Scenario: write 20 pages through a pool of 10 frames, so the last
+        // 10 are resident at shutdown.
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(10, disk_manager, ReplacerType::LruK(k));
+        for i in 0..num_pages {
+            let page = bpm.new_page().unwrap();
+            page.body_mut()[0] = i as u8 + 1;
+            assert!(bpm.unpin_page(i as PageId, true).is_ok());
+        }
+        let resident = bpm.resident_page_ids();
+        assert_eq!((10..num_pages as PageId).collect::<Vec<_>>(), resident);
+        drop(bpm);
+
+        // Scenario: a new pool warmed up with them serves them without going
+        // to disk, and leaves them unpinned.
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(15, disk_manager, ReplacerType::LruK(k));
+        assert_eq!(10, bpm.warm_up(&resident));
+        assert_eq!(resident, bpm.resident_page_ids());
+        for &page_id in resident.iter() {
+            assert_eq!(
+                Some(0),
+                bpm.inspect_page(page_id).map(|info| info.pin_count)
+            );
+        }
+        for &page_id in resident.iter() {
+            let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+            assert_eq!(page_id as u8 + 1, page.body()[0]);
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        let stats = bpm.get_stats();
+        assert_eq!(10, stats.cache_hits);
+        assert_eq!(0, stats.cache_misses);
+
+        // Scenario: warming up only fills free frames, it never evicts.
+        let page_ids: Vec<PageId> = (0..10).collect();
+        assert_eq!(5, bpm.warm_up(&page_ids));
+        assert_eq!(15, bpm.resident_page_ids().len());
+        assert_eq!(0, bpm.get_stats().pages_evicted);
+        assert_eq!(0, bpm.warm_up(&page_ids));
+        assert!(resident.iter().all(|&page_id| bpm.contains_page(page_id)));
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_prefetch_pages_no_frames() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 10;
+        let k = 5;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let data = [1; BUSTUB_PAGE_SIZE];
+        for i in 0..20 {
+            disk_manager.write_page(i, &data).unwrap();
+        }
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+
+        // Scenario: with two frames pinned only eight pages fit, duplicates are
+        // read once.
+        bpm.fetch_page(18, AccessType::Unknown).unwrap();
+        bpm.fetch_page(19, AccessType::Unknown).unwrap();
+        let page_ids: Vec<PageId> = [0, 0].into_iter().chain(0..18).collect();
+        assert_eq!(8, bpm.prefetch_pages(&page_ids));
+        bpm.reset_stats();
+        for page_id in 0..8 {
+            assert!(bpm.fetch_page(page_id, AccessType::Unknown).is_ok());
+        }
+        assert_eq!(8, bpm.get_stats().cache_hits);
+        assert!(matches!(
+            bpm.fetch_page(8, AccessType::Unknown),
+            Err(BufferError::NoFreeFrame)
+        ));
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_reuse_deleted_pages() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 10;
+        let k = 5;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        let create_pages = |n: usize| -> Vec<PageId> {
+            let mut page_ids = Vec::new();
+            for _ in 0..n {
+                let page_id = bpm.new_page().unwrap().get_page_id().unwrap();
+                assert!(bpm.unpin_page(page_id, true).is_ok());
+                page_ids.push(page_id);
+            }
+            // get every page written out
+            for page in bpm.get_pages() {
+                if let Some(page_id) = page.get_page_id() {
+                    assert!(bpm.flush_page(page_id).is_ok());
+                }
+            }
+            page_ids
+        };
+        let file_size = || std::fs::metadata(&db_name).unwrap().len();
+
+        let page_ids = create_pages(100);
+        assert_eq!((0..100).collect::<Vec<PageId>>(), page_ids);
+        let size = file_size();
+
+        // Scenario: deleted pages, resident or not, are handed out again before
+        // the file is extended.
+        let deleted: Vec<PageId> = page_ids.into_iter().filter(|p| p % 2 == 0).collect();
+        for &page_id in deleted.iter() {
+            assert!(bpm.delete_page(page_id).is_ok());
+        }
+        let mut page_ids = create_pages(50);
+        page_ids.sort();
+        assert_eq!(deleted, page_ids);
+        assert_eq!(size, file_size());
+
+        assert_eq!(100, bpm.new_page().unwrap().get_page_id().unwrap());
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_delete_dirty_page() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 4;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        let write_page = |page_id: PageId, byte: u8| {
+            let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+            page.body_mut()[0] = byte;
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        };
+        let read_page = |page_id: PageId| -> u8 {
+            let byte = bpm.fetch_page(page_id, AccessType::Unknown).unwrap().body()[0];
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+            byte
+        };
+        for page_id in 0..2 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+            write_page(page_id, 1);
+            assert!(bpm.flush_page(page_id).is_ok());
+            write_page(page_id, 2);
         }
 
-        // Scenario: We should be able to fetch the data we wrote a while ago.
-        let page0 = bpm.fetch_page(0);
-        assert!(page0.is_some());
-        let page0 = page0.unwrap();
-        assert_eq!(data, &(page0.get_data())[..data.len()]);
+        // Scenario: deleting a dirty page throws its changes away, the page
+        // on disk still holds what was flushed last.
+        assert_eq!(DeleteOutcome::DiscardedDirty, bpm.delete_page(0).unwrap());
+        assert!(!bpm.contains_page(0));
+        assert_eq!(1, read_page(0));
+
+        // Scenario: delete_page_flush writes the changes back first.
+        bpm.fetch_page(1, AccessType::Unknown).unwrap();
+        assert!(matches!(
+            bpm.delete_page_flush(1),
+            Err(BufferError::PagePinned)
+        ));
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert_eq!(DeleteOutcome::Flushed, bpm.delete_page_flush(1).unwrap());
+        assert!(!bpm.contains_page(1));
+        assert_eq!(2, read_page(1));
+
+        // Scenario: both ids are handed out again, as empty pages.
+        assert_eq!(DeleteOutcome::Clean, bpm.delete_page(0).unwrap());
+        assert_eq!(DeleteOutcome::Clean, bpm.delete_page_flush(1).unwrap());
+        assert_eq!(
+            DeleteOutcome::NotResident,
+            bpm.delete_page_flush(1).unwrap()
+        );
+        let mut page_ids = Vec::new();
+        for _ in 0..2 {
+            let page = bpm.new_page().unwrap();
+            assert_eq!(0, page.body()[0]);
+            page_ids.push(page.get_page_id().unwrap());
+        }
+        page_ids.sort();
+        assert_eq!(vec![0, 1], page_ids);
+        assert_eq!(4, bpm.get_stats().pages_deleted);
+        assert_page_table_consistent(&bpm);
+    }
 
-        // Scenario: If we unpin page 0 and then make a new page, all the buffer pages
-        // should now be pinned. Fetching page 0 again should fail.
-        assert_eq!(true, bpm.unpin_page(0, true));
-        assert!(bpm.new_page().is_some());
-        assert!(bpm.fetch_page(0).is_none());
+    #[test]
+    fn test_buffer_pool_manager_debug_dump() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 4;
+        let k = 2;
 
-        // Shutdown the disk manager and remove the temporary file we created.
-        // Replace this with the actual method to shut down the disk manager.
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for _ in 0..3 {
+            bpm.new_page().unwrap();
+        }
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.unpin_page(1, false).is_ok());
+        bpm.fetch_page(0, AccessType::Unknown).unwrap();
+        assert!(bpm.unpin_page(0, false).is_ok());
+
+        // Scenario: page 1 has +inf backward k-distance and goes first, page
+        // 2 is pinned and comes last. The free frame is not listed.
+        assert_eq!(
+            "frame page pins dirty evictable scan  distance history\n    \
+             1    1    0 false true      false      inf [1]\n    \
+             0    0    0 true  true      false        3 [0, 3]\n    \
+             2    2    1 false false     false      inf [2]\n",
+            bpm.debug_dump()
+        );
+
+        // Scenario: the dump shows the eviction order.
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        assert!(!bpm.contains_page(1));
+        assert!(bpm.contains_page(0));
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_flush_clears_dirty() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 3;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let mut bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..buffer_pool_size {
+            let page = bpm.new_page().unwrap();
+            page.body_mut()[0] = i as u8 + 1;
+        }
+        // pages 0 and 1 are unpinned as dirty, page 2 stays pinned and is
+        // flushed as well
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.unpin_page(1, true).is_ok());
+        bpm.get_pages()[2].set_dirty(true);
+        bpm.flush_all_pages().unwrap();
+        assert_eq!(3, bpm.get_disk_manager().get_num_writes());
+        for page in bpm.get_pages() {
+            assert!(!page.is_dirty());
+        }
+
+        // Scenario: flush_page clears the flag as well.
+        assert!(bpm.unpin_page(2, false).is_ok());
+        bpm.fetch_page(0, AccessType::Unknown).unwrap().body_mut()[1] = 1;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.flush_page(0).is_ok());
+        assert!(!bpm.get_pages()[0].is_dirty());
+        assert_eq!(4, bpm.get_disk_manager().get_num_writes());
+
+        // Scenario: evicting the flushed pages writes nothing more.
+        for _ in 0..buffer_pool_size {
+            bpm.new_page().unwrap();
+        }
+        assert_eq!(3, bpm.get_stats().pages_evicted);
+        assert_eq!(0, bpm.get_stats().dirty_writebacks);
+        assert_eq!(4, bpm.get_disk_manager().get_num_writes());
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_sync() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let db_name = db_name.to_str().unwrap();
+        let buffer_pool_size = 3;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name).unwrap());
+        let mut bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..buffer_pool_size {
+            bpm.new_page().unwrap().body_mut()[0] = i as u8 + 1;
+            assert!(bpm.unpin_page(i as PageId, true).is_ok());
+        }
+
+        // Scenario: with the default policy flushing does not sync, asking for
+        // it afterwards does, once.
+        bpm.flush_all_pages().unwrap();
+        assert_eq!(0, bpm.get_disk_manager().get_num_flushes());
+        bpm.sync().unwrap();
+        assert_eq!(1, bpm.get_disk_manager().get_num_flushes());
         drop(bpm);
+
+        let disk_manager = Box::new(DiskManager::new(db_name).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..buffer_pool_size {
+            let page = bpm.fetch_page(i as PageId, AccessType::Unknown).unwrap();
+            assert_eq!(i as u8 + 1, page.body()[0]);
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_delete_file() {
+        for_each_disk_manager(|disk_manager| {
+            let bpm = BufferPoolManager::new(4, disk_manager, ReplacerType::LruK(2));
+            let main_page = bpm.new_page().unwrap();
+            main_page.body_mut()[0] = 1;
+            let main_id = main_page.get_page_id().unwrap();
+            assert!(bpm.unpin_page(main_id, true).is_ok());
+
+            // Scenario: pages of two files live side by side in the pool.
+            let table = bpm.create_file().unwrap();
+            let table_page = bpm.new_page_in(table).unwrap();
+            table_page.body_mut()[0] = 2;
+            let table_id = table_page.get_page_id().unwrap();
+            assert_eq!(table, file_id_of(table_id));
+            assert_ne!(main_id, table_id);
+            let dirty_page = bpm.new_page_in(table).unwrap();
+            dirty_page.body_mut()[0] = 3;
+            let dirty_id = dirty_page.get_page_id().unwrap();
+            assert!(bpm.unpin_page(dirty_id, true).is_ok());
+
+            // Scenario: a file with a pinned page is not deleted, and its
+            // dirty unpinned pages are kept.
+            assert!(matches!(
+                bpm.delete_file(table),
+                Err(BufferError::PagePinned)
+            ));
+            assert!(bpm.contains_page(dirty_id));
+            bpm.flush_page(dirty_id).unwrap();
+            let page = bpm.fetch_page(dirty_id, AccessType::Unknown).unwrap();
+            assert_eq!(3, page.body()[0]);
+            assert!(bpm.unpin_page(dirty_id, false).is_ok());
+            assert!(bpm.unpin_page(table_id, true).is_ok());
+
+            // Scenario: deleting the file drops its pages from the pool, the
+            // other file is untouched.
+            bpm.delete_file(table).unwrap();
+            assert!(!bpm.contains_page(table_id));
+            assert!(!bpm.contains_page(dirty_id));
+            assert!(matches!(
+                bpm.fetch_page(table_id, AccessType::Unknown),
+                Err(BufferError::Io(_))
+            ));
+            bpm.flush_page(main_id).unwrap();
+            let page = bpm.fetch_page(main_id, AccessType::Unknown).unwrap();
+            assert_eq!(1, page.body()[0]);
+        });
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_disk_io_under_read_guard() {
+        let disk_manager = Box::new(DiskManagerMemory::new());
+        let bpm = Arc::new(BufferPoolManager::new(
+            3,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+        bpm.new_page().unwrap().body_mut()[0] = 1;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        let guard = bpm.clone().fetch_page_read(0).unwrap();
+
+        // Scenario: disk requests for the page carry their own buffers, so a
+        // write and a read of the same page id complete while its read latch
+        // is held.
+        let rx = bpm
+            .disk_scheduler
+            .schedule_write(0, Box::new([2; BUSTUB_PAGE_SIZE]));
+        rx.blocking_recv().unwrap().unwrap();
+        let rx = bpm.disk_scheduler.schedule_read(0);
+        assert_eq!(2, rx.blocking_recv().unwrap().unwrap()[SIZE_PAGE_HEADER]);
+        assert_eq!(1, guard.body()[0]);
+
+        // Scenario: flushing the page only needs the latch in shared mode to
+        // copy the data.
+        bpm.flush_page(0).unwrap();
+        let rx = bpm.disk_scheduler.schedule_read(0);
+        assert_eq!(1, rx.blocking_recv().unwrap().unwrap()[SIZE_PAGE_HEADER]);
+        drop(guard);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_scan_resistance() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 10;
+        let k = 2;
+        let num_hot_pages = 5;
+        let num_scan_pages = 1000;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for _ in 0..num_hot_pages + num_scan_pages {
+            let page_id = bpm.new_page().unwrap().get_page_id().unwrap();
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        }
+        let access = |page_id: PageId, access_type: AccessType| {
+            assert!(bpm.fetch_page(page_id, access_type).is_ok());
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        };
+        let lookup_hot_pages = || {
+            for page_id in 0..num_hot_pages as PageId {
+                access(page_id, AccessType::Lookup);
+            }
+        };
+        lookup_hot_pages();
+        lookup_hot_pages();
+
+        // Scenario: a scan touching every page twice, with the hot pages looked
+        // up in between, never evicts the hot pages.
+        for (i, page_id) in (num_hot_pages..num_hot_pages + num_scan_pages).enumerate() {
+            access(page_id as PageId, AccessType::Scan);
+            access(page_id as PageId, AccessType::Scan);
+            if i % 50 == 0 {
+                lookup_hot_pages();
+            }
+        }
+        bpm.reset_stats();
+        lookup_hot_pages();
+        assert_eq!(num_hot_pages as u64, bpm.get_stats().cache_hits);
+        assert_eq!(0, bpm.get_stats().cache_misses);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_flush_on_drop() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 10;
+        let k = 5;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..buffer_pool_size {
+            let page = bpm.new_page().unwrap();
+            page.body_mut()[0] = i as u8 + 1;
+            // leave half of the pages pinned, they must be written back too
+            if i % 2 == 0 {
+                assert!(bpm.unpin_page(i as PageId, true).is_ok());
+            } else {
+                page.set_dirty(true);
+            }
+        }
+        drop(bpm);
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for i in 0..buffer_pool_size {
+            disk_manager.read_page(i as PageId, &mut buf).unwrap();
+            assert_eq!(buf[SIZE_PAGE_HEADER], i as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_replacer_types() {
+        for replacer_type in [
+            ReplacerType::LruK(2),
+            ReplacerType::Lru,
+            ReplacerType::Clock,
+            ReplacerType::TwoQ {
+                a1in_percent: 25,
+                a1out_percent: 50,
+            },
+        ] {
+            let dir = TempDir::new("test").unwrap();
+            let db_name = dir.path().join("test.db");
+            let buffer_pool_size = 5;
+
+            let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+            let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, replacer_type);
+
+            // Scenario: write twice as many pages as there are frames, so that
+            // the first half has to be evicted and written back.
+            for i in 0..buffer_pool_size * 2 {
+                let page = bpm.new_page().unwrap();
+                page.body_mut()[0] = i as u8 + 1;
+                assert!(bpm.unpin_page(i as PageId, true).is_ok());
+            }
+
+            // Scenario: a pinned page is never chosen as a victim, whatever
+            // the policy.
+            let pinned = bpm.fetch_page(0, AccessType::Unknown).unwrap();
+            for i in 1..buffer_pool_size * 2 {
+                let page = bpm.fetch_page(i as PageId, AccessType::Unknown).unwrap();
+                assert_eq!(i as u8 + 1, page.body()[0], "{:?}", replacer_type);
+                assert!(bpm.unpin_page(i as PageId, false).is_ok());
+                assert_eq!(Some(0), pinned.get_page_id());
+            }
+            assert!(bpm.unpin_page(0, false).is_ok());
+
+            // Scenario: with every frame pinned no page can be brought in.
+            for i in 0..buffer_pool_size {
+                bpm.fetch_page(i as PageId, AccessType::Unknown).unwrap();
+            }
+            assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+            assert!(matches!(
+                bpm.fetch_page(buffer_pool_size as PageId, AccessType::Unknown),
+                Err(BufferError::NoFreeFrame)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_victims_in_range() {
+        for replacer_type in [
+            ReplacerType::LruK(2),
+            ReplacerType::Lru,
+            ReplacerType::Clock,
+            ReplacerType::TwoQ {
+                a1in_percent: 25,
+                a1out_percent: 50,
+            },
+        ] {
+            let dir = TempDir::new("test").unwrap();
+            let db_name = dir.path().join("test.db");
+            let buffer_pool_size = 3;
+
+            let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+            let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, replacer_type);
+
+            // Scenario: churn many pages through a small pool, every frame the
+            // replacer hands out is one of the pool's.
+            for i in 0..buffer_pool_size * 10 {
+                bpm.new_page().unwrap();
+                let info = bpm.inspect_page(i as PageId).unwrap();
+                assert!(info.frame_id < buffer_pool_size, "{:?}", replacer_type);
+                assert!(bpm.unpin_page(i as PageId, true).is_ok());
+            }
+            for i in (0..buffer_pool_size * 10).rev() {
+                bpm.fetch_page(i as PageId, AccessType::Scan).unwrap();
+                let info = bpm.inspect_page(i as PageId).unwrap();
+                assert!(info.frame_id < buffer_pool_size, "{:?}", replacer_type);
+                assert!(bpm.unpin_page(i as PageId, false).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_io_errors() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let db_name = db_name.to_str().unwrap();
+        let buffer_pool_size = 2;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..2 {
+            bpm.new_page().unwrap().body_mut()[0] = i + 1;
+            bpm.unpin_page(i as PageId, true).unwrap();
+        }
+        drop(bpm);
+
+        // A read-only disk manager fails every write.
+        let options = DiskManagerOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let disk_manager = Box::new(DiskManager::with_options(db_name, options).unwrap());
+        let mut bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+
+        // Scenario: a failed flush surfaces the I/O error and leaves the page
+        // dirty.
+        bpm.fetch_page(0, AccessType::Unknown).unwrap().body_mut()[0] = 42;
+        bpm.unpin_page(0, true).unwrap();
+        assert!(matches!(bpm.flush_page(0), Err(BufferError::Io(_))));
+        assert!(matches!(bpm.flush_all_pages(), Err(BufferError::Io(_))));
+        assert!(bpm.get_pages()[0].is_dirty());
+
+        // Scenario: a page the file has room for but that was never written
+        // reads as zeros, a page past those fails to read, and failing to
+        // allocate a page id gives the frame back, so it can still be used
+        // afterwards.
+        assert_eq!(
+            0,
+            bpm.fetch_page(10, AccessType::Unknown).unwrap().body()[0]
+        );
+        bpm.unpin_page(10, false).unwrap();
+        assert!(matches!(
+            bpm.fetch_page(100, AccessType::Unknown),
+            Err(BufferError::Io(_))
+        ));
+        assert!(matches!(bpm.new_page(), Err(BufferError::Io(_))));
+        assert_eq!(2, bpm.fetch_page(1, AccessType::Unknown).unwrap().body()[0]);
+        bpm.unpin_page(1, false).unwrap();
+
+        // Scenario: the other errors tell apart why a call failed.
+        assert!(matches!(
+            bpm.unpin_page(1, false),
+            Err(BufferError::PageNotPinned)
+        ));
+        assert!(matches!(
+            bpm.unpin_page(7, false),
+            Err(BufferError::PageNotFound)
+        ));
+        assert!(matches!(bpm.flush_page(7), Err(BufferError::PageNotFound)));
+        bpm.fetch_page(0, AccessType::Unknown).unwrap();
+        bpm.fetch_page(1, AccessType::Unknown).unwrap();
+        assert!(matches!(bpm.delete_page(0), Err(BufferError::PagePinned)));
+        assert!(matches!(
+            bpm.fetch_page(2, AccessType::Unknown),
+            Err(BufferError::NoFreeFrame)
+        ));
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_eviction_write_failure() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let db_name = db_name.to_str().unwrap();
+        let buffer_pool_size = 2;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..3 {
+            bpm.new_page().unwrap().body_mut()[0] = i + 1;
+            bpm.unpin_page(i as PageId, true).unwrap();
+        }
+        drop(bpm);
+
+        // A read-only disk manager fails every write.
+        let options = DiskManagerOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let disk_manager = Box::new(DiskManager::with_options(db_name, options).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+
+        // Scenario: page 0 is dirty and the first choice of LRU-K, so
+        // bringing in page 2 has to write it back, which fails.
+        bpm.fetch_page(0, AccessType::Unknown).unwrap().body_mut()[0] = 42;
+        bpm.unpin_page(0, true).unwrap();
+        bpm.fetch_page(1, AccessType::Unknown).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+        assert!(matches!(
+            bpm.fetch_page(2, AccessType::Unknown),
+            Err(BufferError::Io(_))
+        ));
+
+        // The victim is still in the pool, dirty, unpinned and evictable, and
+        // no frame got lost.
+        assert_page_table_consistent(&bpm);
+        let info = bpm.inspect_page(0).unwrap();
+        assert!(info.is_dirty);
+        assert_eq!(0, info.pin_count);
+        assert!(bpm.contains_page(1));
+        assert!(!bpm.contains_page(2));
+        assert_eq!(0, bpm.get_stats().pages_evicted);
+
+        // Scenario: putting the victim back counts as an access, so the clean
+        // page 1 is evicted next and page 2 can be read.
+        assert_eq!(3, bpm.fetch_page(2, AccessType::Unknown).unwrap().body()[0]);
+        bpm.unpin_page(2, false).unwrap();
+        assert!(!bpm.contains_page(1));
+        assert_eq!(
+            42,
+            bpm.fetch_page(0, AccessType::Unknown).unwrap().body()[0]
+        );
+        bpm.unpin_page(0, false).unwrap();
+        assert!(bpm.inspect_page(0).unwrap().is_dirty);
+        assert_page_table_consistent(&bpm);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_recycled_frame_reset() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(1, disk_manager, ReplacerType::LruK(k));
+
+        // Scenario: a new page evicting a dirty page gets a clean frame.
+        let page0 = bpm.new_page().unwrap();
+        page0.get_data_mut().fill(0xff);
+        bpm.unpin_page(0, true).unwrap();
+        let page1 = bpm.new_page().unwrap();
+        assert_eq!(Some(1), page1.get_page_id());
+        assert!(page1.get_data().iter().all(|&b| b == 0));
+        assert!(!page1.is_dirty());
+        assert_eq!(1, page1.get_pin_count());
+
+        // Scenario: so does a fetched page, which only holds what was read.
+        page1.body_mut()[0] = 1;
+        bpm.unpin_page(1, true).unwrap();
+        let page0 = bpm.fetch_page(0, AccessType::Unknown).unwrap();
+        assert!(page0.get_data().iter().all(|&b| b == 0xff));
+        assert!(!page0.is_dirty());
+        assert_eq!(1, page0.get_pin_count());
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_flush_dirty_pages() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 128;
+        let num_dirty = 64;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..buffer_pool_size {
+            bpm.new_page().unwrap();
+            bpm.unpin_page(i as PageId, false).unwrap();
+        }
+        fn dirty_pages(bpm: &BufferPoolManager, num_dirty: usize, round: u8) {
+            for i in 0..num_dirty {
+                let page = bpm.fetch_page(i as PageId, AccessType::Unknown).unwrap();
+                page.body_mut()[0] = round;
+                bpm.unpin_page(i as PageId, true).unwrap();
+            }
+        }
+
+        // Scenario: every write is scheduled before the first wait, and the
+        // pages cannot be evicted until they are written.
+        dirty_pages(&bpm, num_dirty, 1);
+        let writes_before = bpm.get_disk_manager().get_num_writes();
+        let handle = bpm.flush_dirty_pages_async();
+        assert_eq!(num_dirty, handle.num_scheduled());
+        for page in bpm.get_pages().iter().take(num_dirty) {
+            assert_eq!(1, page.get_pin_count());
+        }
+        assert_eq!(num_dirty, handle.join().unwrap());
+        assert_eq!(
+            writes_before + num_dirty as i32,
+            bpm.get_disk_manager().get_num_writes()
+        );
+        for page in bpm.get_pages().iter() {
+            assert_eq!(0, page.get_pin_count());
+            assert!(!page.is_dirty());
+        }
+
+        // Scenario: clean pages are skipped.
+        assert_eq!(0, bpm.flush_dirty_pages().unwrap());
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_snapshot_flush() {
+        let disk_manager = Box::new(DiskManagerMemory::new());
+        let bpm = Arc::new(BufferPoolManager::new(
+            2,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id().unwrap();
+
+        // Scenario: a writer keeps rewriting the whole body of the page with
+        // one byte value per round, while the page is flushed over and over.
+        // Every version on disk is one round's, never a mix of two.
+        let rounds = 200;
+        let writer = thread::spawn(move || {
+            for round in 1..=rounds {
+                page.set_lsn(round as Lsn);
+                page.body_mut().fill(round);
+            }
+        });
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        let mut flushes = 0;
+        while !writer.is_finished() || flushes == 0 {
+            bpm.flush_page(page_id).unwrap();
+            flushes += 1;
+            bpm.get_disk_manager().read_page(page_id, &mut buf).unwrap();
+            let body = &buf[SIZE_PAGE_HEADER..];
+            assert!(body.iter().all(|&b| b == body[0]), "torn page on disk");
+        }
+        writer.join().unwrap();
+
+        // Scenario: the LSN of the last snapshot written is recorded.
+        assert!(bpm.get_max_written_lsn() <= rounds as Lsn);
+        bpm.flush_page(page_id).unwrap();
+        assert_eq!(rounds as Lsn, bpm.get_max_written_lsn());
+        bpm.get_disk_manager().read_page(page_id, &mut buf).unwrap();
+        assert!(buf[SIZE_PAGE_HEADER..].iter().all(|&b| b == rounds));
+    }
+
+    /// Checks that no page is resident in more than one frame, and that the
+    /// page table agrees with the frames.
+    fn assert_page_table_consistent(bpm: &BufferPoolManager) {
+        let page_table = bpm.page_table.lock().unwrap();
+        let mut resident: Vec<PageId> = bpm
+            .get_pages()
+            .iter()
+            .filter_map(|page| page.get_page_id())
+            .collect();
+        resident.sort();
+        let num_resident = resident.len();
+        resident.dedup();
+        assert_eq!(num_resident, resident.len());
+        assert_eq!(num_resident, page_table.len());
+        for (&page_id, &frame_id) in page_table.iter() {
+            assert_eq!(Some(page_id), bpm.get_pages()[frame_id].get_page_id());
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_concurrent_fetch_same_pages() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let num_pages = 20;
+        let threads = 16;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(num_pages, disk_manager, ReplacerType::LruK(k));
+        for i in 0..num_pages {
+            bpm.new_page().unwrap().body_mut()[0] = i as u8 + 1;
+            bpm.unpin_page(i as PageId, true).unwrap();
+        }
+        drop(bpm);
+
+        // Scenario: with a frame for every page, each page is read from disk
+        // exactly once, however many threads miss on it at the same time. With
+        // half the frames, pages are evicted and read again while other threads
+        // are waiting on them.
+        for buffer_pool_size in [num_pages, num_pages / 2] {
+            let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+            let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let bpm = &bpm;
+                    s.spawn(move || {
+                        for round in 0..50 {
+                            for i in 0..num_pages {
+                                let page_id = ((i + t + round) % num_pages) as PageId;
+                                let page = loop {
+                                    match bpm.fetch_page(page_id, AccessType::Unknown) {
+                                        Ok(page) => break page,
+                                        Err(BufferError::NoFreeFrame) => thread::yield_now(),
+                                        Err(e) => panic!("fetch failed: {}", e),
+                                    }
+                                };
+                                assert_eq!(Some(page_id), page.get_page_id());
+                                assert_eq!(page_id as u8 + 1, page.body()[0]);
+                                bpm.unpin_page(page_id, false).unwrap();
+                            }
+                        }
+                    });
+                }
+            });
+
+            assert_page_table_consistent(&bpm);
+            if buffer_pool_size == num_pages {
+                assert_eq!(num_pages as u64, bpm.get_stats().cache_misses);
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_frame_latches() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 4;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            buffer_pool_size,
+            disk_manager,
+            ReplacerType::LruK(k),
+        ));
+        for i in 0..2 {
+            bpm.new_page().unwrap();
+            bpm.unpin_page(i, false).unwrap();
+        }
+
+        // Scenario: page 0 is write latched, so a flush of it blocks in the
+        // disk scheduler until the latch is released.
+        let write_guard = bpm.clone().fetch_page_write(0).unwrap();
+        let flusher = {
+            let bpm = bpm.clone();
+            thread::spawn(move || bpm.flush_page(0))
+        };
+        thread::sleep(Duration::from_millis(50));
+
+        // Meanwhile the other page can still be used. With one latch for the
+        // whole page table the flush would hold it, and this would wait for
+        // the write latch held by this very thread.
+        let (done_tx, done_rx) = mpsc::channel();
+        {
+            let bpm = bpm.clone();
+            thread::spawn(move || {
+                bpm.fetch_page(1, AccessType::Unknown).unwrap();
+                bpm.unpin_page(1, true).unwrap();
+                assert!(bpm.inspect_page(1).unwrap().is_dirty);
+                assert!(bpm.contains_page(0));
+                done_tx.send(()).unwrap();
+            });
+        }
+        assert!(done_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        drop(write_guard);
+        assert!(flusher.join().unwrap().is_ok());
+        assert_eq!(0, bpm.inspect_page(0).unwrap().pin_count);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_flush_under_write_guard() {
+        let disk_manager = Box::new(DiskManagerMemory::new());
+        let bpm = Arc::new(BufferPoolManager::new(
+            3,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+        let page = bpm.new_page().unwrap();
+        bpm.unpin_page(0, false).unwrap();
+
+        // Scenario: another thread holds the write latch of page 0 while it
+        // is flushed. The flush waits for the latch with the page pinned but
+        // not its frame latched, so the writer can still fetch and unpin the
+        // page before it releases the latch.
+        let (latched_tx, latched_rx) = mpsc::channel();
+        let (go_tx, go_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let writer = {
+            let bpm = bpm.clone();
+            thread::spawn(move || {
+                let mut guard = bpm.clone().fetch_page_write(0).unwrap();
+                guard.get_data_mut()[SIZE_PAGE_HEADER] = 1;
+                latched_tx.send(()).unwrap();
+                go_rx.recv().unwrap();
+                bpm.fetch_page(0, AccessType::Unknown).unwrap();
+                bpm.unpin_page(0, false).unwrap();
+                drop(guard);
+                done_tx.send(()).unwrap();
+            })
+        };
+        latched_rx.recv().unwrap();
+        let flusher = {
+            let bpm = bpm.clone();
+            thread::spawn(move || bpm.flush_page(0))
+        };
+        // the flush pins the page before it waits for the latch
+        while page.get_pin_count() < 2 {
+            thread::yield_now();
+        }
+        go_tx.send(()).unwrap();
+        assert!(done_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        writer.join().unwrap();
+
+        // Scenario: the flush wrote what the writer left, and unpinned the
+        // page again.
+        assert!(flusher.join().unwrap().is_ok());
+        let rx = bpm.disk_scheduler.schedule_read(0);
+        assert_eq!(1, rx.blocking_recv().unwrap().unwrap()[SIZE_PAGE_HEADER]);
+        assert_eq!(0, bpm.inspect_page(0).unwrap().pin_count);
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_concurrent_stress() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 8;
+        let num_pages = 32;
+        let threads = 8;
+        let k = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = BufferPoolManager::new(buffer_pool_size, disk_manager, ReplacerType::LruK(k));
+        for i in 0..num_pages {
+            bpm.new_page().unwrap();
+            bpm.unpin_page(i as PageId, false).unwrap();
+            // evicting a clean page does not write it, make it readable
+            bpm.flush_page(i as PageId).unwrap();
+        }
+        let bpm = Arc::new(bpm);
+        bpm.start_background_flush(Duration::from_millis(1));
+
+        // Scenario: threads fetch, dirty, flush, prefetch and inspect pages
+        // of a pool that is much smaller than the working set. Every path
+        // that takes several locks runs against all the others, so a wrong
+        // lock order shows up as a hang.
+        thread::scope(|s| {
+            for t in 0..threads {
+                let bpm = &bpm;
+                s.spawn(move || {
+                    let mut rng = rand::thread_rng();
+                    let page_dist = Uniform::from(0..num_pages as PageId);
+                    let op_dist = Uniform::from(0..100);
+                    for _ in 0..2000 {
+                        let page_id = page_dist.sample(&mut rng);
+                        match op_dist.sample(&mut rng) {
+                            0..=59 => match bpm.fetch_page(page_id, AccessType::Unknown) {
+                                Ok(page) => {
+                                    assert_eq!(Some(page_id), page.get_page_id());
+                                    page.body_mut()[t] = page_id as u8;
+                                    bpm.unpin_page(page_id, true).unwrap();
+                                }
+                                Err(BufferError::NoFreeFrame) => {}
+                                Err(e) => panic!("fetch failed: {}", e),
+                            },
+                            60..=74 => match bpm.flush_page(page_id) {
+                                Ok(()) | Err(BufferError::PageNotFound) => {}
+                                Err(e) => panic!("flush failed: {}", e),
+                            },
+                            75..=84 => {
+                                let page_ids: Vec<PageId> = (page_id..page_id + 3)
+                                    .map(|page_id| page_id % num_pages as PageId)
+                                    .collect();
+                                bpm.prefetch_pages(&page_ids);
+                            }
+                            85..=89 => {
+                                bpm.flush_dirty_pages().unwrap();
+                            }
+                            _ => {
+                                if let Some(info) = bpm.inspect_page(page_id) {
+                                    assert!(info.frame_id < buffer_pool_size);
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        bpm.stop_background_flush();
+
+        // Every frame is either free or holds an unpinned page, which the
+        // replacer tracks as evictable.
+        assert_page_table_consistent(&bpm);
+        let resident = bpm.resident_page_ids();
+        for &page_id in resident.iter() {
+            assert_eq!(0, bpm.inspect_page(page_id).unwrap().pin_count);
+        }
+        assert_eq!(resident.len(), bpm.replacer.size());
+        assert_eq!(
+            buffer_pool_size,
+            resident.len() + bpm.free_list.lock().unwrap().len()
+        );
+        for page_id in 0..num_pages as PageId {
+            let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+            for t in 0..threads {
+                let byte = page.body()[t];
+                assert!(byte == 0 || byte == page_id as u8);
+            }
+            bpm.unpin_page(page_id, false).unwrap();
+        }
+    }
+
+    /// A replacer call, as recorded by MockReplacer.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ReplacerCall {
+        Evict(Option<FrameId>),
+        RecordAccess(FrameId, AccessType),
+        SetEvictable(FrameId, bool),
+        Remove(FrameId),
+        BindPage(FrameId, PageId),
+    }
+
+    /// Forwards to an LRU-K replacer and records every call except size, so
+    /// that tests can check how the buffer pool drives its replacer.
+    struct MockReplacer {
+        inner: LRUKReplacer,
+        calls: Arc<Mutex<Vec<ReplacerCall>>>,
+    }
+
+    impl MockReplacer {
+        fn record(&self, call: ReplacerCall) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl Replacer for MockReplacer {
+        fn evict(&self) -> Option<FrameId> {
+            let frame_id = self.inner.evict();
+            self.record(ReplacerCall::Evict(frame_id));
+            frame_id
+        }
+
+        fn record_access(&self, frame_id: FrameId, access_type: AccessType) {
+            self.record(ReplacerCall::RecordAccess(frame_id, access_type));
+            self.inner.record_access(frame_id, access_type);
+        }
+
+        fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) {
+            self.record(ReplacerCall::SetEvictable(frame_id, set_evictable));
+            self.inner.set_evictable(frame_id, set_evictable);
+        }
+
+        fn remove(&self, frame_id: FrameId) {
+            self.record(ReplacerCall::Remove(frame_id));
+            self.inner.remove(frame_id);
+        }
+
+        fn size(&self) -> usize {
+            self.inner.size()
+        }
+
+        fn bind_page(&self, frame_id: FrameId, page_id: PageId) {
+            self.record(ReplacerCall::BindPage(frame_id, page_id));
+            self.inner.bind_page(frame_id, page_id);
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_manager_replacer_calls() {
+        use ReplacerCall::*;
+
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let buffer_pool_size = 2;
+        let k = 2;
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let replacer = MockReplacer {
+            inner: LRUKReplacer::new(buffer_pool_size, k),
+            calls: calls.clone(),
+        };
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm =
+            BufferPoolManager::with_replacer(buffer_pool_size, disk_manager, Box::new(replacer));
+        let take_calls = || std::mem::take(&mut *calls.lock().unwrap());
+
+        // Scenario: a new page is tracked and pinned, in that order.
+        bpm.new_page().unwrap();
+        assert_eq!(
+            vec![
+                BindPage(0, 0),
+                RecordAccess(0, AccessType::Unknown),
+                SetEvictable(0, false)
+            ],
+            take_calls()
+        );
+
+        // Scenario: a hit records the access and pins the frame again. Only
+        // the last unpin makes it evictable.
+        bpm.fetch_page(0, AccessType::Lookup).unwrap();
+        assert_eq!(
+            vec![RecordAccess(0, AccessType::Lookup), SetEvictable(0, false)],
+            take_calls()
+        );
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert_eq!(Vec::<ReplacerCall>::new(), take_calls());
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert_eq!(vec![SetEvictable(0, true)], take_calls());
+
+        // Scenario: flushing pins the page for the write with a scan access,
+        // which leaves the access history alone, and unpins it again.
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert!(bpm.flush_page(1).is_ok());
+        assert_eq!(
+            vec![
+                BindPage(1, 1),
+                RecordAccess(1, AccessType::Unknown),
+                SetEvictable(1, false),
+                SetEvictable(1, true),
+                RecordAccess(1, AccessType::Scan),
+                SetEvictable(1, false),
+                SetEvictable(1, true),
+            ],
+            take_calls()
+        );
+
+        // Scenario: with no free frame left, a new page takes the frame the
+        // replacer gives up. Page 1 has +inf backward k-distance, so it goes.
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(2, true).is_ok());
+        assert_eq!(
+            vec![
+                Evict(Some(1)),
+                Remove(1),
+                BindPage(1, 2),
+                RecordAccess(1, AccessType::Unknown),
+                SetEvictable(1, false),
+                SetEvictable(1, true),
+            ],
+            take_calls()
+        );
+
+        // Scenario: a miss does the same, with the access type of the fetch.
+        bpm.fetch_page(1, AccessType::Scan).unwrap();
+        assert_eq!(
+            vec![
+                Evict(Some(1)),
+                Remove(1),
+                BindPage(1, 1),
+                RecordAccess(1, AccessType::Scan),
+                SetEvictable(1, false),
+            ],
+            take_calls()
+        );
+
+        // Scenario: with every frame pinned the replacer has nothing to give.
+        bpm.fetch_page(0, AccessType::Unknown).unwrap();
+        assert!(matches!(
+            bpm.fetch_page(2, AccessType::Unknown),
+            Err(BufferError::NoFreeFrame)
+        ));
+        assert_eq!(
+            vec![
+                RecordAccess(0, AccessType::Unknown),
+                SetEvictable(0, false),
+                Evict(None),
+            ],
+            take_calls()
+        );
     }
 }
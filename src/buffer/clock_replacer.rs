@@ -0,0 +1,240 @@
+use std::sync::Mutex;
+
+use super::replacer::{AccessType, Replacer};
+use crate::common::config::FrameId;
+
+#[derive(Debug)]
+struct ClockFrame {
+    /// Set on every access, cleared when the clock hand passes the frame.
+    referenced: bool,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+struct ClockState {
+    /// One slot per frame, none if the frame is not tracked.
+    frames: Vec<Option<ClockFrame>>,
+    /// The slot the clock hand points at.
+    hand: usize,
+    /// Number of evictable frames.
+    size: usize,
+}
+
+/// ClockReplacer implements the clock (second chance) replacement policy.
+///
+/// Frames sit in a circular buffer with a reference bit each. To find a
+/// victim the clock hand sweeps over the buffer: a frame with its reference
+/// bit set gets a second chance and has the bit cleared, the first evictable
+/// frame without it is evicted.
+///
+/// Scan accesses do not set the reference bit, so frames only touched by a
+/// scan are evicted on the first pass of the hand.
+#[derive(Debug)]
+pub struct ClockReplacer {
+    state: Mutex<ClockState>,
+}
+
+impl ClockReplacer {
+    /// @brief a new ClockReplacer.
+    /// @param num_frames the maximum number of frames the ClockReplacer will
+    /// be required to store
+    pub fn new(num_frames: usize) -> Self {
+        Self {
+            state: Mutex::new(ClockState {
+                frames: (0..num_frames).map(|_| None).collect(),
+                hand: 0,
+                size: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for ClockReplacer {
+    /// @brief Advance the clock hand until it finds an evictable frame whose
+    /// reference bit is not set, clearing the bits it passes over, and evict
+    /// that frame.
+    ///
+    /// @return the evicted frame, or none if no frames can be evicted.
+    fn evict(&self) -> Option<FrameId> {
+        let mut state = self.state.lock().unwrap();
+        if state.size == 0 {
+            return None;
+        }
+        // at least one frame is evictable, so two sweeps always find a victim
+        loop {
+            let frame_id = state.hand;
+            state.hand = (state.hand + 1) % state.frames.len();
+            let slot = &mut state.frames[frame_id];
+            match slot {
+                Some(frame) if frame.is_evictable => {
+                    if frame.referenced {
+                        frame.referenced = false;
+                        continue;
+                    }
+                    *slot = None;
+                    state.size -= 1;
+                    return Some(frame_id);
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// @brief Record an access to the given frame by setting its reference
+    /// bit. A frame seen for the first time starts out evictable.
+    ///
+    /// If frame id is invalid (ie. larger than the number of frames), panic.
+    ///
+    /// @param frame_id id of frame that received a new access.
+    /// @param access_type type of access that was received.
+    fn record_access(&self, frame_id: FrameId, access_type: AccessType) {
+        let mut state = self.state.lock().unwrap();
+        let referenced = access_type != AccessType::Scan;
+        let slot = match state.frames.get_mut(frame_id) {
+            Some(slot) => slot,
+            None => panic!("Invalid frame id"),
+        };
+        match slot {
+            Some(frame) => frame.referenced |= referenced,
+            None => {
+                *slot = Some(ClockFrame {
+                    referenced,
+                    is_evictable: true,
+                });
+                state.size += 1;
+            }
+        }
+    }
+
+    /// @brief Toggle whether a frame is evictable or non-evictable, adjusting
+    /// the replacer's size accordingly.
+    ///
+    /// If frame id is invalid, panic. A frame that is not tracked is left
+    /// alone.
+    ///
+    /// @param frame_id id of frame whose 'evictable' status will be modified
+    /// @param set_evictable whether the given frame is evictable or not
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) {
+        let mut state = self.state.lock().unwrap();
+        let frame = match state.frames.get_mut(frame_id) {
+            Some(Some(frame)) => frame,
+            Some(None) => return,
+            None => panic!("Invalid frame id"),
+        };
+        if frame.is_evictable == set_evictable {
+            return;
+        }
+        frame.is_evictable = set_evictable;
+        if set_evictable {
+            state.size += 1;
+        } else {
+            state.size -= 1;
+        }
+    }
+
+    /// @brief Remove an evictable frame from the replacer, wherever the clock
+    /// hand is.
+    ///
+    /// If Remove is called on a non-evictable frame, panic. If frame id is
+    /// invalid, panic.
+    ///
+    /// If specified frame is not found, directly return from this function.
+    ///
+    /// @param frame_id id of frame to be removed
+    fn remove(&self, frame_id: FrameId) {
+        let mut state = self.state.lock().unwrap();
+        let slot = match state.frames.get_mut(frame_id) {
+            Some(slot) => slot,
+            None => panic!("Invalid frame id"),
+        };
+        if let Some(frame) = slot {
+            if !frame.is_evictable {
+                panic!("Frame is not evictable");
+            }
+            *slot = None;
+            state.size -= 1;
+        }
+    }
+
+    /// @brief Return replacer's size, which tracks the number of evictable
+    /// frames.
+    fn size(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClockReplacer;
+    use crate::buffer::replacer::{AccessType, Replacer};
+
+    #[test]
+    fn test_clock_sample() {
+        let clock_replacer = ClockReplacer::new(7);
+
+        // Scenario: add six elements to the replacer. We have [1,2,3,4,5]. Frame 6 is
+        // non-evictable.
+        for frame_id in 1..=6 {
+            clock_replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        clock_replacer.set_evictable(6, false);
+        assert_eq!(5, clock_replacer.size());
+
+        // Scenario: every frame is referenced, so the hand clears all the bits
+        // on its first sweep and evicts in slot order on the second one.
+        assert_eq!(Some(1), clock_replacer.evict());
+        assert_eq!(Some(2), clock_replacer.evict());
+        assert_eq!(Some(3), clock_replacer.evict());
+        assert_eq!(2, clock_replacer.size());
+
+        // Scenario: now the replacer has [4,5] with the hand at 4. Access 4
+        // again and add frames 1 and 3. 4 gets a second chance, so 5 goes next.
+        clock_replacer.record_access(4, AccessType::Unknown);
+        clock_replacer.record_access(1, AccessType::Unknown);
+        clock_replacer.record_access(3, AccessType::Unknown);
+        assert_eq!(4, clock_replacer.size());
+        assert_eq!(Some(5), clock_replacer.evict());
+        assert_eq!(3, clock_replacer.size());
+
+        // Scenario: set 6 evictable. The hand passes 6 and wraps around to 1
+        // and 3, whose bits are still set, then finds 4 with its bit cleared.
+        clock_replacer.set_evictable(6, true);
+        assert_eq!(4, clock_replacer.size());
+        assert_eq!(Some(4), clock_replacer.evict());
+        assert_eq!(Some(6), clock_replacer.evict());
+
+        // Scenario: a non-evictable frame is skipped by the hand.
+        clock_replacer.set_evictable(1, false);
+        assert_eq!(Some(3), clock_replacer.evict());
+        assert_eq!(0, clock_replacer.size());
+        assert_eq!(None, clock_replacer.evict());
+        clock_replacer.set_evictable(1, true);
+        assert_eq!(Some(1), clock_replacer.evict());
+
+        // This operation should not modify size
+        assert_eq!(None, clock_replacer.evict());
+        assert_eq!(0, clock_replacer.size());
+    }
+
+    #[test]
+    fn test_clock_scan_access() {
+        let clock_replacer = ClockReplacer::new(7);
+
+        // Scenario: frames 1 and 2 are looked up, frames 3 and 4 are only
+        // scanned, so they never get a reference bit.
+        clock_replacer.record_access(1, AccessType::Lookup);
+        clock_replacer.record_access(2, AccessType::Index);
+        clock_replacer.record_access(3, AccessType::Scan);
+        clock_replacer.record_access(4, AccessType::Scan);
+        // a later lookup gives a scanned frame a second chance
+        clock_replacer.record_access(5, AccessType::Scan);
+        clock_replacer.record_access(5, AccessType::Lookup);
+
+        assert_eq!(Some(3), clock_replacer.evict());
+        assert_eq!(Some(4), clock_replacer.evict());
+        assert_eq!(Some(1), clock_replacer.evict());
+        assert_eq!(Some(2), clock_replacer.evict());
+        assert_eq!(Some(5), clock_replacer.evict());
+        assert_eq!(None, clock_replacer.evict());
+    }
+}
@@ -1,13 +1,78 @@
-use std::collections::{HashMap, LinkedList};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 use std::sync::Mutex;
 
+use super::replacer::{AccessType, FrameDebugInfo, Replacer};
 use crate::common::config::FrameId;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-enum Distance {
-    Num(usize),
-    Inf(usize),
+/// Where a frame stands in the eviction order. The smallest key is evicted
+/// first, so the variants are listed from the first to go to the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EvictionKey {
+    /// Only accessed by scans, least recently used first.
+    ScanOnly { last_access: usize },
+    /// +inf backward k-distance, earliest first access first.
+    Inf { first_access: usize },
+    /// Largest backward k-distance first, then the earliest of the k
+    /// accesses.
+    Num {
+        distance: Reverse<usize>,
+        first_access: usize,
+    },
+}
+
+/// The timestamps of the last k accesses of a frame, in a ring buffer that is
+/// allocated once with room for exactly k of them.
+#[derive(Debug)]
+struct AccessHistory {
+    timestamps: Vec<usize>,
+    /// Where the oldest timestamp is once the buffer is full.
+    head: usize,
+}
+
+impl AccessHistory {
+    fn new(k: usize) -> Self {
+        Self {
+            timestamps: Vec::with_capacity(k),
+            head: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    /// Add the latest access, dropping the oldest one if there are k already.
+    fn push(&mut self, ts: usize) {
+        if self.timestamps.len() < self.timestamps.capacity() {
+            self.timestamps.push(ts);
+        } else {
+            self.timestamps[self.head] = ts;
+            self.head = (self.head + 1) % self.timestamps.len();
+        }
+    }
+
+    /// Forget all accesses, keeping the buffer.
+    fn clear(&mut self) {
+        self.timestamps.clear();
+        self.head = 0;
+    }
+
+    fn oldest(&self) -> usize {
+        self.timestamps[self.head]
+    }
+
+    fn latest(&self) -> usize {
+        let len = self.timestamps.len();
+        self.timestamps[(self.head + len - 1) % len]
+    }
+
+    /// The timestamps from the oldest to the latest.
+    fn to_vec(&self) -> Vec<usize> {
+        let (latest, oldest) = self.timestamps.split_at(self.head);
+        oldest.iter().chain(latest).copied().collect()
+    }
 }
 
 #[derive(Debug)]
@@ -16,32 +81,70 @@ pub struct LRUKNode {
     /// stored in front.
     // Remove maybe_unused if you start using them. Feel free to change the member variables as you
     // want.
-    history: LinkedList<usize>,
+    history: AccessHistory,
     k: usize,
     frame_id: FrameId,
     is_evictable: bool,
+    /// True while the frame has only been accessed by scans.
+    scan_only: bool,
 }
 
 impl LRUKNode {
     pub fn new(frame_id: FrameId, k: usize) -> Self {
         Self {
-            history: LinkedList::new(),
+            history: AccessHistory::new(k),
             k,
             frame_id,
             is_evictable: true,
+            scan_only: false,
         }
     }
 
-    fn backward_k_distance(&self) -> Distance {
-        if self.history.len() < self.k {
-            // make it ordered in descending order
-            // so the max will be the inf one with earliest timestamp
-            return Distance::Inf(usize::MAX - self.history.front().unwrap());
+    /// The position of the frame in the eviction order. It only changes when
+    /// the frame is accessed.
+    fn eviction_key(&self) -> (EvictionKey, FrameId) {
+        let first_access = self.history.oldest();
+        let last_access = self.history.latest();
+        let key = if self.scan_only {
+            EvictionKey::ScanOnly { last_access }
+        } else if self.history.len() < self.k {
+            EvictionKey::Inf { first_access }
+        } else {
+            EvictionKey::Num {
+                distance: Reverse(last_access - first_access),
+                first_access,
+            }
+        };
+        (key, self.frame_id)
+    }
+
+    fn debug_info(&self) -> FrameDebugInfo {
+        let history = self.history.to_vec();
+        let backward_k_distance = if history.len() < self.k {
+            None
+        } else {
+            Some(history[history.len() - 1] - history[0])
+        };
+        FrameDebugInfo {
+            frame_id: self.frame_id,
+            is_evictable: self.is_evictable,
+            scan_only: self.scan_only,
+            history,
+            backward_k_distance,
         }
-        Distance::Num(self.history.back().unwrap() - self.history.front().unwrap())
     }
 }
 
+#[derive(Debug)]
+struct LRUKState {
+    node_store: HashMap<FrameId, LRUKNode>,
+    /// Taken under the same lock as the history it goes into, so that
+    /// histories are ordered the same way as the accesses were recorded.
+    current_timestamp: usize,
+    /// The eviction keys of all evictable frames, next victim first.
+    evictable: BTreeSet<(EvictionKey, FrameId)>,
+}
+
 /// LRUKReplacer implements the LRU-k replacement policy.
 ///
 /// The LRU-k algorithm evicts a frame whose backward k-distance is maximum
@@ -51,13 +154,19 @@ impl LRUKNode {
 /// A frame with less than k historical references is given
 /// +inf as its backward k-distance. When multiple frames have +inf backward
 /// k-distance, classical LRU algorithm is used to choose victim.
+///
+/// To keep a large scan from flushing out the working set, frames that have
+/// only been accessed by scans are evicted before all others, in LRU order,
+/// and scans do not add to the history of other frames.
+///
+/// Evictable frames are kept ordered by their eviction key, so evict does
+/// not have to look at every frame.
 #[derive(Debug)]
 pub struct LRUKReplacer {
     // TODO(student): implement me! You can replace these member variables as you like.
     // Remove maybe_unused if you start using them.
-    node_store: Mutex<HashMap<FrameId, LRUKNode>>,
-    current_timestamp: AtomicUsize,
-    current_size: AtomicUsize,
+    state: Mutex<LRUKState>,
+    /// The number of frames, valid frame ids are below it.
     replacer_size: usize,
     k: usize,
 }
@@ -68,16 +177,32 @@ impl LRUKReplacer {
     /// @brief a new LRUKReplacer.
     /// @param num_frames the maximum number of frames the LRUReplacer will be
     /// required to store
+    /// @param k the number of accesses remembered per frame, at least 1
     pub fn new(num_frames: usize, k: usize) -> Self {
+        assert!(k >= 1, "k must be at least 1");
         Self {
-            node_store: Mutex::new(HashMap::new()),
-            current_timestamp: AtomicUsize::new(0),
-            current_size: AtomicUsize::new(0),
+            state: Mutex::new(LRUKState {
+                node_store: HashMap::with_capacity(num_frames),
+                current_timestamp: 0,
+                evictable: BTreeSet::new(),
+            }),
             replacer_size: num_frames,
             k,
         }
     }
 
+    /// @brief Panic if frame_id is not a valid frame id.
+    fn check_frame_id(&self, frame_id: FrameId) {
+        if frame_id >= self.replacer_size {
+            panic!(
+                "Invalid frame id {}, the replacer has {} frames",
+                frame_id, self.replacer_size
+            );
+        }
+    }
+}
+
+impl Replacer for LRUKReplacer {
     /// TODO(P1): Add implementation
     ///
     /// @brief Find the frame with largest backward k-distance and evict that
@@ -87,6 +212,8 @@ impl LRUKReplacer {
     /// A frame with less than k historical references is given +inf as its
     /// backward k-distance. If multiple frames have inf backward
     /// k-distance, then evict frame with earliest timestamp based on LRU.
+    /// Frames with the same finite backward k-distance are evicted in the
+    /// order of the earliest of their last k accesses.
     ///
     /// Successful eviction of a frame should decrement the size of replacer and
     /// remove the frame's access history.
@@ -94,25 +221,11 @@ impl LRUKReplacer {
     /// @param[out] frame_id id of frame that is evicted.
     /// @return true if a frame is evicted successfully, false if no frames can
     /// be evicted.
-    pub fn evict(&self) -> Option<FrameId> {
-        let mut node_store = self.node_store.lock().unwrap();
-        let mut max_frame_id = None;
-        let mut max_backward_k_distance = Distance::Num(0);
-        for (frame_id, node) in node_store.iter() {
-            if !node.is_evictable {
-                continue;
-            }
-            let backward_k_distance = node.backward_k_distance();
-            if backward_k_distance > max_backward_k_distance {
-                max_backward_k_distance = backward_k_distance;
-                max_frame_id = Some(*frame_id);
-            }
-        }
-        if let Some(id) = max_frame_id {
-            node_store.remove(&id);
-            self.current_size.fetch_sub(1, Ordering::SeqCst);
-        }
-        max_frame_id
+    fn evict(&self) -> Option<FrameId> {
+        let mut state = self.state.lock().unwrap();
+        let (_, frame_id) = state.evictable.pop_first()?;
+        state.node_store.remove(&frame_id);
+        Some(frame_id)
     }
 
     /// TODO(P1): Add implementation
@@ -121,25 +234,47 @@ impl LRUKReplacer {
     /// timestamp. Create a new entry for access history if frame id has not
     /// been seen before.
     ///
-    /// If frame id is invalid (ie. larger than replacer_size_), panic.
+    /// If frame id is invalid (ie. larger than replacer_size_), panic. A
+    /// valid frame id can always be tracked: there is room for every frame,
+    /// evictable or not.
+    ///
+    /// A scan access is only recorded for frames that have not been accessed
+    /// otherwise. The first other access forgets the scan history.
     ///
     /// @param frame_id id of frame that received a new access.
-    pub fn record_access(&self, frame_id: FrameId) {
-        let ts = self.current_timestamp.fetch_add(1, Ordering::SeqCst);
-        let mut node_store = self.node_store.lock().unwrap();
+    /// @param access_type type of access that was received.
+    fn record_access(&self, frame_id: FrameId, access_type: AccessType) {
+        self.check_frame_id(frame_id);
+        let is_scan = access_type == AccessType::Scan;
+        let mut state = self.state.lock().unwrap();
+        let LRUKState {
+            node_store,
+            current_timestamp,
+            evictable,
+        } = &mut *state;
+        let ts = *current_timestamp;
+        *current_timestamp += 1;
         if let Some(node) = node_store.get_mut(&frame_id) {
-            node.history.push_back(ts);
-            if node.history.len() > self.k {
-                node.history.pop_front();
+            if is_scan && !node.scan_only {
+                return;
             }
-        } else {
-            if self.current_size.load(Ordering::SeqCst) >= self.replacer_size {
-                panic!("Replacer is full");
+            if node.is_evictable {
+                evictable.remove(&node.eviction_key());
+            }
+            if !is_scan && node.scan_only {
+                node.scan_only = false;
+                node.history.clear();
             }
+            node.history.push(ts);
+            if node.is_evictable {
+                evictable.insert(node.eviction_key());
+            }
+        } else {
             let mut node = LRUKNode::new(frame_id, self.k);
-            node.history.push_back(ts);
+            node.scan_only = is_scan;
+            node.history.push(ts);
+            evictable.insert(node.eviction_key());
             node_store.insert(frame_id, node);
-            self.current_size.fetch_add(1, Ordering::SeqCst);
         }
     }
 
@@ -153,27 +288,32 @@ impl LRUKReplacer {
     /// then size should decrement. If a frame was previously non-evictable
     /// and is to be set to evictable, then size should increment.
     ///
-    /// If frame id is invalid, panic.
+    /// If frame id is invalid, panic. A valid frame that has not been
+    /// accessed yet is not tracked and is left alone.
     ///
     /// For other scenarios, this function should terminate without modifying
     /// anything.
     ///
     /// @param frame_id id of frame whose 'evictable' status will be modified
     /// @param set_evictable whether the given frame is evictable or not
-    pub fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) {
-        let mut node_store = self.node_store.lock().unwrap();
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) {
+        self.check_frame_id(frame_id);
+        let mut state = self.state.lock().unwrap();
+        let LRUKState {
+            node_store,
+            evictable,
+            ..
+        } = &mut *state;
         if let Some(node) = node_store.get_mut(&frame_id) {
             if node.is_evictable == set_evictable {
                 return;
             }
             node.is_evictable = set_evictable;
             if set_evictable {
-                self.current_size.fetch_add(1, Ordering::SeqCst);
+                evictable.insert(node.eviction_key());
             } else {
-                self.current_size.fetch_sub(1, Ordering::SeqCst);
+                evictable.remove(&node.eviction_key());
             }
-        } else {
-            panic!("Invalid frame id");
         }
     }
 
@@ -187,20 +327,22 @@ impl LRUKReplacer {
     /// the frame with largest backward k-distance. This function removes
     /// specified frame id, no matter what its backward k-distance is.
     ///
-    /// If Remove is called on a non-evictable frame, panic.
+    /// If Remove is called on a non-evictable frame, panic. If frame id is
+    /// invalid, panic.
     ///
     /// If specified frame is not found, directly return from this function.
     ///
     /// @param frame_id id of frame to be removed
-    pub fn remove(&self, frame_id: FrameId) {
-        let mut node_store = self.node_store.lock().unwrap();
-        if let Some(node) = node_store.get_mut(&frame_id) {
-            if !node.is_evictable {
-                panic!("Frame is not evictable");
-            }
-            node_store.remove(&frame_id);
-            self.current_size.fetch_sub(1, Ordering::SeqCst);
-        }
+    fn remove(&self, frame_id: FrameId) {
+        self.check_frame_id(frame_id);
+        let mut state = self.state.lock().unwrap();
+        let key = match state.node_store.get(&frame_id) {
+            Some(node) if !node.is_evictable => panic!("Frame is not evictable"),
+            Some(node) => node.eviction_key(),
+            None => return,
+        };
+        state.node_store.remove(&frame_id);
+        state.evictable.remove(&key);
     }
 
     /// TODO(P1): Add implementation
@@ -209,26 +351,52 @@ impl LRUKReplacer {
     /// frames.
     ///
     /// @return size_t
-    pub fn size(&self) -> usize {
-        self.current_size.load(Ordering::SeqCst)
+    fn size(&self) -> usize {
+        self.state.lock().unwrap().evictable.len()
+    }
+
+    /// @brief Describe every tracked frame in eviction order, evictable frames
+    /// first.
+    fn dump(&self) -> Vec<FrameDebugInfo> {
+        let state = self.state.lock().unwrap();
+        let mut nodes: Vec<&LRUKNode> = state.node_store.values().collect();
+        nodes.sort_by_key(|node| (!node.is_evictable, node.eviction_key()));
+        nodes.into_iter().map(LRUKNode::debug_info).collect()
     }
 }
 
+impl fmt::Display for LRUKReplacer {
+    /// Formats the dump as a table, one frame per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frame evictable scan  distance history")?;
+        for info in self.dump() {
+            writeln!(f, "{:>5} {}", info.frame_id, info)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+    use std::thread;
+
     use super::LRUKReplacer;
+    use crate::buffer::replacer::{AccessType, FrameDebugInfo, Replacer};
+    use crate::common::config::FrameId;
 
     #[test]
     pub fn test_sample() {
-        let mut lru_replacer = LRUKReplacer::new(7, 2);
+        let lru_replacer = LRUKReplacer::new(7, 2);
 
         // Scenario: add six elements to the replacer. We have [1,2,3,4,5]. Frame 6 is
         // non-evictable.
-        lru_replacer.record_access(1);
-        lru_replacer.record_access(2);
-        lru_replacer.record_access(3);
-        lru_replacer.record_access(4);
-        lru_replacer.record_access(5);
-        lru_replacer.record_access(6);
+        lru_replacer.record_access(1, AccessType::Unknown);
+        lru_replacer.record_access(2, AccessType::Unknown);
+        lru_replacer.record_access(3, AccessType::Unknown);
+        lru_replacer.record_access(4, AccessType::Unknown);
+        lru_replacer.record_access(5, AccessType::Unknown);
+        lru_replacer.record_access(6, AccessType::Unknown);
         lru_replacer.set_evictable(1, true);
         lru_replacer.set_evictable(2, true);
         lru_replacer.set_evictable(3, true);
@@ -240,7 +408,7 @@ mod tests {
         // Scenario: Insert access history for frame 1. Now frame 1 has two access
         // histories. All other frames have max backward k-dist. The order of
         // eviction is [2,3,4,5,1].
-        lru_replacer.record_access(1);
+        lru_replacer.record_access(1, AccessType::Unknown);
 
         // Scenario: Evict three pages from the replacer. Elements with max k-distance
         // should be popped first based on LRU.
@@ -254,10 +422,10 @@ mod tests {
 
         // Scenario: Now replacer has frames [5,1]. Insert new frames 3, 4, and update
         // access history for 5. We should end with [3,1,5,4]
-        lru_replacer.record_access(3);
-        lru_replacer.record_access(4);
-        lru_replacer.record_access(5);
-        lru_replacer.record_access(4);
+        lru_replacer.record_access(3, AccessType::Unknown);
+        lru_replacer.record_access(4, AccessType::Unknown);
+        lru_replacer.record_access(5, AccessType::Unknown);
+        lru_replacer.record_access(4, AccessType::Unknown);
         lru_replacer.set_evictable(3, true);
         lru_replacer.set_evictable(4, true);
         assert_eq!(4, lru_replacer.size());
@@ -283,8 +451,8 @@ mod tests {
         assert_eq!(1, lru_replacer.size());
 
         // Update access history for 1. Now we have [4,1]. Next victim is 4.
-        lru_replacer.record_access(1);
-        lru_replacer.record_access(1);
+        lru_replacer.record_access(1, AccessType::Unknown);
+        lru_replacer.record_access(1, AccessType::Unknown);
         lru_replacer.set_evictable(1, true);
         assert_eq!(2, lru_replacer.size());
         let value = lru_replacer.evict();
@@ -299,4 +467,264 @@ mod tests {
         assert_eq!(None, lru_replacer.evict());
         assert_eq!(0, lru_replacer.size());
     }
+
+    #[test]
+    pub fn test_scan_access() {
+        let lru_replacer = LRUKReplacer::new(7, 2);
+
+        // Scenario: frames 1 and 2 are looked up twice, frames 3 and 4 are
+        // scanned twice, which gives them the smallest backward k-distance.
+        lru_replacer.record_access(2, AccessType::Lookup);
+        lru_replacer.record_access(1, AccessType::Lookup);
+        lru_replacer.record_access(1, AccessType::Lookup);
+        lru_replacer.record_access(2, AccessType::Index);
+        for frame_id in 3..=4 {
+            lru_replacer.record_access(frame_id, AccessType::Scan);
+            lru_replacer.record_access(frame_id, AccessType::Scan);
+        }
+        // a scan over a frame with lookup history does not count
+        lru_replacer.record_access(1, AccessType::Scan);
+        lru_replacer.record_access(1, AccessType::Scan);
+        // frame 5 starts as a scan page but is looked up afterwards
+        lru_replacer.record_access(5, AccessType::Scan);
+        lru_replacer.record_access(5, AccessType::Lookup);
+        assert_eq!(5, lru_replacer.size());
+
+        // Scenario: scan-only frames are evicted first in LRU order, then the
+        // rest by backward k-distance.
+        assert_eq!(Some(3), lru_replacer.evict());
+        assert_eq!(Some(4), lru_replacer.evict());
+        assert_eq!(Some(5), lru_replacer.evict());
+        assert_eq!(Some(2), lru_replacer.evict());
+        assert_eq!(Some(1), lru_replacer.evict());
+        assert_eq!(None, lru_replacer.evict());
+    }
+
+    #[test]
+    #[should_panic(expected = "k must be at least 1")]
+    pub fn test_k_zero() {
+        LRUKReplacer::new(7, 0);
+    }
+
+    #[test]
+    pub fn test_distance_ties() {
+        let lru_replacer = LRUKReplacer::new(7, 3);
+
+        // Scenario: frames 1 to 4 have less than k accesses, so all of them
+        // have +inf backward k-distance. Accessing frame 1 again does not
+        // move it back, and neither does pinning and unpinning frame 2.
+        for frame_id in 1..=4 {
+            lru_replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        lru_replacer.record_access(1, AccessType::Unknown);
+        lru_replacer.set_evictable(2, false);
+        lru_replacer.set_evictable(2, true);
+        assert_eq!(4, lru_replacer.size());
+        assert_eq!(Some(1), lru_replacer.evict());
+        assert_eq!(Some(2), lru_replacer.evict());
+
+        // Scenario: frames 5 and 6 are accessed k times with the same backward
+        // k-distance, the one accessed first goes first. Both go after the
+        // frames with +inf distance.
+        for _ in 0..3 {
+            lru_replacer.record_access(6, AccessType::Unknown);
+            lru_replacer.record_access(5, AccessType::Unknown);
+        }
+        assert_eq!(Some(3), lru_replacer.evict());
+        assert_eq!(Some(4), lru_replacer.evict());
+        assert_eq!(Some(6), lru_replacer.evict());
+        assert_eq!(Some(5), lru_replacer.evict());
+        assert_eq!(None, lru_replacer.evict());
+        assert_eq!(0, lru_replacer.size());
+    }
+
+    /// Returns where the history buffer of each frame is, and its capacity.
+    fn history_buffers(lru_replacer: &LRUKReplacer) -> Vec<(FrameId, *const usize, usize)> {
+        let state = lru_replacer.state.lock().unwrap();
+        let mut buffers: Vec<_> = state
+            .node_store
+            .iter()
+            .map(|(&frame_id, node)| {
+                let timestamps = &node.history.timestamps;
+                (frame_id, timestamps.as_ptr(), timestamps.capacity())
+            })
+            .collect();
+        buffers.sort();
+        buffers
+    }
+
+    #[test]
+    pub fn test_access_reuses_history() {
+        let num_frames = 64;
+        let k = 10;
+        let lru_replacer = LRUKReplacer::new(num_frames, k);
+
+        // Scenario: fill the history of every frame but frame 0, which is
+        // only scanned. All frames stay evictable.
+        lru_replacer.record_access(0, AccessType::Scan);
+        for frame_id in 1..num_frames {
+            for _ in 0..k {
+                lru_replacer.record_access(frame_id, AccessType::Unknown);
+            }
+        }
+        let warm_up = 1 + (num_frames - 1) * k;
+        let buffers = history_buffers(&lru_replacer);
+        assert!(buffers.iter().all(|&(_, _, capacity)| capacity == k));
+
+        // Scenario: further accesses overwrite the oldest timestamps in the
+        // buffer every frame got on its first access, and never move it.
+        // Frame 0 forgets its scan history and starts over in the same
+        // buffer. This only covers the histories: moving an evictable frame
+        // in the eviction order may still allocate.
+        let accesses = 100000;
+        for i in 0..accesses {
+            lru_replacer.record_access(i % num_frames, AccessType::Lookup);
+        }
+        assert_eq!(buffers, history_buffers(&lru_replacer));
+
+        // Scenario: the history holds the last k accesses, oldest first.
+        let info = lru_replacer
+            .dump()
+            .into_iter()
+            .find(|info| info.frame_id == 1)
+            .unwrap();
+        let mut history: Vec<usize> = (0..accesses)
+            .filter(|i| i % num_frames == 1)
+            .map(|i| warm_up + i)
+            .collect();
+        let history = history.split_off(history.len() - k);
+        assert_eq!(history, info.history);
+        assert_eq!(Some((k - 1) * num_frames), info.backward_k_distance);
+
+        // Scenario: all frames have the same backward k-distance, so they are
+        // evicted in the order of the oldest of their last k accesses.
+        let first = accesses % num_frames;
+        let order: Vec<FrameId> = (first..num_frames).chain(0..first).collect();
+        let evicted: Vec<FrameId> = std::iter::from_fn(|| lru_replacer.evict()).collect();
+        assert_eq!(order, evicted);
+    }
+
+    #[test]
+    pub fn test_dump() {
+        let lru_replacer = LRUKReplacer::new(7, 2);
+
+        // Scenario: frame 1 is accessed twice, frames 2 and 3 once, frame 4
+        // only by a scan, and frame 5 is pinned.
+        lru_replacer.record_access(1, AccessType::Unknown);
+        lru_replacer.record_access(2, AccessType::Unknown);
+        lru_replacer.record_access(1, AccessType::Unknown);
+        lru_replacer.record_access(3, AccessType::Unknown);
+        lru_replacer.record_access(4, AccessType::Scan);
+        lru_replacer.record_access(5, AccessType::Unknown);
+        lru_replacer.set_evictable(5, false);
+
+        let dump = lru_replacer.dump();
+        assert_eq!(
+            FrameDebugInfo {
+                frame_id: 1,
+                is_evictable: true,
+                scan_only: false,
+                history: vec![0, 2],
+                backward_k_distance: Some(2),
+            },
+            dump[3]
+        );
+        assert_eq!(None, dump[0].backward_k_distance);
+        assert!(dump[0].scan_only);
+        assert!(!dump[4].is_evictable);
+        assert_eq!(
+            "frame evictable scan  distance history\n    \
+             4 true      true       inf [4]\n    \
+             2 true      false      inf [1]\n    \
+             3 true      false      inf [3]\n    \
+             1 true      false        2 [0, 2]\n    \
+             5 false     false      inf [5]\n",
+            lru_replacer.to_string()
+        );
+
+        // Scenario: the dump lists the evictable frames in the order they are
+        // evicted, the pinned one last.
+        let order: Vec<_> = dump.iter().map(|info| info.frame_id).collect();
+        assert_eq!(vec![4, 2, 3, 1, 5], order);
+        let mut evicted = Vec::new();
+        while let Some(frame_id) = lru_replacer.evict() {
+            evicted.push(frame_id);
+        }
+        lru_replacer.set_evictable(5, true);
+        evicted.push(lru_replacer.evict().unwrap());
+        assert_eq!(order, evicted);
+        assert!(lru_replacer.dump().is_empty());
+    }
+
+    #[test]
+    pub fn test_evict_order_many_frames() {
+        let num_frames = 50000;
+        let lru_replacer = LRUKReplacer::new(num_frames, 2);
+
+        // Scenario: every frame is accessed once, then the odd frames a second
+        // time in reverse order.
+        for frame_id in 0..num_frames {
+            lru_replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        for frame_id in (1..num_frames).rev().step_by(2) {
+            lru_replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        assert_eq!(num_frames, lru_replacer.size());
+
+        // Scenario: the even frames have an infinite backward k-distance and
+        // go first, oldest access first. The odd frames follow in the order of
+        // their first access, not their last.
+        let order: Vec<FrameId> = (0..num_frames)
+            .step_by(2)
+            .chain((1..num_frames).step_by(2))
+            .collect();
+        let evicted: Vec<FrameId> = std::iter::from_fn(|| lru_replacer.evict()).collect();
+        assert_eq!(order, evicted);
+        assert_eq!(0, lru_replacer.size());
+    }
+
+    #[test]
+    pub fn test_concurrent_access_and_evict() {
+        let threads = 8;
+        let num_frames = 8000;
+        let lru_replacer = LRUKReplacer::new(num_frames, 2);
+        let evicted = Mutex::new(Vec::new());
+
+        // Scenario: every thread brings in its own share of the frames while
+        // all threads keep evicting.
+        thread::scope(|s| {
+            for t in 0..threads {
+                let lru_replacer = &lru_replacer;
+                let evicted = &evicted;
+                s.spawn(move || {
+                    let mut mine = Vec::new();
+                    for frame_id in (t..num_frames).step_by(threads) {
+                        // a second access could bring the frame back after
+                        // another thread evicted it, so there is only one
+                        lru_replacer.record_access(frame_id, AccessType::Unknown);
+                        if frame_id % 3 == 0 {
+                            lru_replacer.set_evictable(frame_id, false);
+                            lru_replacer.set_evictable(frame_id, true);
+                        }
+                        if let Some(victim) = lru_replacer.evict() {
+                            mine.push(victim);
+                        }
+                        assert!(lru_replacer.size() <= num_frames);
+                    }
+                    evicted.lock().unwrap().extend(mine);
+                });
+            }
+        });
+
+        // Scenario: what is left can be evicted, and no frame was evicted
+        // twice.
+        let mut evicted = evicted.into_inner().unwrap();
+        assert_eq!(num_frames - evicted.len(), lru_replacer.size());
+        while let Some(victim) = lru_replacer.evict() {
+            evicted.push(victim);
+        }
+        assert_eq!(0, lru_replacer.size());
+        evicted.sort();
+        assert_eq!((0..num_frames).collect::<Vec<_>>(), evicted);
+    }
 }
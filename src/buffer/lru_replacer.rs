@@ -0,0 +1,311 @@
+use std::sync::Mutex;
+
+use super::replacer::{AccessType, Replacer};
+use crate::common::config::FrameId;
+
+#[derive(Debug)]
+struct LruFrame {
+    /// The frame accessed just before this one.
+    prev: Option<FrameId>,
+    /// The frame accessed just after this one.
+    next: Option<FrameId>,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+struct LruState {
+    /// One slot per frame, none if the frame is not tracked. The tracked
+    /// frames are linked in the order they were last accessed.
+    frames: Vec<Option<LruFrame>>,
+    /// The least recently used frame.
+    head: Option<FrameId>,
+    /// The most recently used frame.
+    tail: Option<FrameId>,
+    /// Number of evictable frames.
+    size: usize,
+}
+
+impl LruState {
+    fn frame_mut(&mut self, frame_id: FrameId) -> &mut LruFrame {
+        self.frames[frame_id].as_mut().unwrap()
+    }
+
+    /// Take a tracked frame out of the list, its slot stays.
+    fn unlink(&mut self, frame_id: FrameId) {
+        let frame = self.frame_mut(frame_id);
+        let (prev, next) = (frame.prev.take(), frame.next.take());
+        match prev {
+            Some(prev) => self.frame_mut(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.frame_mut(next).prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    /// Put a tracked frame that is not in the list at its most recently used
+    /// end.
+    fn push_back(&mut self, frame_id: FrameId) {
+        let tail = self.tail;
+        self.frame_mut(frame_id).prev = tail;
+        match tail {
+            Some(tail) => self.frame_mut(tail).next = Some(frame_id),
+            None => self.head = Some(frame_id),
+        }
+        self.tail = Some(frame_id);
+    }
+}
+
+/// LRUReplacer implements the least recently used replacement policy.
+///
+/// Frames are kept in a doubly linked list in the order of their last access,
+/// so recording an access is O(1). The evictable frame that was accessed
+/// longest ago is the victim. Non-evictable frames stay in the list and are
+/// skipped by evict; in the buffer pool those are pinned pages, which have
+/// usually been accessed recently.
+///
+/// This is the same order as LRU-K with k = 1, without keeping a history per
+/// frame. All access types count the same, scans included.
+#[derive(Debug)]
+pub struct LRUReplacer {
+    state: Mutex<LruState>,
+}
+
+impl LRUReplacer {
+    /// @brief a new LRUReplacer.
+    /// @param num_frames the maximum number of frames the LRUReplacer will be
+    /// required to store
+    pub fn new(num_frames: usize) -> Self {
+        Self {
+            state: Mutex::new(LruState {
+                frames: (0..num_frames).map(|_| None).collect(),
+                head: None,
+                tail: None,
+                size: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for LRUReplacer {
+    /// @brief Evict the evictable frame that was accessed longest ago.
+    ///
+    /// @return the evicted frame, or none if no frames can be evicted.
+    fn evict(&self) -> Option<FrameId> {
+        let mut state = self.state.lock().unwrap();
+        if state.size == 0 {
+            return None;
+        }
+        let mut cursor = state.head;
+        while let Some(frame_id) = cursor {
+            let frame = state.frame_mut(frame_id);
+            if frame.is_evictable {
+                state.unlink(frame_id);
+                state.frames[frame_id] = None;
+                state.size -= 1;
+                return Some(frame_id);
+            }
+            cursor = frame.next;
+        }
+        unreachable!("an evictable frame is not in the list");
+    }
+
+    /// @brief Record an access to the given frame, making it the most recently
+    /// used one. A frame seen for the first time starts out evictable.
+    ///
+    /// If frame id is invalid (ie. larger than the number of frames), panic.
+    ///
+    /// @param frame_id id of frame that received a new access.
+    /// @param _access_type type of access that was received.
+    fn record_access(&self, frame_id: FrameId, _access_type: AccessType) {
+        let mut state = self.state.lock().unwrap();
+        match state.frames.get(frame_id) {
+            Some(Some(_)) => state.unlink(frame_id),
+            Some(None) => {
+                state.frames[frame_id] = Some(LruFrame {
+                    prev: None,
+                    next: None,
+                    is_evictable: true,
+                });
+                state.size += 1;
+            }
+            None => panic!("Invalid frame id"),
+        }
+        state.push_back(frame_id);
+    }
+
+    /// @brief Toggle whether a frame is evictable or non-evictable, adjusting
+    /// the replacer's size accordingly. The frame keeps its place in the list.
+    ///
+    /// If frame id is invalid, panic. A frame that is not tracked is left
+    /// alone.
+    ///
+    /// @param frame_id id of frame whose 'evictable' status will be modified
+    /// @param set_evictable whether the given frame is evictable or not
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) {
+        let mut state = self.state.lock().unwrap();
+        let frame = match state.frames.get_mut(frame_id) {
+            Some(Some(frame)) => frame,
+            Some(None) => return,
+            None => panic!("Invalid frame id"),
+        };
+        if frame.is_evictable == set_evictable {
+            return;
+        }
+        frame.is_evictable = set_evictable;
+        if set_evictable {
+            state.size += 1;
+        } else {
+            state.size -= 1;
+        }
+    }
+
+    /// @brief Remove an evictable frame from the replacer, wherever it is in
+    /// the list.
+    ///
+    /// If Remove is called on a non-evictable frame, panic. If frame id is
+    /// invalid, panic.
+    ///
+    /// If specified frame is not found, directly return from this function.
+    ///
+    /// @param frame_id id of frame to be removed
+    fn remove(&self, frame_id: FrameId) {
+        let mut state = self.state.lock().unwrap();
+        match state.frames.get(frame_id) {
+            Some(Some(frame)) if !frame.is_evictable => panic!("Frame is not evictable"),
+            Some(Some(_)) => {
+                state.unlink(frame_id);
+                state.frames[frame_id] = None;
+                state.size -= 1;
+            }
+            Some(None) => {}
+            None => panic!("Invalid frame id"),
+        }
+    }
+
+    /// @brief Return replacer's size, which tracks the number of evictable
+    /// frames.
+    fn size(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::LRUReplacer;
+    use crate::buffer::lru_k_replacer::LRUKReplacer;
+    use crate::buffer::replacer::{AccessType, Replacer};
+
+    #[test]
+    fn test_lru_sample() {
+        let lru_replacer = LRUReplacer::new(7);
+
+        // Scenario: add six elements to the replacer. We have [1,2,3,4,5]. Frame 6 is
+        // non-evictable.
+        for frame_id in 1..=6 {
+            lru_replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        lru_replacer.set_evictable(6, false);
+        assert_eq!(5, lru_replacer.size());
+
+        // Scenario: access frame 1 again, which makes it the most recently
+        // used one. The order of eviction is [2,3,4,5,1].
+        lru_replacer.record_access(1, AccessType::Unknown);
+        assert_eq!(Some(2), lru_replacer.evict());
+        assert_eq!(Some(3), lru_replacer.evict());
+        assert_eq!(Some(4), lru_replacer.evict());
+        assert_eq!(2, lru_replacer.size());
+
+        // Scenario: now the replacer has [5,1]. Insert new frames 3, 4, and
+        // access 5 and 4 again. We should end with [1,3,5,4].
+        lru_replacer.record_access(3, AccessType::Unknown);
+        lru_replacer.record_access(4, AccessType::Unknown);
+        lru_replacer.record_access(5, AccessType::Unknown);
+        lru_replacer.record_access(4, AccessType::Unknown);
+        assert_eq!(4, lru_replacer.size());
+        assert_eq!(Some(1), lru_replacer.evict());
+        assert_eq!(3, lru_replacer.size());
+
+        // Scenario: set 6 evictable. It was accessed before all the others, so
+        // it goes next.
+        lru_replacer.set_evictable(6, true);
+        assert_eq!(4, lru_replacer.size());
+        assert_eq!(Some(6), lru_replacer.evict());
+        assert_eq!(3, lru_replacer.size());
+
+        // Scenario: now we have [3,5,4]. A non-evictable frame is skipped.
+        lru_replacer.set_evictable(3, false);
+        assert_eq!(2, lru_replacer.size());
+        assert_eq!(Some(5), lru_replacer.evict());
+        assert_eq!(1, lru_replacer.size());
+
+        // Scenario: access 3 again and make it evictable. We have [4,3].
+        lru_replacer.record_access(3, AccessType::Unknown);
+        lru_replacer.record_access(3, AccessType::Unknown);
+        lru_replacer.set_evictable(3, true);
+        assert_eq!(2, lru_replacer.size());
+        assert_eq!(Some(4), lru_replacer.evict());
+        assert_eq!(Some(3), lru_replacer.evict());
+        assert_eq!(0, lru_replacer.size());
+
+        // This operation should not modify size
+        assert_eq!(None, lru_replacer.evict());
+        assert_eq!(0, lru_replacer.size());
+    }
+
+    #[test]
+    fn test_lru_matches_lru_k_with_k_1() {
+        let num_frames = 16;
+        let access_types = [AccessType::Unknown, AccessType::Lookup, AccessType::Index];
+
+        // Scenario: random traces of accesses, pins, unpins, removals and
+        // evictions always pick the same victims as LRU-K with k = 1.
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let lru_replacer = LRUReplacer::new(num_frames);
+            let lru_k_replacer = LRUKReplacer::new(num_frames, 1);
+            // the tracked frames and whether they are evictable
+            let mut tracked = HashMap::new();
+            for _ in 0..2000 {
+                let frame_id = rng.gen_range(0..num_frames);
+                match rng.gen_range(0..10) {
+                    0..=4 => {
+                        let access_type = access_types[rng.gen_range(0..access_types.len())];
+                        lru_replacer.record_access(frame_id, access_type);
+                        lru_k_replacer.record_access(frame_id, access_type);
+                        tracked.entry(frame_id).or_insert(true);
+                    }
+                    5..=6 => {
+                        let evictable = rng.gen_bool(0.5);
+                        lru_replacer.set_evictable(frame_id, evictable);
+                        lru_k_replacer.set_evictable(frame_id, evictable);
+                        if let Some(is_evictable) = tracked.get_mut(&frame_id) {
+                            *is_evictable = evictable;
+                        }
+                    }
+                    7 => {
+                        if tracked.get(&frame_id) == Some(&true) {
+                            lru_replacer.remove(frame_id);
+                            lru_k_replacer.remove(frame_id);
+                            tracked.remove(&frame_id);
+                        }
+                    }
+                    _ => {
+                        let victim = lru_replacer.evict();
+                        assert_eq!(lru_k_replacer.evict(), victim);
+                        if let Some(victim) = victim {
+                            tracked.remove(&victim);
+                        }
+                    }
+                }
+                assert_eq!(lru_k_replacer.size(), lru_replacer.size());
+            }
+        }
+    }
+}
@@ -1,2 +1,7 @@
 pub mod buffer_pool_manager;
+pub mod clock_replacer;
 pub mod lru_k_replacer;
+pub mod lru_replacer;
+pub mod parallel_buffer_pool_manager;
+pub mod replacer;
+pub mod two_q_replacer;
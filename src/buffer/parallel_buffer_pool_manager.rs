@@ -0,0 +1,344 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::buffer_pool_manager::{BufferError, BufferPoolManager, BufferPoolStats, DeleteOutcome};
+use super::replacer::{AccessType, ReplacerType};
+use crate::common::config::PageId;
+use crate::storage::disk::disk_manager::DiskManagerTrait;
+use crate::storage::disk::disk_scheduler::DiskScheduler;
+use crate::storage::page::page::Page;
+
+/// ParallelBufferPoolManager shards pages across several BufferPoolManager
+/// instances, each with its own page table, replacer and free list, so that
+/// threads working on different pages don't serialize on a single latch.
+///
+/// A page always lives in instance `page_id % num_instances`. All instances
+/// share one disk scheduler.
+pub struct ParallelBufferPoolManager {
+    /// The buffer pool manager instances.
+    instances: Vec<BufferPoolManager>,
+    /// The instance new_page tries first on its next call.
+    start_index: AtomicUsize,
+}
+
+impl ParallelBufferPoolManager {
+    /// @brief Creates a new ParallelBufferPoolManager.
+    /// @param num_instances the number of individual BufferPoolManager
+    /// instances
+    /// @param pool_size the pool size of each BufferPoolManager instance
+    /// @param disk_manager the disk manager
+    /// @param replacer_type the replacement policy of every instance
+    pub fn new(
+        num_instances: usize,
+        pool_size: usize,
+        disk_manager: Box<dyn DiskManagerTrait>,
+        replacer_type: ReplacerType,
+    ) -> Self {
+        let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager));
+        Self {
+            instances: (0..num_instances)
+                .map(|i| {
+                    BufferPoolManager::new_instance(
+                        pool_size,
+                        num_instances,
+                        i,
+                        disk_scheduler.clone(),
+                        replacer_type,
+                    )
+                })
+                .collect(),
+            start_index: AtomicUsize::new(0),
+        }
+    }
+
+    /// @brief Return the total size of all the buffer pools.
+    pub fn get_pool_size(&self) -> usize {
+        self.instances.iter().map(|bpm| bpm.get_pool_size()).sum()
+    }
+
+    /// @brief Return the usage statistics summed over all instances.
+    pub fn get_stats(&self) -> BufferPoolStats {
+        let mut stats = BufferPoolStats::default();
+        for bpm in self.instances.iter() {
+            stats += bpm.get_stats();
+        }
+        stats
+    }
+
+    /// @brief Reset the usage statistics of all instances to zero.
+    pub fn reset_stats(&self) {
+        for bpm in self.instances.iter() {
+            bpm.reset_stats();
+        }
+    }
+
+    /// @brief Return the BufferPoolManager instance responsible for page_id.
+    pub fn get_buffer_pool_manager(&self, page_id: PageId) -> &BufferPoolManager {
+        &self.instances[page_id as usize % self.instances.len()]
+    }
+
+    /// @brief Create a new page in one of the buffer pools.
+    ///
+    /// The instances are tried round robin, starting from a different one on
+    /// every call, until one of them has a frame to spare. Any other error
+    /// is returned right away.
+    ///
+    /// @return NoFreeFrame if no instance has a frame to spare, otherwise the
+    /// new page
+    pub fn new_page(&self) -> Result<Page, BufferError> {
+        let num_instances = self.instances.len();
+        let start = self.start_index.fetch_add(1, Ordering::Relaxed) % num_instances;
+        for i in 0..num_instances {
+            match self.instances[(start + i) % num_instances].new_page() {
+                Err(BufferError::NoFreeFrame) => continue,
+                res => return res,
+            }
+        }
+        Err(BufferError::NoFreeFrame)
+    }
+
+    /// @brief Fetch the requested page from the responsible buffer pool.
+    /// @param page_id id of page to be fetched
+    /// @param access_type type of access to the page
+    /// @return the requested page, or the error of the responsible instance
+    pub fn fetch_page(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<Page, BufferError> {
+        self.get_buffer_pool_manager(page_id)
+            .fetch_page(page_id, access_type)
+    }
+
+    /// @brief Unpin the target page from the responsible buffer pool.
+    /// @param page_id id of page to be unpinned
+    /// @param is_dirty true if the page should be marked as dirty, false
+    /// otherwise
+    /// @return PageNotFound if the page is not in the page table,
+    /// PageNotPinned if its pin count is <= 0 before this call
+    pub fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> Result<(), BufferError> {
+        self.get_buffer_pool_manager(page_id)
+            .unpin_page(page_id, is_dirty)
+    }
+
+    /// @brief Flush the target page to disk.
+    /// @param page_id id of page to be flushed
+    /// @return PageNotFound if the page could not be found in the page table,
+    /// Io if the write failed
+    pub fn flush_page(&self, page_id: PageId) -> Result<(), BufferError> {
+        self.get_buffer_pool_manager(page_id).flush_page(page_id)
+    }
+
+    /// @brief Delete a page from the responsible buffer pool, throwing away
+    /// changes of a dirty page.
+    /// @param page_id id of page to be deleted
+    /// @return PagePinned if the page exists but could not be deleted, Io if
+    /// the page id could not be deallocated on disk, otherwise whether the
+    /// page was resident and whether dirty changes were discarded
+    pub fn delete_page(&self, page_id: PageId) -> Result<DeleteOutcome, BufferError> {
+        self.get_buffer_pool_manager(page_id).delete_page(page_id)
+    }
+
+    /// @brief Delete a page from the responsible buffer pool, writing a
+    /// dirty page back first.
+    /// @param page_id id of page to be deleted
+    /// @return PagePinned if the page exists but could not be deleted, Io if
+    /// the write-back or the deallocation failed
+    pub fn delete_page_flush(&self, page_id: PageId) -> Result<DeleteOutcome, BufferError> {
+        self.get_buffer_pool_manager(page_id)
+            .delete_page_flush(page_id)
+    }
+
+    /// @brief Make every page written back so far durable. All instances
+    /// share the disk, so one sync covers them all.
+    /// @return Io if the sync failed
+    pub fn sync(&self) -> Result<(), BufferError> {
+        self.instances[0].sync()
+    }
+
+    /// @brief Flush all the pages in all the buffer pools to disk. Stops at
+    /// the first write that fails.
+    pub fn flush_all_pages(&mut self) -> Result<(), BufferError> {
+        for bpm in self.instances.iter_mut() {
+            bpm.flush_all_pages()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::storage::disk::disk_manager::DiskManager;
+
+    #[test]
+    fn test_parallel_buffer_pool_manager_sample() {
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let num_instances = 5;
+        let pool_size = 2;
+
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let mut bpm = ParallelBufferPoolManager::new(
+            num_instances,
+            pool_size,
+            disk_manager,
+            ReplacerType::LruK(2),
+        );
+        assert_eq!(num_instances * pool_size, bpm.get_pool_size());
+
+        // Scenario: new pages are spread round robin, so every instance is
+        // filled before any instance runs out of frames.
+        let mut pages = Vec::new();
+        for i in 0..num_instances * pool_size {
+            let page = bpm.new_page().unwrap();
+            let page_id = page.get_page_id().unwrap();
+            page.body_mut()[0] = i as u8 + 1;
+            pages.push(page_id);
+        }
+        assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+        let mut sorted = pages.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(num_instances * pool_size, sorted.len());
+
+        // Scenario: each page is unpinned through the instance it was routed
+        // to, after which new pages evict all of them.
+        for &page_id in pages.iter() {
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+            assert!(matches!(
+                bpm.unpin_page(page_id, true),
+                Err(BufferError::PageNotPinned)
+            ));
+        }
+        let mut new_pages = Vec::new();
+        for _ in 0..num_instances * pool_size {
+            new_pages.push(bpm.new_page().unwrap().get_page_id().unwrap());
+        }
+        assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+        for &page_id in new_pages.iter() {
+            assert!(bpm.flush_page(page_id).is_ok());
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        // Scenario: the data written before survived eviction.
+        for (i, &page_id) in pages.iter().enumerate() {
+            let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+            assert_eq!(i as u8 + 1, page.body()[0]);
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        let stats = bpm.get_stats();
+        assert_eq!(20, stats.pages_created);
+        assert_eq!(20, stats.pages_evicted);
+        assert_eq!(10, stats.dirty_writebacks);
+        assert_eq!(10, stats.cache_misses);
+
+        // Scenario: resetting the statistics clears them in every instance.
+        bpm.reset_stats();
+        assert_eq!(BufferPoolStats::default(), bpm.get_stats());
+        bpm.fetch_page(pages[1], AccessType::Unknown).unwrap();
+        assert!(bpm.unpin_page(pages[1], false).is_ok());
+        assert_eq!(1, bpm.get_stats().cache_hits + bpm.get_stats().cache_misses);
+
+        // Scenario: a pinned page cannot be deleted.
+        bpm.fetch_page(pages[0], AccessType::Unknown).unwrap();
+        assert!(matches!(
+            bpm.delete_page(pages[0]),
+            Err(BufferError::PagePinned)
+        ));
+        assert!(bpm.unpin_page(pages[0], true).is_ok());
+        assert_eq!(
+            DeleteOutcome::Flushed,
+            bpm.delete_page_flush(pages[0]).unwrap()
+        );
+        let page = bpm.fetch_page(pages[0], AccessType::Unknown).unwrap();
+        assert_eq!(1, page.body()[0]);
+        assert!(bpm.unpin_page(pages[0], false).is_ok());
+
+        // Scenario: flushing all pages writes back the dirty pages of every
+        // instance, pinned or not.
+        let dirty = &pages[1..num_instances + 1];
+        for &page_id in dirty {
+            bpm.fetch_page(page_id, AccessType::Unknown)
+                .unwrap()
+                .body_mut()[1] = 1;
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        }
+        // the first one stays pinned through the flush
+        bpm.fetch_page(dirty[0], AccessType::Unknown).unwrap();
+        let disk_manager = bpm.get_buffer_pool_manager(0).get_disk_manager();
+        let num_writes = disk_manager.get_num_writes();
+        assert!(bpm.flush_all_pages().is_ok());
+        let disk_manager = bpm.get_buffer_pool_manager(0).get_disk_manager();
+        assert_eq!(
+            num_writes + num_instances as i32,
+            disk_manager.get_num_writes()
+        );
+        for &page_id in dirty {
+            let bpm = bpm.get_buffer_pool_manager(page_id);
+            assert!(!bpm.inspect_page(page_id).unwrap().is_dirty);
+        }
+
+        // Scenario: one sync makes the writes of all instances durable, as
+        // they share the disk.
+        let num_flushes = disk_manager.get_num_flushes();
+        assert!(bpm.sync().is_ok());
+        assert_eq!(num_flushes + 1, disk_manager.get_num_flushes());
+        assert!(bpm.unpin_page(dirty[0], false).is_ok());
+    }
+
+    #[test]
+    fn test_parallel_buffer_pool_manager_concurrent_fetch() {
+        let threads = 8;
+        let fetches = 1000;
+
+        let dir = TempDir::new("test").unwrap();
+        let db_name = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_name.to_str().unwrap()).unwrap());
+        let bpm = ParallelBufferPoolManager::new(threads, 1, disk_manager, ReplacerType::LruK(2));
+
+        // Scenario: with one instance per thread, the threads' pages are all
+        // routed to different instances, so threads fetching their own page
+        // never share a page table, replacer or frame latch.
+        let page_ids: Vec<PageId> = (0..threads)
+            .map(|_| {
+                let page = bpm.new_page().unwrap();
+                let page_id = page.get_page_id().unwrap();
+                bpm.unpin_page(page_id, false).unwrap();
+                page_id
+            })
+            .collect();
+        let mut owners: Vec<*const BufferPoolManager> = page_ids
+            .iter()
+            .map(|&page_id| bpm.get_buffer_pool_manager(page_id) as *const _)
+            .collect();
+        owners.sort();
+        owners.dedup();
+        assert_eq!(threads, owners.len());
+
+        thread::scope(|s| {
+            for &page_id in page_ids.iter() {
+                let bpm = &bpm;
+                s.spawn(move || {
+                    for _ in 0..fetches {
+                        let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+                        assert_eq!(Some(page_id), page.get_page_id());
+                        assert!(bpm.unpin_page(page_id, false).is_ok());
+                    }
+                });
+            }
+        });
+
+        // Scenario: every instance served the fetches of exactly one thread.
+        for &page_id in page_ids.iter() {
+            let stats = bpm.get_buffer_pool_manager(page_id).get_stats();
+            assert_eq!(fetches, stats.cache_hits);
+            assert_eq!(0, stats.cache_misses);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+
+use super::clock_replacer::ClockReplacer;
+use super::lru_k_replacer::LRUKReplacer;
+use super::lru_replacer::LRUReplacer;
+use super::two_q_replacer::TwoQReplacer;
+use crate::common::config::{FrameId, PageId, LRUK_REPLACER_K};
+
+/// How a page is being accessed, passed down from the buffer pool to the
+/// replacer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessType {
+    #[default]
+    Unknown,
+    Lookup,
+    Scan,
+    Index,
+}
+
+/// What a replacer knows about one of its frames, see Replacer::dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameDebugInfo {
+    pub frame_id: FrameId,
+    pub is_evictable: bool,
+    /// Whether the frame has only been accessed by scans.
+    pub scan_only: bool,
+    /// Timestamps of the accesses the policy remembers, oldest first.
+    pub history: Vec<usize>,
+    /// The backward k-distance, none if it is +inf.
+    pub backward_k_distance: Option<usize>,
+}
+
+impl fmt::Display for FrameDebugInfo {
+    /// Formats the columns after the frame id of a dump table.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let distance = match self.backward_k_distance {
+            Some(distance) => distance.to_string(),
+            None => "inf".to_string(),
+        };
+        write!(
+            f,
+            "{:<9} {:<5} {:>8} {:?}",
+            self.is_evictable, self.scan_only, distance, self.history
+        )
+    }
+}
+
+/// Replacer tracks frame usage for the buffer pool and picks the frame to
+/// evict when the pool is full.
+///
+/// Only frames that are marked as evictable are candidates for eviction. A
+/// frame the replacer sees for the first time in record_access starts out
+/// evictable.
+pub trait Replacer: Send + Sync {
+    /// @brief Evict a frame according to the replacement policy and stop
+    /// tracking it.
+    ///
+    /// @return the evicted frame, or none if no frames can be evicted.
+    fn evict(&self) -> Option<FrameId>;
+
+    /// @brief Record an access to the given frame, starting to track it if
+    /// it has not been seen before.
+    ///
+    /// @param frame_id id of frame that received a new access.
+    /// @param access_type type of access that was received.
+    fn record_access(&self, frame_id: FrameId, access_type: AccessType);
+
+    /// @brief Toggle whether a frame is evictable or non-evictable. Panics if
+    /// the frame id is out of range, does nothing if the frame is not
+    /// tracked.
+    ///
+    /// @param frame_id id of frame whose 'evictable' status will be modified
+    /// @param set_evictable whether the given frame is evictable or not
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool);
+
+    /// @brief Stop tracking an evictable frame, no matter what the policy
+    /// would choose. Panics if the frame id is out of range or the frame is
+    /// not evictable, does nothing if it is not tracked.
+    ///
+    /// @param frame_id id of frame to be removed
+    fn remove(&self, frame_id: FrameId);
+
+    /// @brief Return the number of evictable frames.
+    fn size(&self) -> usize;
+
+    /// @brief Tell the replacer which page a frame is about to hold. The
+    /// buffer pool calls it before the first record_access of the page.
+    /// Policies that remember pages after evicting them need it, all others
+    /// can ignore it.
+    ///
+    /// @param frame_id id of frame the page is put into
+    /// @param page_id id of the page
+    fn bind_page(&self, _frame_id: FrameId, _page_id: PageId) {}
+
+    /// @brief Describe every tracked frame, for debugging eviction decisions.
+    /// Evictable frames come first, in the order they would be evicted, then
+    /// the others in the order they would be evicted once evictable.
+    /// Policies that cannot explain their decisions return nothing.
+    fn dump(&self) -> Vec<FrameDebugInfo> {
+        Vec::new()
+    }
+}
+
+/// The replacement policies a BufferPoolManager can be created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplacerType {
+    /// LRU-K with the given lookback constant k.
+    LruK(usize),
+    /// Plain LRU, which evicts in the same order as LRU-K with k = 1.
+    Lru,
+    /// Clock, a.k.a. second chance.
+    Clock,
+    /// 2Q, with the share of frames for first-time accesses and the number
+    /// of remembered evicted pages, both in percent of the pool size.
+    TwoQ {
+        a1in_percent: usize,
+        a1out_percent: usize,
+    },
+}
+
+impl Default for ReplacerType {
+    /// LRU-K with the default lookback constant.
+    fn default() -> Self {
+        ReplacerType::LruK(LRUK_REPLACER_K)
+    }
+}
+
+impl ReplacerType {
+    /// @brief Create a replacer of this type for num_frames frames.
+    pub fn create(self, num_frames: usize) -> Box<dyn Replacer> {
+        match self {
+            ReplacerType::LruK(k) => Box::new(LRUKReplacer::new(num_frames, k)),
+            ReplacerType::Lru => Box::new(LRUReplacer::new(num_frames)),
+            ReplacerType::Clock => Box::new(ClockReplacer::new(num_frames)),
+            ReplacerType::TwoQ {
+                a1in_percent,
+                a1out_percent,
+            } => Box::new(TwoQReplacer::new(num_frames, a1in_percent, a1out_percent)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    /// Checks the behavior every policy has to agree on.
+    fn check_replacer_contract(replacer_type: ReplacerType) {
+        let replacer = replacer_type.create(7);
+
+        // Scenario: add six frames, frame 6 is non-evictable.
+        for frame_id in 1..=6 {
+            replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        assert_eq!(6, replacer.size());
+        replacer.set_evictable(6, false);
+        replacer.set_evictable(6, false);
+        assert_eq!(5, replacer.size());
+
+        // Scenario: removed and evicted frames are no longer tracked.
+        replacer.remove(1);
+        replacer.remove(1);
+        assert_eq!(4, replacer.size());
+        let mut evicted: Vec<FrameId> = (0..4).map(|_| replacer.evict().unwrap()).collect();
+        evicted.sort();
+        assert_eq!(vec![2, 3, 4, 5], evicted);
+        assert_eq!(0, replacer.size());
+        assert_eq!(None, replacer.evict());
+
+        // Scenario: the pinned frame is evicted once it is evictable again.
+        replacer.set_evictable(6, true);
+        assert_eq!(Some(6), replacer.evict());
+        assert_eq!(None, replacer.evict());
+
+        // Scenario: a frame that is not tracked is not affected by
+        // set_evictable, and starts out evictable once it is accessed.
+        replacer.set_evictable(0, false);
+        replacer.set_evictable(0, true);
+        assert_eq!(0, replacer.size());
+        assert_eq!(None, replacer.evict());
+        replacer.record_access(0, AccessType::Unknown);
+        assert_eq!(1, replacer.size());
+        assert_eq!(Some(0), replacer.evict());
+
+        // Scenario: every frame can be tracked at once, even if none of them
+        // is evictable.
+        for frame_id in 0..7 {
+            replacer.record_access(frame_id, AccessType::Unknown);
+            replacer.set_evictable(frame_id, false);
+        }
+        assert_eq!(0, replacer.size());
+        for frame_id in 0..7 {
+            replacer.record_access(frame_id, AccessType::Lookup);
+        }
+        assert_eq!(0, replacer.size());
+        assert_eq!(None, replacer.evict());
+        replacer.set_evictable(3, true);
+        assert_eq!(1, replacer.size());
+        assert_eq!(Some(3), replacer.evict());
+    }
+
+    #[test]
+    fn test_replacer_contract() {
+        check_replacer_contract(ReplacerType::LruK(2));
+        check_replacer_contract(ReplacerType::Lru);
+        check_replacer_contract(ReplacerType::Clock);
+        check_replacer_contract(ReplacerType::TwoQ {
+            a1in_percent: 25,
+            a1out_percent: 50,
+        });
+    }
+
+    #[test]
+    fn test_replacer_frame_id_bounds() {
+        for replacer_type in [
+            ReplacerType::LruK(2),
+            ReplacerType::Lru,
+            ReplacerType::Clock,
+            ReplacerType::TwoQ {
+                a1in_percent: 25,
+                a1out_percent: 50,
+            },
+        ] {
+            let replacer = replacer_type.create(7);
+
+            // Scenario: the last frame id is fine.
+            replacer.record_access(6, AccessType::Unknown);
+            replacer.set_evictable(6, false);
+            replacer.set_evictable(6, true);
+            replacer.remove(6);
+            replacer.record_access(6, AccessType::Unknown);
+            assert_eq!(Some(6), replacer.evict());
+
+            // Scenario: the one after it is rejected by every method. A panic
+            // poisons the replacer, so each call gets a fresh one.
+            let calls: [fn(&dyn Replacer); 3] = [
+                |replacer| replacer.record_access(7, AccessType::Unknown),
+                |replacer| replacer.set_evictable(7, true),
+                |replacer| replacer.remove(7),
+            ];
+            for call in calls {
+                let replacer = replacer_type.create(7);
+                let res = panic::catch_unwind(AssertUnwindSafe(|| call(replacer.as_ref())));
+                let payload = res.unwrap_err();
+                let message = payload
+                    .downcast_ref::<String>()
+                    .map(String::as_str)
+                    .or_else(|| payload.downcast_ref::<&str>().copied())
+                    .unwrap();
+                assert!(
+                    message.starts_with("Invalid frame id"),
+                    "{:?}: {}",
+                    replacer_type,
+                    message
+                );
+            }
+        }
+    }
+}
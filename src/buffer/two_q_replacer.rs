@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::replacer::{AccessType, Replacer};
+use crate::common::config::{FrameId, PageId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Queue {
+    /// First-time accesses, in FIFO order.
+    A1In,
+    /// Frames accessed again, in LRU order.
+    Am,
+}
+
+#[derive(Debug)]
+struct TwoQFrame {
+    queue: Queue,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+struct TwoQState {
+    /// One slot per frame, none if the frame is not tracked.
+    frames: Vec<Option<TwoQFrame>>,
+    /// The page each frame holds, as told by bind_page.
+    page_ids: Vec<Option<PageId>>,
+    /// Frames seen once, oldest in front.
+    a1in: VecDeque<FrameId>,
+    /// Frames seen more than once, least recently used in front.
+    am: VecDeque<FrameId>,
+    /// Ghost queue of pages recently evicted from A1in, oldest in front.
+    a1out: VecDeque<PageId>,
+    /// Number of evictable frames.
+    size: usize,
+}
+
+impl TwoQState {
+    fn queue_mut(&mut self, queue: Queue) -> &mut VecDeque<FrameId> {
+        match queue {
+            Queue::A1In => &mut self.a1in,
+            Queue::Am => &mut self.am,
+        }
+    }
+
+    /// Removes the frame from the queue it sits in.
+    fn unlink(&mut self, frame_id: FrameId, queue: Queue) {
+        let queue = self.queue_mut(queue);
+        let pos = queue.iter().position(|&id| id == frame_id).unwrap();
+        queue.remove(pos);
+    }
+
+    /// Stops tracking the first evictable frame of the queue and returns it.
+    fn take_victim(&mut self, queue: Queue) -> Option<FrameId> {
+        let frames = &self.frames;
+        let pos = match queue {
+            Queue::A1In => &self.a1in,
+            Queue::Am => &self.am,
+        }
+        .iter()
+        .position(|&id| frames[id].as_ref().unwrap().is_evictable)?;
+        let frame_id = self.queue_mut(queue).remove(pos).unwrap();
+        self.frames[frame_id] = None;
+        self.size -= 1;
+        Some(frame_id)
+    }
+}
+
+/// TwoQReplacer implements the 2Q replacement policy.
+///
+/// A frame accessed for the first time goes to the A1in queue, which is
+/// evicted in FIFO order. A second access promotes it to the Am queue, which
+/// is evicted in LRU order. As long as A1in holds no more than its share of
+/// the frames, victims are taken from Am, so pages touched only once cannot
+/// push out pages that are used again and again.
+///
+/// Pages evicted from A1in are remembered in the A1out ghost queue. A page
+/// that comes back while it is still remembered was evicted too early, and
+/// goes straight to Am. Ghosts are tracked by page id, which the buffer pool
+/// passes in through bind_page.
+///
+/// Scan accesses never promote a frame, and do not refresh frames in Am.
+#[derive(Debug)]
+pub struct TwoQReplacer {
+    state: Mutex<TwoQState>,
+    /// Number of frames A1in may hold before it is evicted from first.
+    a1in_capacity: usize,
+    /// Number of pages remembered in A1out.
+    a1out_capacity: usize,
+}
+
+impl TwoQReplacer {
+    /// @brief a new TwoQReplacer.
+    /// @param num_frames the maximum number of frames the TwoQReplacer will
+    /// be required to store
+    /// @param a1in_percent share of the frames, in percent, that A1in may
+    /// hold before it is evicted from first, at least one frame. 25 is a
+    /// good default.
+    /// @param a1out_percent number of evicted pages remembered in A1out, in
+    /// percent of num_frames. 50 is a good default.
+    pub fn new(num_frames: usize, a1in_percent: usize, a1out_percent: usize) -> Self {
+        Self {
+            state: Mutex::new(TwoQState {
+                frames: (0..num_frames).map(|_| None).collect(),
+                page_ids: vec![None; num_frames],
+                a1in: VecDeque::new(),
+                am: VecDeque::new(),
+                a1out: VecDeque::new(),
+                size: 0,
+            }),
+            a1in_capacity: (num_frames * a1in_percent / 100).max(1),
+            a1out_capacity: num_frames * a1out_percent / 100,
+        }
+    }
+}
+
+impl Replacer for TwoQReplacer {
+    /// @brief Evict the oldest evictable frame of A1in if A1in holds more
+    /// than its share of the frames, otherwise the least recently used
+    /// evictable frame of Am. If the chosen queue has no evictable frame the
+    /// other one is used.
+    ///
+    /// The page of a frame evicted from A1in is remembered in A1out.
+    ///
+    /// @return the evicted frame, or none if no frames can be evicted.
+    fn evict(&self) -> Option<FrameId> {
+        let mut state = self.state.lock().unwrap();
+        if state.size == 0 {
+            return None;
+        }
+        let order = if state.a1in.len() > self.a1in_capacity {
+            [Queue::A1In, Queue::Am]
+        } else {
+            [Queue::Am, Queue::A1In]
+        };
+        for queue in order {
+            if let Some(frame_id) = state.take_victim(queue) {
+                let page_id = state.page_ids[frame_id].take();
+                if let (Queue::A1In, Some(page_id)) = (queue, page_id) {
+                    if self.a1out_capacity > 0 {
+                        if state.a1out.len() == self.a1out_capacity {
+                            state.a1out.pop_front();
+                        }
+                        state.a1out.push_back(page_id);
+                    }
+                }
+                return Some(frame_id);
+            }
+        }
+        unreachable!("an evictable frame is in neither queue");
+    }
+
+    /// @brief Record an access to the given frame. A frame seen for the first
+    /// time starts out evictable in A1in, or in Am if its page is remembered
+    /// in A1out. A frame in A1in is promoted to Am, a frame in Am becomes the
+    /// most recently used one.
+    ///
+    /// If frame id is invalid (ie. larger than the number of frames), panic.
+    ///
+    /// @param frame_id id of frame that received a new access.
+    /// @param access_type type of access that was received.
+    fn record_access(&self, frame_id: FrameId, access_type: AccessType) {
+        let mut state = self.state.lock().unwrap();
+        let is_scan = access_type == AccessType::Scan;
+        let queue = match state.frames.get(frame_id) {
+            Some(frame) => frame.as_ref().map(|frame| frame.queue),
+            None => panic!("Invalid frame id"),
+        };
+        match queue {
+            Some(_) if is_scan => {}
+            Some(queue) => {
+                state.unlink(frame_id, queue);
+                state.am.push_back(frame_id);
+                state.frames[frame_id].as_mut().unwrap().queue = Queue::Am;
+            }
+            None => {
+                // the page is in the pool again, so it is no ghost anymore
+                let mut ghost_hit = false;
+                if let Some(page_id) = state.page_ids[frame_id] {
+                    if let Some(pos) = state.a1out.iter().position(|&id| id == page_id) {
+                        state.a1out.remove(pos);
+                        ghost_hit = true;
+                    }
+                }
+                let queue = if ghost_hit && !is_scan {
+                    Queue::Am
+                } else {
+                    Queue::A1In
+                };
+                state.queue_mut(queue).push_back(frame_id);
+                state.frames[frame_id] = Some(TwoQFrame {
+                    queue,
+                    is_evictable: true,
+                });
+                state.size += 1;
+            }
+        }
+    }
+
+    /// @brief Toggle whether a frame is evictable or non-evictable, adjusting
+    /// the replacer's size accordingly. The frame keeps its place in its
+    /// queue.
+    ///
+    /// If frame id is invalid, panic. A frame that is not tracked is left
+    /// alone.
+    ///
+    /// @param frame_id id of frame whose 'evictable' status will be modified
+    /// @param set_evictable whether the given frame is evictable or not
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) {
+        let mut state = self.state.lock().unwrap();
+        let frame = match state.frames.get_mut(frame_id) {
+            Some(Some(frame)) => frame,
+            Some(None) => return,
+            None => panic!("Invalid frame id"),
+        };
+        if frame.is_evictable == set_evictable {
+            return;
+        }
+        frame.is_evictable = set_evictable;
+        if set_evictable {
+            state.size += 1;
+        } else {
+            state.size -= 1;
+        }
+    }
+
+    /// @brief Remove an evictable frame from the replacer, whichever queue it
+    /// is in. Its page is not remembered in A1out.
+    ///
+    /// If Remove is called on a non-evictable frame, panic. If frame id is
+    /// invalid, panic.
+    ///
+    /// If specified frame is not found, directly return from this function.
+    ///
+    /// @param frame_id id of frame to be removed
+    fn remove(&self, frame_id: FrameId) {
+        let mut state = self.state.lock().unwrap();
+        let queue = match state.frames.get(frame_id) {
+            Some(Some(frame)) if !frame.is_evictable => panic!("Frame is not evictable"),
+            Some(Some(frame)) => frame.queue,
+            Some(None) => return,
+            None => panic!("Invalid frame id"),
+        };
+        state.unlink(frame_id, queue);
+        state.frames[frame_id] = None;
+        state.page_ids[frame_id] = None;
+        state.size -= 1;
+    }
+
+    /// @brief Return replacer's size, which tracks the number of evictable
+    /// frames.
+    fn size(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+
+    /// @brief Remember which page the frame holds, so the page can be found
+    /// in A1out once the frame is evicted.
+    ///
+    /// If frame id is invalid, panic.
+    fn bind_page(&self, frame_id: FrameId, page_id: PageId) {
+        let mut state = self.state.lock().unwrap();
+        match state.page_ids.get_mut(frame_id) {
+            Some(slot) => *slot = Some(page_id),
+            None => panic!("Invalid frame id"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoQReplacer;
+    use crate::buffer::replacer::{AccessType, Replacer};
+
+    #[test]
+    fn test_two_q_promotion() {
+        // A1in holds up to 2 of the 8 frames before it is evicted from first.
+        let two_q_replacer = TwoQReplacer::new(8, 25, 50);
+
+        // Scenario: frames 1..=5 are accessed once, frames 2 and 4 a second
+        // time, which promotes them to Am. A1in is [1,3,5], Am is [4,2].
+        for frame_id in 1..=5 {
+            two_q_replacer.record_access(frame_id, AccessType::Unknown);
+        }
+        two_q_replacer.record_access(4, AccessType::Lookup);
+        two_q_replacer.record_access(2, AccessType::Lookup);
+        assert_eq!(5, two_q_replacer.size());
+
+        // Scenario: A1in holds more than its share, so it is evicted from in
+        // FIFO order until it is back to two frames.
+        assert_eq!(Some(1), two_q_replacer.evict());
+        // then Am goes first, least recently used first
+        assert_eq!(Some(4), two_q_replacer.evict());
+        assert_eq!(3, two_q_replacer.size());
+
+        // Scenario: a scan neither promotes frame 3 nor refreshes frame 2.
+        two_q_replacer.record_access(3, AccessType::Scan);
+        two_q_replacer.record_access(6, AccessType::Unknown);
+        two_q_replacer.record_access(2, AccessType::Scan);
+        two_q_replacer.record_access(6, AccessType::Unknown);
+        // A1in is [3,5], Am is [2,6]
+        assert_eq!(Some(2), two_q_replacer.evict());
+
+        // Scenario: a non-evictable frame keeps its place but is skipped.
+        two_q_replacer.set_evictable(6, false);
+        assert_eq!(Some(3), two_q_replacer.evict());
+        assert_eq!(Some(5), two_q_replacer.evict());
+        assert_eq!(None, two_q_replacer.evict());
+        two_q_replacer.set_evictable(6, true);
+        assert_eq!(Some(6), two_q_replacer.evict());
+        assert_eq!(0, two_q_replacer.size());
+    }
+
+    #[test]
+    fn test_two_q_ghost_promotion() {
+        // A1in holds up to 1 of the 4 frames, A1out remembers 2 pages.
+        let two_q_replacer = TwoQReplacer::new(4, 25, 50);
+        let load = |frame_id, page_id, access_type| {
+            two_q_replacer.bind_page(frame_id, page_id);
+            two_q_replacer.record_access(frame_id, access_type);
+        };
+
+        // Scenario: pages 10, 11 and 12 are loaded once. A1in overflows, so
+        // 10 and 11 are evicted and remembered in A1out.
+        load(0, 10, AccessType::Unknown);
+        load(1, 11, AccessType::Unknown);
+        load(2, 12, AccessType::Unknown);
+        assert_eq!(Some(0), two_q_replacer.evict());
+        assert_eq!(Some(1), two_q_replacer.evict());
+
+        // Scenario: page 10 comes back in another frame while A1out still
+        // remembers it, so it goes straight to Am. A1in only holds page 12,
+        // which is evicted before it.
+        load(1, 10, AccessType::Unknown);
+        load(0, 13, AccessType::Unknown);
+        assert_eq!(Some(2), two_q_replacer.evict());
+        assert_eq!(Some(1), two_q_replacer.evict());
+        assert_eq!(Some(0), two_q_replacer.evict());
+
+        // Scenario: A1out has room for 2 pages. 11 is forgotten once 12 and
+        // 13 are evicted after it, and comes back through A1in, while 12 is
+        // still remembered and goes to Am.
+        load(0, 11, AccessType::Unknown);
+        load(1, 12, AccessType::Unknown);
+        load(2, 14, AccessType::Unknown);
+        // A1in is [0,2], Am is [1]
+        assert_eq!(Some(0), two_q_replacer.evict());
+        assert_eq!(Some(1), two_q_replacer.evict());
+        assert_eq!(Some(2), two_q_replacer.evict());
+
+        // Scenario: A1out remembers 11 and 14. 14 is brought back by a scan,
+        // which does not promote it, 11 by a lookup, which does, so Am goes
+        // first.
+        load(0, 14, AccessType::Scan);
+        load(1, 11, AccessType::Lookup);
+        assert_eq!(Some(1), two_q_replacer.evict());
+        assert_eq!(Some(0), two_q_replacer.evict());
+
+        // Scenario: a removed frame's page is not remembered, so page 30 comes
+        // back through A1in while the evicted page 14 goes to Am.
+        load(0, 30, AccessType::Unknown);
+        two_q_replacer.remove(0);
+        load(2, 30, AccessType::Unknown);
+        load(3, 14, AccessType::Unknown);
+        assert_eq!(Some(3), two_q_replacer.evict());
+        assert_eq!(Some(2), two_q_replacer.evict());
+        assert_eq!(None, two_q_replacer.evict());
+    }
+}
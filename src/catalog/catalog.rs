@@ -195,7 +195,7 @@ mod tests {
         let db_path = "./test_catalog_create_table.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut catalog = super::Catalog::new(buffer_pool_manager);
 
@@ -311,7 +311,7 @@ mod tests {
         let db_path = "./test_catalog_get_table.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut catalog = super::Catalog::new(buffer_pool_manager);
 
@@ -399,7 +399,7 @@ mod tests {
         let db_path = "./test_catalog_create_index.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut catalog = super::Catalog::new(buffer_pool_manager);
 
@@ -6,7 +6,28 @@ pub const INVALID_PAGE_ID: PageId = std::u32::MAX;
 pub const TABLE_HEAP_BUFFER_POOL_SIZE: usize = 100;
 pub const LRUK_REPLACER_K: usize = 10; // lookback window for lru-k replacer
 
+// 页号的高 8 位是数据文件号，低 24 位是文件内的页号
+pub const PAGE_NO_BITS: u32 = 24;
+pub const MAIN_FILE_ID: FileId = 0; // the db file itself, other files are made by create_file
+pub const MAX_FILE_ID: FileId = (INVALID_PAGE_ID >> PAGE_NO_BITS) - 1; // the last id is taken by INVALID_PAGE_ID
+
 pub type FrameId = usize; // frame id type
 pub type PageId = u32; // page id type
+pub type FileId = u32; // data file id type
 pub type TransactionId = u32; // transaction id type
 pub type Lsn = u64; // log sequence number type
+
+// 由文件号和文件内页号组成页号
+pub fn make_page_id(file_id: FileId, page_no: PageId) -> PageId {
+    (file_id << PAGE_NO_BITS) | page_no
+}
+
+// 页号所在的数据文件
+pub fn file_id_of(page_id: PageId) -> FileId {
+    page_id >> PAGE_NO_BITS
+}
+
+// 页号在其数据文件内的页号
+pub fn page_no_of(page_id: PageId) -> PageId {
+    page_id & ((1 << PAGE_NO_BITS) - 1)
+}
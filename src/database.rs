@@ -19,7 +19,7 @@ pub struct Database {
 }
 impl Database {
     pub fn new_on_disk(db_path: &str) -> Self {
-        let disk_manager = Arc::new(DiskManager::new(db_path.to_string()));
+        let disk_manager = Arc::new(DiskManager::new(db_path.to_string()).unwrap());
         let buffer_pool_manager =
             BufferPoolManager::new(TABLE_HEAP_BUFFER_POOL_SIZE, disk_manager.clone());
         // TODO load catalog from disk
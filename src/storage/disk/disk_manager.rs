@@ -1,245 +1,2456 @@
-use std::fs::{File, OpenOptions};
-use std::future::Future;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::sync::Mutex;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use log::debug;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+use log::{debug, warn};
+use lz4_flex::block;
+use rand::Rng;
 
-use crate::common::config::{PageId, BUSTUB_PAGE_SIZE};
+use crate::common::config::{
+    file_id_of, make_page_id, page_no_of, FileId, PageId, BUSTUB_PAGE_SIZE, MAIN_FILE_ID,
+    MAX_FILE_ID, PAGE_NO_BITS,
+};
+
+/// How often a blocked open retries to take the database file lock.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+/// How many pages the db file grows by at a time, unless configured.
+pub const DEFAULT_EXTENT_PAGES: usize = 64;
+/// How many pages are read ahead of a sequential scan, unless configured.
+pub const DEFAULT_READ_AHEAD_PAGES: usize = 8;
+/// How many pages have to be read in order before reading ahead starts.
+const READ_AHEAD_TRIGGER: usize = 3;
+
+// A database is made of data files: the db file itself, and the files made by
+// create_file next to it, `<name>.<file id>.db`. A page id tells the file in
+// its high bits and the page number within the file in the low bits.
+//
+// The first page of a data file is a header that tracks page allocation, page
+// number N is stored right after it, at file offset (N + 1) * BUSTUB_PAGE_SIZE.
+// The file is grown a whole extent of zeroed pages at a time, the header
+// records how many data pages it has room for.
+const HEADER_MAGIC: u32 = 0x4255_5348;
+const OFFSET_HEADER_MAGIC: usize = 0;
+const OFFSET_HEADER_NEXT_PAGE_ID: usize = 4;
+const OFFSET_HEADER_NUM_FREE_PAGES: usize = 8;
+const OFFSET_HEADER_ALLOCATED_PAGES: usize = 12;
+const OFFSET_HEADER_FREE_PAGES: usize = 16;
+/// How many deallocated page ids fit into the header. Ids beyond that are
+/// still reused while the DiskManager is open, but are lost on reopen.
+const MAX_HEADER_FREE_PAGES: usize =
+    (BUSTUB_PAGE_SIZE - OFFSET_HEADER_FREE_PAGES) / std::mem::size_of::<PageId>();
+
+// With compression on, the header has its own magic, and data pages are
+// variable sized records appended after the header page: a flag byte telling
+// whether the data is compressed, the length of the data as u32, then the
+// data. Compressed data is an LZ4 block. Where the record of each page lives
+// is kept in the page map file next to the db file, one entry of a u64 offset
+// and a u32 capacity per page id. An offset of 0 means the page was never
+// written.
+const HEADER_MAGIC_COMPRESSED: u32 = 0x4255_535A;
+const RECORD_RAW: u8 = 0;
+const RECORD_COMPRESSED: u8 = 1;
+const RECORD_HEADER_SIZE: usize = 5;
+const PAGE_MAP_ENTRY_SIZE: usize = 12;
+
+// With encryption on, pages are encrypted and authenticated with
+// ChaCha20-Poly1305 (RFC 8439). The header has yet another magic, and its last
+// SEAL_SIZE bytes are a nonce and a tag that authenticate the rest of the
+// header page, which tells whether the key is the right one. Data pages are
+// stored in slots of SEAL_SIZE + BUSTUB_PAGE_SIZE bytes: the nonce, the tag,
+// then the encrypted page, authenticated along with its page id so it cannot
+// be moved to another page. A slot whose nonce and tag are zeros was never
+// written.
+const HEADER_MAGIC_ENCRYPTED: u32 = 0x4255_5345;
+/// The size of an EncryptionKey, in bytes.
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const SEAL_SIZE: usize = NONCE_SIZE + TAG_SIZE;
+
+// With double writes on, the pages of a write go to the double-write file
+// next to the db file, `<name>.dw`, which is synced before any of them is
+// written to its data file, and emptied once the data files are synced. Each
+// record is the page id as u32, the CRC-32 of the slot as u32, and the slot as
+// it is stored in the data file. When the database is opened, every intact
+// record whose page does not have that checksum in its data file is written
+// there again, which repairs a page torn by a crash in the middle of its write.
+const DW_RECORD_HEADER_SIZE: usize = 8;
+
+/// Errors specific to the disk layer. They are carried inside an
+/// `io::Error`, use `DiskError::from_io_error` to tell them apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskError {
+    /// The database file is locked by another DiskManager, either in this
+    /// process or in another one.
+    DatabaseInUse,
+    /// The database file does not start with a valid header page.
+    InvalidHeader,
+    /// The disk scheduler was shut down, or the worker the request was queued
+    /// for is gone, so the request was not executed.
+    SchedulerClosed,
+    /// The database file is encrypted and no key was given, or the other way
+    /// around.
+    EncryptionMismatch,
+    /// The key is not the one the database file was encrypted with, or its
+    /// header was tampered with.
+    WrongKey,
+    /// An encrypted page failed authentication: it was changed, damaged or
+    /// moved outside of the DiskManager.
+    PageTampered(PageId),
+    /// The page was neither allocated nor written, so there is nothing to
+    /// read.
+    PageOutOfRange(PageId),
+}
+
+impl DiskError {
+    /// Returns the DiskError wrapped in `err`, if it carries one.
+    pub fn from_io_error(err: &io::Error) -> Option<&DiskError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<DiskError>())
+    }
+}
+
+impl fmt::Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskError::DatabaseInUse => write!(f, "database file is in use"),
+            DiskError::InvalidHeader => write!(f, "database file has no valid header"),
+            DiskError::SchedulerClosed => write!(f, "disk scheduler is shut down"),
+            DiskError::EncryptionMismatch => {
+                write!(f, "database file encryption does not match the options")
+            }
+            DiskError::WrongKey => write!(f, "wrong key for the database file"),
+            DiskError::PageTampered(page_id) => {
+                write!(f, "page {} failed authentication", page_id)
+            }
+            DiskError::PageOutOfRange(page_id) => {
+                write!(f, "page {} is past the allocated pages", page_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DiskError {}
+
+impl From<DiskError> for io::Error {
+    fn from(e: DiskError) -> Self {
+        let kind = match e {
+            DiskError::DatabaseInUse => io::ErrorKind::WouldBlock,
+            DiskError::InvalidHeader => io::ErrorKind::InvalidData,
+            DiskError::SchedulerClosed => io::ErrorKind::BrokenPipe,
+            DiskError::EncryptionMismatch | DiskError::WrongKey => io::ErrorKind::InvalidInput,
+            DiskError::PageTampered(_) => io::ErrorKind::InvalidData,
+            DiskError::PageOutOfRange(_) => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// When page writes are made durable with an fsync. Whatever the policy,
+/// DiskManagerTrait::sync makes every write so far durable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync the data after every page write.
+    EveryWrite,
+    /// Only sync when asked to. Writes still reach the OS right away.
+    #[default]
+    OnFlushCall,
+    /// Sync after a page write when the last sync is at least this long ago.
+    Periodic(Duration),
+}
+
+/// A key to encrypt pages with. The key is never written anywhere, and its
+/// Debug output does not show it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; KEY_SIZE]);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        Self(key)
+    }
+
+    /// Encrypts data in place and returns the tag that authenticates it
+    /// along with aad. A nonce must never be used twice with the same key.
+    fn seal(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], data: &mut [u8]) -> Tag {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+            .encrypt_in_place_detached(Nonce::from_slice(nonce), aad, data)
+            .expect("pages are far below the ChaCha20-Poly1305 size limit")
+    }
+
+    /// Decrypts data in place if tag authenticates it along with aad.
+    /// Returns false and leaves data as it is otherwise.
+    fn open(&self, nonce: &[u8], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+            .decrypt_in_place_detached(Nonce::from_slice(nonce), aad, data, Tag::from_slice(tag))
+            .is_ok()
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+/// Options used when opening a DiskManager.
+#[derive(Debug, Clone)]
+pub struct DiskManagerOptions {
+    /// Open the database without write access. Read-only opens take a shared
+    /// lock on the db file, so any number of them can coexist, but none can
+    /// coexist with a read-write open.
+    pub read_only: bool,
+    /// How long to wait for a conflicting lock to be released before giving
+    /// up with `DiskError::DatabaseInUse`. `None` fails immediately.
+    pub lock_timeout: Option<Duration>,
+    /// Compress pages before they are written. A db file created with
+    /// compression can only be opened with compression, and the other way
+    /// around, otherwise the open fails with `DiskError::InvalidHeader`.
+    pub compress_pages: bool,
+    /// When page writes are synced to disk.
+    pub sync_policy: SyncPolicy,
+    /// How many pages the db file grows by when a page is written past its
+    /// end. Ignored with compression, records are appended one by one.
+    pub extent_pages: usize,
+    /// How many pages to read ahead once pages of a data file are read in
+    /// order, with one read covering them all. 0 turns reading ahead off.
+    /// Ignored with compression.
+    pub read_ahead_pages: usize,
+    /// Encrypt pages with this key. The pages are authenticated too, a page
+    /// changed outside of the DiskManager fails to read with
+    /// `DiskError::PageTampered`. An older version of a page put back in its
+    /// place is not detected though. A db file created with a key can only be
+    /// opened with that key, and one created without only without, otherwise
+    /// the open fails with `DiskError::WrongKey` or
+    /// `DiskError::EncryptionMismatch`. Cannot be combined with compression.
+    /// The log is not encrypted.
+    pub encryption_key: Option<EncryptionKey>,
+    /// Write pages to the double-write file first, so that a page torn by a
+    /// crash can be repaired when the database is opened again. Costs a sync
+    /// of the double-write file and of the data files per write, or per batch
+    /// with DiskManagerTrait::write_pages. Cannot be combined with
+    /// compression.
+    pub double_write: bool,
+}
+
+impl Default for DiskManagerOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            lock_timeout: None,
+            compress_pages: false,
+            sync_policy: SyncPolicy::default(),
+            extent_pages: DEFAULT_EXTENT_PAGES,
+            read_ahead_pages: DEFAULT_READ_AHEAD_PAGES,
+            encryption_key: None,
+            double_write: false,
+        }
+    }
+}
+
+/// The number of operations of one kind and the time they took in total.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// Number of operations, failed ones included.
+    pub count: u64,
+    /// Time spent in them, summed up.
+    pub total_time: Duration,
+}
+
+impl OpStats {
+    /// Returns the mean time an operation took, none if there was none.
+    pub fn mean_time(&self) -> Option<Duration> {
+        (self.count > 0)
+            .then(|| Duration::from_nanos((self.total_time.as_nanos() / self.count as u128) as u64))
+    }
+}
+
+/// I/O statistics of a disk manager since it was opened. A plain copy, cheap
+/// to take periodically and to compare with an earlier one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStats {
+    /// Page reads.
+    pub reads: OpStats,
+    /// Page writes.
+    pub writes: OpStats,
+    /// Log writes, each one is flushed.
+    pub log_flushes: OpStats,
+}
+
+/// The counters behind an OpStats, updated concurrently.
+#[derive(Default)]
+pub(super) struct OpCounter {
+    count: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl OpCounter {
+    /// Starts timing an operation, which is counted when the returned timer
+    /// is dropped.
+    pub(super) fn start(&self) -> OpTimer<'_> {
+        OpTimer {
+            counter: self,
+            start: Instant::now(),
+        }
+    }
+
+    fn get(&self) -> OpStats {
+        OpStats {
+            count: self.count.load(Ordering::Relaxed),
+            total_time: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Times an operation for an OpCounter until it is dropped, so that every
+/// way out of the operation is counted.
+pub(super) struct OpTimer<'a> {
+    counter: &'a OpCounter,
+    start: Instant,
+}
+
+impl Drop for OpTimer<'_> {
+    fn drop(&mut self) {
+        let nanos = self.start.elapsed().as_nanos() as u64;
+        self.counter.count.fetch_add(1, Ordering::Relaxed);
+        self.counter.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+/// The counters behind IoStats.
+#[derive(Default)]
+pub(super) struct IoCounters {
+    pub(super) reads: OpCounter,
+    pub(super) writes: OpCounter,
+    pub(super) log_flushes: OpCounter,
+}
+
+impl IoCounters {
+    pub(super) fn get(&self) -> IoStats {
+        IoStats {
+            reads: self.reads.get(),
+            writes: self.writes.get(),
+            log_flushes: self.log_flushes.get(),
+        }
+    }
+}
+
+/// DiskManagerTrait is what the disk scheduler needs from a disk manager:
+/// reading and writing pages and the log, and handing out page ids. The
+/// file-backed DiskManager is the real one, DiskManagerMemory keeps everything
+/// in memory for tests.
+pub trait DiskManagerTrait: Send + Sync {
+    /// Write a page to the database.
+    fn write_page(&self, page_id: PageId, page_data: &[u8]) -> io::Result<()>;
+
+    /// Write several pages to the database, e.g. for a checkpoint. Stops at
+    /// the first page that fails. By default the pages are written one by
+    /// one, a disk manager that can do better with a whole batch overrides
+    /// it.
+    fn write_pages(&self, pages: &[(PageId, &[u8])]) -> io::Result<()> {
+        for &(page_id, page_data) in pages {
+            self.write_page(page_id, page_data)?;
+        }
+        Ok(())
+    }
+
+    /// Read a page from the database. A page that was allocated, or that
+    /// there is room for because a later page was written, reads as zeros
+    /// until it is written. Fails with `DiskError::PageOutOfRange` for a page
+    /// past those, and with `io::ErrorKind::UnexpectedEof` for a page that is
+    /// only partly stored, e.g. because the file was truncated.
+    fn read_page(&self, page_id: PageId, page_data: &mut [u8]) -> io::Result<()>;
+
+    /// Allocates a page id in the data file file_id, reusing a deallocated
+    /// one first.
+    ///
+    /// With several buffer pool instances sharing the disk, each instance only
+    /// owns the page ids `p` with `p % num_instances == instance_index`. A
+    /// standalone buffer pool passes 1 and 0.
+    fn allocate_page(
+        &self,
+        file_id: FileId,
+        num_instances: usize,
+        instance_index: usize,
+    ) -> io::Result<PageId>;
+
+    /// Deallocates a page id so a later allocate_page can reuse it. Ids that
+    /// are not allocated are ignored.
+    fn deallocate_page(&self, page_id: PageId) -> io::Result<()>;
+
+    /// Append to the log. Only returns once the data is durable.
+    fn write_log(&self, log_data: &[u8]) -> io::Result<()>;
+
+    /// Read the log starting at offset into log_data, zero-filling whatever
+    /// is past the end of the log.
+    /// @return: false means offset is already past the end
+    fn read_log(&self, log_data: &mut [u8], offset: usize) -> io::Result<bool>;
+
+    /// Makes every page write and log write so far durable, whatever the
+    /// sync policy.
+    fn sync(&self) -> io::Result<()>;
+
+    /// Returns the number of flushes: log writes, and syncs of the pages.
+    fn get_num_flushes(&self) -> i32;
+
+    /// Returns the number of page writes.
+    fn get_num_writes(&self) -> i32;
+
+    /// Creates a new, empty data file.
+    /// @return the id of the file, to allocate pages in
+    fn create_file(&self) -> io::Result<FileId>;
+
+    /// Deletes a data file with all its pages, releasing their space. The
+    /// main file cannot be deleted. Requests for pages of a deleted file fail.
+    fn delete_file(&self, file_id: FileId) -> io::Result<()>;
+
+    /// Returns how many pages the storage has room for, written or not, over
+    /// all data files.
+    fn allocated_pages(&self) -> usize;
+
+    /// Returns the number and the duration of page reads, page writes and
+    /// log writes so far.
+    fn get_io_stats(&self) -> IoStats;
+}
 
 /// DiskManager takes care of the allocation and deallocation of pages within a
 /// database. It performs the reading and writing of pages to and from disk,
 /// providing a logical file layer within the context of a database management
 /// system.
 pub struct DiskManager {
-    // Stream to write log file
-    log_io: File,
+    // Stream to write the log file
+    log: Mutex<File>,
     log_name: String,
-    // Stream to write db file
-    // Protects file access with multiple buffer pool instances
-    db_io: Mutex<File>,
     file_name: String,
+    // The data files by id, the main file included
+    files: RwLock<HashMap<FileId, Arc<DataFile>>>,
+    // Whether the database was opened without write access
+    read_only: bool,
+    // Whether pages are compressed
+    compress_pages: bool,
+    // When page writes are synced
+    sync_policy: SyncPolicy,
+    // How many pages the db file grows by at a time
+    extent_pages: usize,
+    // How many pages are read ahead of a sequential scan
+    read_ahead_pages: usize,
+    // The key pages are encrypted with, if they are
+    encryption_key: Option<EncryptionKey>,
+    // The double-write file, if double writes are on
+    double_write: Option<Mutex<File>>,
+    // When the pages were last synced
+    last_sync: Mutex<Instant>,
     // Number of disk flushes
-    num_flushes: i32,
+    num_flushes: AtomicI32,
     // Number of disk writes
-    num_writes: i32,
-    // Indicates if the in-memory content has not been flushed yet
-    flush_log: bool,
-    // Future for non-blocking flushes
-    flush_log_f: Option<Box<dyn Future<Output = ()> + Send + Sync>>,
+    num_writes: AtomicI32,
+    // Number of reads from the data files, read ahead pages served from
+    // memory are not counted
+    num_reads: AtomicI32,
+    // Count and duration of reads, writes and log flushes
+    io_counters: IoCounters,
+}
+
+/// One data file of the database.
+struct DataFile {
+    // Stream to write the file
+    // Protects file access with multiple buffer pool instances
+    io: Mutex<File>,
+    // Where the file is
+    path: PathBuf,
+    // Page allocation state, mirrored in the header page
+    allocation: Mutex<PageAllocation>,
+    // Where the page records are, if pages are compressed
+    page_map: Option<Mutex<PageMap>>,
+    // Pages read ahead of a sequential scan
+    read_ahead: Mutex<ReadAhead>,
+}
+
+/// Detects sequential page reads of a data file and keeps the pages read
+/// ahead of them.
+#[derive(Default)]
+struct ReadAhead {
+    // The page number read last
+    last_page_no: Option<PageId>,
+    // How many pages were read in order up to the last one
+    run: usize,
+    // Page number of the first page in pages
+    start: PageId,
+    // The content of the pages read ahead, back to back
+    pages: Vec<u8>,
+}
+
+impl ReadAhead {
+    /// Records a read of page page_no and returns whether it continues a
+    /// sequential scan long enough to read ahead.
+    fn record(&mut self, page_no: PageId) -> bool {
+        if self
+            .last_page_no
+            .is_some_and(|last| last.checked_add(1) == Some(page_no))
+        {
+            self.run += 1;
+        } else {
+            self.run = 1;
+        }
+        self.last_page_no = Some(page_no);
+        self.run >= READ_AHEAD_TRIGGER
+    }
+
+    /// Returns the content of page page_no if it was read ahead.
+    fn get(&self, page_no: PageId) -> Option<&[u8]> {
+        let index = page_no.checked_sub(self.start)? as usize;
+        self.pages
+            .get(index * BUSTUB_PAGE_SIZE..(index + 1) * BUSTUB_PAGE_SIZE)
+    }
+
+    /// Drops the pages read ahead if page page_no is one of them.
+    fn invalidate(&mut self, page_no: PageId) {
+        if self.get(page_no).is_some() {
+            self.pages.clear();
+        }
+    }
+}
+
+/// The location of every page record of a db file with compressed pages,
+/// mirrored in the page map file.
+struct PageMap {
+    // Stream to write the page map file
+    io: File,
+    // Offset and capacity of the record of every page written so far
+    locations: HashMap<PageId, (u64, usize)>,
+    // The file offset a record that does not fit in place is appended at
+    end: u64,
+}
+
+impl PageMap {
+    /// Loads the page map file of a db file that is db_len bytes long.
+    fn load(mut io: File, db_len: u64) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        io.seek(SeekFrom::Start(0))?;
+        io.read_to_end(&mut entries)?;
+        let mut locations = HashMap::new();
+        for (page_id, entry) in entries.chunks_exact(PAGE_MAP_ENTRY_SIZE).enumerate() {
+            let offset = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let capacity = u32::from_le_bytes(entry[8..].try_into().unwrap()) as usize;
+            if offset != 0 {
+                locations.insert(page_id as PageId, (offset, capacity));
+            }
+        }
+        Ok(Self {
+            io,
+            locations,
+            end: db_len.max(BUSTUB_PAGE_SIZE as u64),
+        })
+    }
+
+    /// Records that the record of page_id is now at offset.
+    fn set(&mut self, page_id: PageId, offset: u64, capacity: usize) -> io::Result<()> {
+        let mut entry = [0; PAGE_MAP_ENTRY_SIZE];
+        entry[..8].copy_from_slice(&offset.to_le_bytes());
+        entry[8..].copy_from_slice(&(capacity as u32).to_le_bytes());
+        self.io
+            .seek(SeekFrom::Start(page_id as u64 * PAGE_MAP_ENTRY_SIZE as u64))?;
+        self.io.write_all(&entry)?;
+        self.io.flush()?;
+        self.locations.insert(page_id, (offset, capacity));
+        Ok(())
+    }
+}
+
+/// The page allocation state of one data file. It works on page numbers
+/// within the file.
+#[derive(Clone, Default)]
+pub(super) struct PageAllocation {
+    // The page number the file is extended with on the next allocation
+    next_page_id: PageId,
+    // Deallocated page numbers, reused before the file is extended
+    free_pages: BTreeSet<PageId>,
+    // Number of data pages the db file has room for
+    allocated_pages: usize,
+}
+
+impl PageAllocation {
+    /// Hands out a page id of the data file file_id owned by instance_index,
+    /// see DiskManagerTrait::allocate_page. Ids of other instances passed
+    /// over when extending are kept as free for them.
+    /// @return StorageFull if the file has no page numbers left
+    pub(super) fn allocate(
+        &mut self,
+        file_id: FileId,
+        num_instances: usize,
+        instance_index: usize,
+    ) -> io::Result<PageId> {
+        let owned = |page_no: PageId| {
+            make_page_id(file_id, page_no) as usize % num_instances == instance_index
+        };
+        if let Some(page_no) = self.free_pages.iter().copied().find(|&p| owned(p)) {
+            self.free_pages.remove(&page_no);
+            return Ok(make_page_id(file_id, page_no));
+        }
+        while !owned(self.next_page_id) {
+            self.free_pages.insert(self.next_page_id);
+            self.next_page_id += 1;
+        }
+        if self.next_page_id >= 1 << PAGE_NO_BITS {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!("data file {} is full", file_id),
+            ));
+        }
+        self.next_page_id += 1;
+        Ok(make_page_id(file_id, self.next_page_id - 1))
+    }
+
+    /// Returns whether page number page_no was handed out or has room in the
+    /// file, which makes it readable.
+    pub(super) fn in_range(&self, page_no: PageId) -> bool {
+        page_no < self.next_page_id || (page_no as usize) < self.allocated_pages
+    }
+
+    /// Marks page number page_no as free again.
+    /// @return false if it was not allocated
+    pub(super) fn deallocate(&mut self, page_no: PageId) -> bool {
+        page_no < self.next_page_id && self.free_pages.insert(page_no)
+    }
 }
 
 impl DiskManager {
     /// Creates a new disk manager that writes to the specified database file.
-    pub fn new(db_file: &str) -> Self {
+    /// Fails if the db file or its log file cannot be opened or created, e.g.
+    /// because the directory does not exist or is read-only.
+    pub fn new(db_file: &str) -> io::Result<Self> {
+        Self::with_options(db_file, DiskManagerOptions::default())
+    }
+
+    /// Opens the specified database file according to `options`.
+    ///
+    /// The db file is locked for the lifetime of the DiskManager: exclusively
+    /// for read-write opens, shared for read-only ones. The lock is released
+    /// when the DiskManager is dropped. Fails with `DiskError::DatabaseInUse`
+    /// if the lock cannot be acquired within `options.lock_timeout`.
+    pub fn with_options(db_file: &str, options: DiskManagerOptions) -> io::Result<Self> {
+        if options.compress_pages && (options.encryption_key.is_some() || options.double_write) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed pages can be neither encrypted nor double-written",
+            ));
+        }
         // Extract the base file name and add ".log" extension for the log file
         let file_name = Path::new(db_file);
         let log_name = file_name.with_extension("log");
 
-        // Try to open the log file, create it if it doesn't exist
-        let log_io = OpenOptions::new()
-            .read(true)
-            .append(true)
-            .write(true)
-            .open(&log_name)
-            .or_else(|_| {
-                OpenOptions::new()
-                    .create(true)
-                    .read(true)
-                    .write(true)
-                    .open(&log_name)
-            })
-            .unwrap();
-
-        // Ensure the db file is open, create it if it doesn't exist
-        let db_io = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(db_file)
-            .or_else(|_| {
-                OpenOptions::new()
-                    .create(true)
-                    .read(true)
-                    .write(true)
-                    .open(db_file)
-            })
-            .unwrap();
+        let (log_io, db_io) = if options.read_only {
+            // A read-only open never creates anything
+            let log_io = OpenOptions::new().read(true).open(&log_name)?;
+            let db_io = OpenOptions::new().read(true).open(db_file)?;
+            (log_io, db_io)
+        } else {
+            // Try to open the log file, create it if it doesn't exist
+            let log_io = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(&log_name)?;
+            // Ensure the db file is open, create it if it doesn't exist
+            let db_io = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(db_file)?;
+            (log_io, db_io)
+        };
+        Self::lock_db_file(&db_io, &options)?;
 
-        Self {
-            log_io,
+        let mut dm = Self {
+            log: Mutex::new(log_io),
             log_name: log_name.to_string_lossy().to_string(),
-            db_io: Mutex::new(db_io),
             file_name: db_file.to_string(),
-            num_flushes: 0,
-            num_writes: 0,
-            flush_log: false,
-            flush_log_f: None,
+            files: RwLock::new(HashMap::new()),
+            read_only: options.read_only,
+            compress_pages: options.compress_pages,
+            sync_policy: options.sync_policy,
+            extent_pages: options.extent_pages.max(1),
+            read_ahead_pages: options.read_ahead_pages,
+            encryption_key: options.encryption_key,
+            double_write: None,
+            last_sync: Mutex::new(Instant::now()),
+            num_flushes: AtomicI32::new(0),
+            num_writes: AtomicI32::new(0),
+            num_reads: AtomicI32::new(0),
+            io_counters: IoCounters::default(),
+        };
+        let main_file = dm.load_file(db_io, file_name.to_path_buf())?;
+        let mut files = HashMap::from([(MAIN_FILE_ID, Arc::new(main_file))]);
+        for (file_id, path) in Self::find_data_files(file_name)? {
+            let io = OpenOptions::new()
+                .read(true)
+                .write(!options.read_only)
+                .open(&path)?;
+            files.insert(file_id, Arc::new(dm.load_file(io, path)?));
         }
-    }
+        *dm.files.write().unwrap() = files;
 
-    /// Write a page to the database file.
-    pub fn write_page(&mut self, page_id: PageId, page_data: &[u8]) {
-        assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
+        // pages left in the double-write file are repaired even if double
+        // writes are off now, a read-only open leaves them alone
+        let dw_name = file_name.with_extension("dw");
+        if !options.read_only && (options.double_write || dw_name.exists()) {
+            let dw_io = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&dw_name)?;
+            dm.recover_double_write(&dw_io)?;
+            if options.double_write {
+                dm.double_write = Some(Mutex::new(dw_io));
+            } else {
+                std::fs::remove_file(&dw_name)?;
+            }
+        }
+        Ok(dm)
+    }
 
-        let offset = page_id as usize * BUSTUB_PAGE_SIZE;
-        // set write cursor to offset
-        self.num_writes += 1;
+    /// Writes the intact records of the double-write file whose pages differ
+    /// in their data files back there, then empties the double-write file.
+    fn recover_double_write(&self, mut dw_io: &File) -> io::Result<()> {
+        let mut records = Vec::new();
+        dw_io.seek(SeekFrom::Start(0))?;
+        dw_io.read_to_end(&mut records)?;
+        let slot_size = self.slot_size();
+        let mut repaired = Vec::new();
+        for record in records.chunks_exact(DW_RECORD_HEADER_SIZE + slot_size) {
+            let page_id = PageId::from_le_bytes(record[..4].try_into().unwrap());
+            let checksum = u32::from_le_bytes(record[4..8].try_into().unwrap());
+            let slot = &record[DW_RECORD_HEADER_SIZE..];
+            if crc32fast::hash(slot) != checksum {
+                // torn itself, so its page was not written yet
+                debug!("Skipped a torn double-write record");
+                continue;
+            }
+            let Ok(file) = self.file_of(page_id) else {
+                continue;
+            };
+            let page_no = page_no_of(page_id);
+            let mut home = vec![0; slot_size];
+            self.read_at(&file, self.page_offset(page_no), &mut home)?;
+            if crc32fast::hash(&home) != checksum {
+                self.write_slot(&file, page_no, slot)?;
+                repaired.push((page_id, file));
+            }
+        }
+        if !repaired.is_empty() {
+            warn!(
+                "repaired {} pages from the double-write file",
+                repaired.len()
+            );
+            Self::sync_files(repaired.iter().map(|(_, file)| &**file))?;
+        }
+        dw_io.set_len(0)?;
+        dw_io.sync_all()
+    }
 
-        let mut db_io = self.db_io.lock().unwrap();
-        db_io.seek(SeekFrom::Start(offset as u64)).unwrap();
-        if let Err(e) = db_io.write_all(&page_data) {
-            panic!("I/O error while writing: {:?}", e);
+    /// Syncs the data of every file once.
+    fn sync_files<'a>(files: impl Iterator<Item = &'a DataFile>) -> io::Result<()> {
+        let mut synced: Vec<*const DataFile> = Vec::new();
+        for file in files {
+            if !synced.contains(&(file as *const _)) {
+                file.io.lock().unwrap().sync_data()?;
+                synced.push(file);
+            }
         }
-        // needs to flush to keep disk file in sync
-        db_io.flush().unwrap();
+        Ok(())
     }
 
-    /// Read a page from the database file.
-    pub fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) {
-        let offset = page_id as usize * BUSTUB_PAGE_SIZE;
+    /// Writes pages to the double-write file, then to their data files, as
+    /// described at the top of this file.
+    fn double_write_pages(&self, dw: &Mutex<File>, pages: &[(PageId, &[u8])]) -> io::Result<()> {
+        let mut slots = Vec::with_capacity(pages.len());
+        for &(page_id, page_data) in pages {
+            assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
+            slots.push((
+                self.file_of(page_id)?,
+                page_id,
+                self.encode_page(page_id, page_data),
+            ));
+        }
+        let mut records =
+            Vec::with_capacity(pages.len() * (DW_RECORD_HEADER_SIZE + self.slot_size()));
+        for (_, page_id, slot) in &slots {
+            records.extend_from_slice(&page_id.to_le_bytes());
+            records.extend_from_slice(&crc32fast::hash(slot).to_le_bytes());
+            records.extend_from_slice(slot);
+        }
 
-        let mut db_io = self.db_io.lock().unwrap();
-        // check if read beyond file length
-        if offset > db_io.metadata().unwrap().len() as usize {
-            panic!("I/O error reading past end of file");
+        // one batch at a time, the double-write file holds only one
+        let mut dw_io = dw.lock().unwrap();
+        dw_io.seek(SeekFrom::Start(0))?;
+        dw_io.write_all(&records)?;
+        dw_io.sync_data()?;
+        for (file, page_id, slot) in &slots {
+            self.num_writes.fetch_add(1, Ordering::Relaxed);
+            let _timer = self.io_counters.writes.start();
+            self.write_slot(file, page_no_of(*page_id), slot)?;
         }
-        // set read cursor to offset
-        db_io.seek(SeekFrom::Start(offset as u64)).unwrap();
-        assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
-        match db_io.read(page_data) {
-            Ok(read_count) => {
-                // if file ends before reading BUSTUB_PAGE_SIZE
-                if read_count < BUSTUB_PAGE_SIZE {
-                    debug!("Read less than a page");
-                    // fill the rest of the buffer with 0
-                    page_data[read_count..].fill(0);
-                }
-            }
-            Err(e) => panic!("I/O error while reading: {:?}", e),
+        // the pages have to be durable in their data files before their
+        // copies go away
+        Self::sync_files(slots.iter().map(|(file, _, _)| &**file))?;
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        dw_io.set_len(0)?;
+        dw_io.sync_data()
+    }
+
+    /// Returns the data files next to the db file, by id.
+    fn find_data_files(db_file: &Path) -> io::Result<Vec<(FileId, PathBuf)>> {
+        let dir = match db_file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
         };
+        let stem = db_file.file_stem().unwrap_or_default().to_string_lossy();
+        let mut data_files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&*stem)?.strip_prefix('.'))
+                .and_then(|name| name.strip_suffix(".db")?.parse::<FileId>().ok())
+                .filter(|&file_id| file_id != MAIN_FILE_ID && file_id <= MAX_FILE_ID);
+            if let Some(file_id) = file_id {
+                data_files.push((file_id, path));
+            }
+        }
+        Ok(data_files)
     }
 
-    /// Write the contents of the log into disk file
-    /// Only return when sync is done, and only perform sequence write
-    pub fn write_log(&mut self, log_data: &[u8]) {
-        if log_data.is_empty() {
-            // no effect on num_flushes_ if log buffer is empty
-            return;
+    /// Returns the path of the data file file_id.
+    fn data_file_path(&self, file_id: FileId) -> PathBuf {
+        Path::new(&self.file_name).with_extension(format!("{}.db", file_id))
+    }
+
+    /// Sets up an open data file: loads its page map if pages are compressed,
+    /// and loads its header, or writes one if the file is new.
+    fn load_file(&self, io: File, path: PathBuf) -> io::Result<DataFile> {
+        let page_map = if self.compress_pages {
+            let map_io = OpenOptions::new()
+                .read(true)
+                .write(!self.read_only)
+                .create(!self.read_only)
+                .truncate(false)
+                .open(path.with_extension("map"))?;
+            Some(Mutex::new(PageMap::load(map_io, io.metadata()?.len())?))
+        } else {
+            None
+        };
+        let file = DataFile {
+            io: Mutex::new(io),
+            path,
+            allocation: Mutex::new(PageAllocation::default()),
+            page_map,
+            read_ahead: Mutex::new(ReadAhead::default()),
+        };
+        if file.io.lock().unwrap().metadata()?.len() == 0 {
+            if !self.read_only {
+                self.write_header(&file, &file.allocation.lock().unwrap())?;
+            }
+        } else {
+            let mut allocation = self.read_header(&file)?;
+            // a file grown without the header being updated, e.g. because of
+            // a crash in between, still has room for all its pages
+            let len = file.io.lock().unwrap().metadata()?.len() as usize;
+            allocation.allocated_pages = allocation
+                .allocated_pages
+                .max(len.saturating_sub(BUSTUB_PAGE_SIZE) / self.slot_size());
+            *file.allocation.lock().unwrap() = allocation;
         }
+        Ok(file)
+    }
+
+    /// Returns the data file page_id is in.
+    fn file_of(&self, page_id: PageId) -> io::Result<Arc<DataFile>> {
+        let file_id = file_id_of(page_id);
+        self.files
+            .read()
+            .unwrap()
+            .get(&file_id)
+            .cloned()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("data file {} does not exist", file_id),
+                )
+            })
+    }
+
+    /// Loads the page allocation state from the header page of file.
+    fn read_header(&self, file: &DataFile) -> io::Result<PageAllocation> {
+        let mut header = [0; BUSTUB_PAGE_SIZE];
+        let mut db_io = file.io.lock().unwrap();
+        db_io.seek(SeekFrom::Start(0))?;
+        db_io
+            .read_exact(&mut header)
+            .map_err(|_| io::Error::from(DiskError::InvalidHeader))?;
 
-        self.flush_log = true;
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let magic = read_u32(OFFSET_HEADER_MAGIC);
+        let expected_magic = self.header_magic(file);
+        if magic != expected_magic
+            && [
+                HEADER_MAGIC,
+                HEADER_MAGIC_COMPRESSED,
+                HEADER_MAGIC_ENCRYPTED,
+            ]
+            .contains(&magic)
+            && (magic == HEADER_MAGIC_ENCRYPTED) != (expected_magic == HEADER_MAGIC_ENCRYPTED)
+        {
+            return Err(DiskError::EncryptionMismatch.into());
+        }
+        let num_free_pages = read_u32(OFFSET_HEADER_NUM_FREE_PAGES) as usize;
+        if magic != expected_magic || num_free_pages > self.max_header_free_pages() {
+            return Err(DiskError::InvalidHeader.into());
+        }
+        if let Some(key) = &self.encryption_key {
+            let (header, seal) = header.split_at(BUSTUB_PAGE_SIZE - SEAL_SIZE);
+            let (nonce, tag) = seal.split_at(NONCE_SIZE);
+            if !key.open(nonce, header, &mut [], tag) {
+                return Err(DiskError::WrongKey.into());
+            }
+        }
+        Ok(PageAllocation {
+            next_page_id: read_u32(OFFSET_HEADER_NEXT_PAGE_ID),
+            free_pages: (0..num_free_pages)
+                .map(|i| read_u32(OFFSET_HEADER_FREE_PAGES + i * 4))
+                .collect(),
+            allocated_pages: read_u32(OFFSET_HEADER_ALLOCATED_PAGES) as usize,
+        })
+    }
 
-        if let Some(_f) = &self.flush_log_f {
-            // used for checking non-blocking flushing
-            // assert(flush_log_f_->wait_for(std::chrono::seconds(10)) ==
-            //     std::future_status::ready);
-            unimplemented!();
+    /// Persists the page allocation state to the header page of file.
+    fn write_header(&self, file: &DataFile, allocation: &PageAllocation) -> io::Result<()> {
+        let mut header = [0; BUSTUB_PAGE_SIZE];
+        let mut write_u32 = |offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        write_u32(OFFSET_HEADER_MAGIC, self.header_magic(file));
+        write_u32(OFFSET_HEADER_NEXT_PAGE_ID, allocation.next_page_id);
+        let free_pages: Vec<PageId> = allocation
+            .free_pages
+            .iter()
+            .copied()
+            .take(self.max_header_free_pages())
+            .collect();
+        write_u32(OFFSET_HEADER_NUM_FREE_PAGES, free_pages.len() as u32);
+        write_u32(
+            OFFSET_HEADER_ALLOCATED_PAGES,
+            allocation.allocated_pages as u32,
+        );
+        for (i, page_id) in free_pages.into_iter().enumerate() {
+            write_u32(OFFSET_HEADER_FREE_PAGES + i * 4, page_id);
+        }
+        if let Some(key) = &self.encryption_key {
+            let nonce = Self::new_nonce();
+            let (header, seal) = header.split_at_mut(BUSTUB_PAGE_SIZE - SEAL_SIZE);
+            let tag = key.seal(&nonce, header, &mut []);
+            seal[..NONCE_SIZE].copy_from_slice(&nonce);
+            seal[NONCE_SIZE..].copy_from_slice(&tag);
         }
 
-        self.num_flushes += 1;
-        // sequence write
-        if let Err(e) = self.log_io.write_all(log_data) {
-            // check for I/O error
-            panic!("I/O error while writing log: {:?}", e);
+        let mut db_io = file.io.lock().unwrap();
+        db_io.seek(SeekFrom::Start(0))?;
+        db_io.write_all(&header)?;
+        db_io.flush()?;
+        self.sync_after_write(&[&db_io])
+    }
+
+    /// Grows file by whole extents of zeroed pages until page number page_no
+    /// fits into it, and records the new size in the header.
+    fn grow_to_fit(&self, file: &DataFile, page_no: PageId) -> io::Result<()> {
+        let mut guard = file.allocation.lock().unwrap();
+        if (page_no as usize) < guard.allocated_pages {
+            return Ok(());
         }
-        // needs to flush to keep disk file in sync
-        self.log_io.flush().unwrap();
-        self.flush_log = false;
+        let mut allocation = guard.clone();
+        let extents = page_no as usize / self.extent_pages + 1;
+        allocation.allocated_pages = extents * self.extent_pages;
+        let len = self.page_offset(allocation.allocated_pages as PageId);
+        file.io.lock().unwrap().set_len(len as u64)?;
+        self.write_header(file, &allocation)?;
+        *guard = allocation;
+        Ok(())
     }
 
-    /// Read the contents of the log into the given memory area
-    /// Always read from the beginning and perform sequence read
-    /// @return: false means already reach the end
-    pub fn read_log(&mut self, log_data: &mut [u8], offset: usize) -> bool {
-        if offset >= self.log_io.metadata().unwrap().len() as usize {
-            debug!("Read past end of log file");
-            debug!("file size is {}", self.log_io.metadata().unwrap().len());
-            return false;
-        }
-        self.log_io.seek(SeekFrom::Start(offset as u64)).unwrap();
-        match self.log_io.read(log_data) {
-            Ok(read_count) => {
-                // if file ends before reading BUSTUB_PAGE_SIZE
-                if read_count < BUSTUB_PAGE_SIZE {
-                    debug!("Read less than a page");
-                    // fill the rest of the buffer with 0
-                    log_data[read_count..].fill(0);
+    /// Syncs files that were just written to if the sync policy asks for it.
+    fn sync_after_write(&self, files: &[&File]) -> io::Result<()> {
+        match self.sync_policy {
+            SyncPolicy::EveryWrite => {}
+            SyncPolicy::OnFlushCall => return Ok(()),
+            SyncPolicy::Periodic(period) => {
+                let mut last_sync = self.last_sync.lock().unwrap();
+                if last_sync.elapsed() < period {
+                    return Ok(());
                 }
+                *last_sync = Instant::now();
             }
-            Err(e) => panic!("I/O error while reading log: {:?}", e),
-        };
-        true
+        }
+        for file in files {
+            file.sync_data()?;
+        }
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
 
-    /// Returns the number of disk flushes.
-    pub fn get_num_flushes(&self) -> i32 {
-        self.num_flushes
+    /// Returns the magic the header starts with, which tells whether pages
+    /// are compressed or encrypted.
+    fn header_magic(&self, file: &DataFile) -> u32 {
+        if file.page_map.is_some() {
+            HEADER_MAGIC_COMPRESSED
+        } else if self.encryption_key.is_some() {
+            HEADER_MAGIC_ENCRYPTED
+        } else {
+            HEADER_MAGIC
+        }
     }
 
-    /// Returns true if the in-memory content has not been flushed yet.
-    pub fn get_flush_state(&self) -> bool {
-        self.flush_log
+    /// Returns how many deallocated page ids fit into the header.
+    fn max_header_free_pages(&self) -> usize {
+        match self.encryption_key {
+            Some(_) => MAX_HEADER_FREE_PAGES - SEAL_SIZE.div_ceil(std::mem::size_of::<PageId>()),
+            None => MAX_HEADER_FREE_PAGES,
+        }
     }
 
-    /// Returns the number of disk writes.
-    pub fn get_num_writes(&self) -> i32 {
-        self.num_writes
+    /// Returns the size of the slot a page is stored in.
+    fn slot_size(&self) -> usize {
+        match self.encryption_key {
+            Some(_) => SEAL_SIZE + BUSTUB_PAGE_SIZE,
+            None => BUSTUB_PAGE_SIZE,
+        }
     }
 
-    /// Sets the future which is used to check for non-blocking flushes.
-    fn set_flush_log_future(&mut self, f: Box<dyn Future<Output = ()> + Send + Sync>) {
-        self.flush_log_f = Some(f);
+    /// Returns the offset page number page_no is stored at in its file.
+    fn page_offset(&self, page_no: PageId) -> usize {
+        BUSTUB_PAGE_SIZE + page_no as usize * self.slot_size()
     }
 
-    /// Checks if the non-blocking flush future was set.
-    fn has_flush_log_future(&self) -> bool {
-        self.flush_log_f.is_some()
+    /// Returns a random nonce. An all zero nonce marks a page that was never
+    /// written, so that one is never returned.
+    fn new_nonce() -> [u8; NONCE_SIZE] {
+        let mut nonce = [0; NONCE_SIZE];
+        while nonce == [0; NONCE_SIZE] {
+            rand::thread_rng().fill(&mut nonce);
+        }
+        nonce
     }
-}
 
-mod tests {
-    use tempdir::TempDir;
+    /// Encrypts page page_id into the slot it is stored in.
+    fn seal_page(key: &EncryptionKey, page_id: PageId, page_data: &[u8]) -> Vec<u8> {
+        let nonce = Self::new_nonce();
+        let mut slot = vec![0; SEAL_SIZE + BUSTUB_PAGE_SIZE];
+        let (seal, data) = slot.split_at_mut(SEAL_SIZE);
+        data.copy_from_slice(page_data);
+        let tag = key.seal(&nonce, &page_id.to_le_bytes(), data);
+        seal[..NONCE_SIZE].copy_from_slice(&nonce);
+        seal[NONCE_SIZE..].copy_from_slice(&tag);
+        slot
+    }
 
-    use super::*;
-    use crate::common::config::BUSTUB_PAGE_SIZE;
+    /// Decrypts page page_id from the slot it is stored in. A slot that was
+    /// never written reads as zeros.
+    fn open_page(
+        key: &EncryptionKey,
+        page_id: PageId,
+        slot: &[u8],
+        page_data: &mut [u8],
+    ) -> io::Result<()> {
+        let (seal, data) = slot.split_at(SEAL_SIZE);
+        page_data.copy_from_slice(data);
+        if seal.iter().all(|&b| b == 0) {
+            debug!("Read a page that was never written");
+            page_data.fill(0);
+            return Ok(());
+        }
+        let (nonce, tag) = seal.split_at(NONCE_SIZE);
+        if !key.open(nonce, &page_id.to_le_bytes(), page_data, tag) {
+            page_data.fill(0);
+            return Err(DiskError::PageTampered(page_id).into());
+        }
+        Ok(())
+    }
 
-    #[test]
-    fn read_write_page() {
-        let mut buf = [0; BUSTUB_PAGE_SIZE];
-        let mut data = [0; BUSTUB_PAGE_SIZE];
+    /// Returns the slot page page_id is stored in, encrypting it if needed.
+    fn encode_page<'a>(&self, page_id: PageId, page_data: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.encryption_key {
+            Some(key) => Cow::Owned(Self::seal_page(key, page_id, page_data)),
+            None => Cow::Borrowed(page_data),
+        }
+    }
 
-        let dir = TempDir::new("test").unwrap();
-        let db_file = dir.path().join("test.db");
-        let mut dm = DiskManager::new(db_file.to_str().unwrap());
-        let test_str = b"A test string.";
-        data[..test_str.len()].copy_from_slice(test_str);
+    /// Writes the slot of page number page_no to file, growing the file if
+    /// needed, and drops the page from the pages read ahead.
+    fn write_slot(&self, file: &DataFile, page_no: PageId, slot: &[u8]) -> io::Result<()> {
+        let res = self.grow_to_fit(file, page_no).and_then(|_| {
+            let offset = self.page_offset(page_no);
+            // set write cursor to offset
+            let mut db_io = file.io.lock().unwrap();
+            db_io.seek(SeekFrom::Start(offset as u64))?;
+            db_io.write_all(slot)?;
+            // needs to flush to keep disk file in sync
+            db_io.flush()
+        });
+        // even a failed write may have changed the page in the file
+        file.read_ahead.lock().unwrap().invalidate(page_no);
+        res
+    }
 
-        dm.read_page(0, &mut buf); // tolerate empty read
+    /// Returns page page_id from the slot it is stored in, decrypting it if
+    /// needed.
+    fn decode_page(&self, page_id: PageId, slot: &[u8], page_data: &mut [u8]) -> io::Result<()> {
+        match &self.encryption_key {
+            Some(key) => Self::open_page(key, page_id, slot, page_data),
+            None => {
+                page_data.copy_from_slice(slot);
+                Ok(())
+            }
+        }
+    }
 
-        dm.write_page(0, &data);
-        dm.read_page(0, &mut buf);
-        assert_eq!(buf, data);
+    /// Writes the record of a compressed page. The record is overwritten in
+    /// place if the new one fits, otherwise it is appended to the file and
+    /// the old one's space is lost.
+    fn write_record(
+        &self,
+        file: &DataFile,
+        page_map: &Mutex<PageMap>,
+        page_no: PageId,
+        page_data: &[u8],
+    ) -> io::Result<()> {
+        let compressed = block::compress(page_data);
+        // a page that does not get smaller is stored as it is
+        let (flag, data) = if compressed.len() < BUSTUB_PAGE_SIZE {
+            (RECORD_COMPRESSED, &compressed[..])
+        } else {
+            (RECORD_RAW, page_data)
+        };
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + data.len());
+        record.push(flag);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
 
-        buf.fill(0);
-        dm.write_page(5, &data);
-        dm.read_page(5, &mut buf);
-        assert_eq!(buf, data);
+        let mut page_map = page_map.lock().unwrap();
+        let (offset, capacity) = match page_map.locations.get(&page_no) {
+            Some(&(offset, capacity)) if record.len() <= capacity => (offset, capacity),
+            _ => {
+                let offset = page_map.end;
+                page_map.end += record.len() as u64;
+                (offset, record.len())
+            }
+        };
+        let mut db_io = file.io.lock().unwrap();
+        db_io.seek(SeekFrom::Start(offset))?;
+        db_io.write_all(&record)?;
+        db_io.flush()?;
+        if page_map.locations.get(&page_no) != Some(&(offset, capacity)) {
+            page_map.set(page_no, offset, capacity)?;
+        }
+        self.sync_after_write(&[&db_io, &page_map.io])
     }
 
-    #[test]
-    fn read_write_log() {
-        let mut buf = [0; 14];
+    /// Reads the record of a compressed page. A page without a record reads
+    /// as zeros, a record that is cut short or does not decompress to a full
+    /// page is an error.
+    fn read_record(
+        &self,
+        file: &DataFile,
+        page_map: &Mutex<PageMap>,
+        page_id: PageId,
+        page_data: &mut [u8],
+    ) -> io::Result<()> {
+        let page_map = page_map.lock().unwrap();
+        let page_no = page_no_of(page_id);
+        let Some(&(offset, capacity)) = page_map.locations.get(&page_no) else {
+            if !file.allocation.lock().unwrap().in_range(page_no) {
+                return Err(DiskError::PageOutOfRange(page_id).into());
+            }
+            debug!("Read a page that was never written");
+            page_data.fill(0);
+            return Ok(());
+        };
+        self.num_reads.fetch_add(1, Ordering::Relaxed);
+        let mut db_io = file.io.lock().unwrap();
+        db_io.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; RECORD_HEADER_SIZE];
+        db_io.read_exact(&mut header)?;
+        let len = u32::from_le_bytes(header[1..].try_into().unwrap()) as usize;
+        if RECORD_HEADER_SIZE + len > capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record of page {} is longer than its space", page_id),
+            ));
+        }
+        let mut data = vec![0; len];
+        db_io.read_exact(&mut data)?;
+        let valid = match header[0] {
+            RECORD_RAW if len == BUSTUB_PAGE_SIZE => {
+                page_data.copy_from_slice(&data);
+                true
+            }
+            RECORD_COMPRESSED => {
+                matches!(
+                    block::decompress_into(&data, page_data),
+                    Ok(BUSTUB_PAGE_SIZE)
+                )
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record of page {} is corrupt", page_id),
+            ));
+        }
+        Ok(())
+    }
 
-        let dir = TempDir::new("test").unwrap();
-        let db_file = dir.path().join("test.db");
-        let mut dm = DiskManager::new(db_file.to_str().unwrap());
-        let test_str = b"A test string.";
+    /// Reads into buf from offset of file until buf is full or the file ends,
+    /// and returns how many bytes were read.
+    fn read_at(&self, file: &DataFile, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let mut db_io = file.io.lock().unwrap();
+        // check if read beyond file length
+        if offset >= db_io.metadata()?.len() as usize {
+            debug!("Read past end of file");
+            return Ok(0);
+        }
+        // set read cursor to offset
+        db_io.seek(SeekFrom::Start(offset as u64))?;
+        let mut read_count = 0;
+        while read_count < buf.len() {
+            self.num_reads.fetch_add(1, Ordering::Relaxed);
+            match db_io.read(&mut buf[read_count..]) {
+                Ok(0) => break,
+                Ok(n) => read_count += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(read_count)
+    }
 
-        dm.read_log(&mut buf, 0); // tolerate empty read
+    /// Fails if only read_count bytes of the slot of page page_id, which
+    /// starts inside the file, are in the file.
+    fn check_short_read(&self, page_id: PageId, read_count: usize) -> io::Result<()> {
+        if read_count > 0 && read_count < self.slot_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("page {} is cut short", page_id),
+            ));
+        }
+        Ok(())
+    }
 
-        dm.write_log(test_str);
-        dm.read_log(&mut buf, 0);
-        assert_eq!(&buf, test_str);
+    /// Reads page page_id along with the pages after it in its file, and
+    /// keeps the ones that are fully in the file in read_ahead. A page past
+    /// the end of the file reads as zeros.
+    fn read_ahead(
+        &self,
+        file: &DataFile,
+        read_ahead: &mut ReadAhead,
+        page_id: PageId,
+        page_data: &mut [u8],
+    ) -> io::Result<()> {
+        read_ahead.pages.clear();
+        let slot_size = self.slot_size();
+        let mut buf = vec![0; (self.read_ahead_pages + 1) * slot_size];
+        let page_no = page_no_of(page_id);
+        let read_count = self.read_at(file, self.page_offset(page_no), &mut buf)?;
+        self.check_short_read(page_id, read_count.min(slot_size))?;
+        let mut slots = buf[..read_count.max(slot_size)].chunks_exact(slot_size);
+        self.decode_page(page_id, slots.next().unwrap(), page_data)?;
+        let mut pages = vec![0; slots.len() * BUSTUB_PAGE_SIZE];
+        for (i, slot) in slots.enumerate() {
+            let page = &mut pages[i * BUSTUB_PAGE_SIZE..(i + 1) * BUSTUB_PAGE_SIZE];
+            // a page that cannot be read fails when it is read for real
+            if self
+                .decode_page(page_id + 1 + i as PageId, slot, page)
+                .is_err()
+            {
+                pages.truncate(i * BUSTUB_PAGE_SIZE);
+                break;
+            }
+        }
+        read_ahead.start = page_no + 1;
+        read_ahead.pages = pages;
+        Ok(())
+    }
+
+    /// Returns the number of reads from the data files.
+    pub fn get_num_reads(&self) -> i32 {
+        self.num_reads.load(Ordering::Relaxed)
+    }
+
+    /// Takes the advisory lock on the db file, retrying until
+    /// `options.lock_timeout` expires.
+    fn lock_db_file(db_io: &File, options: &DiskManagerOptions) -> io::Result<()> {
+        let deadline = options.lock_timeout.map(|t| Instant::now() + t);
+        loop {
+            let res = if options.read_only {
+                db_io.try_lock_shared()
+            } else {
+                db_io.try_lock()
+            };
+            match res {
+                Ok(()) => return Ok(()),
+                Err(TryLockError::Error(e)) => return Err(e),
+                Err(TryLockError::WouldBlock) => match deadline {
+                    Some(deadline) if Instant::now() < deadline => {
+                        thread::sleep(LOCK_RETRY_INTERVAL)
+                    }
+                    _ => return Err(DiskError::DatabaseInUse.into()),
+                },
+            }
+        }
+    }
+}
+
+impl DiskManagerTrait for DiskManager {
+    /// Write a page to the database file.
+    fn write_page(&self, page_id: PageId, page_data: &[u8]) -> io::Result<()> {
+        assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
+        if let Some(dw) = &self.double_write {
+            return self.double_write_pages(dw, &[(page_id, page_data)]);
+        }
+
+        self.num_writes.fetch_add(1, Ordering::Relaxed);
+        let _timer = self.io_counters.writes.start();
+        let file = self.file_of(page_id)?;
+        let page_no = page_no_of(page_id);
+        if let Some(page_map) = &file.page_map {
+            return self.write_record(&file, page_map, page_no, page_data);
+        }
+        let slot = self.encode_page(page_id, page_data);
+        self.write_slot(&file, page_no, &slot)?;
+        let db_io = file.io.lock().unwrap();
+        self.sync_after_write(&[&db_io])
+    }
+
+    /// Writes a batch of pages. With double writes on, the whole batch goes
+    /// through the double-write file at once.
+    fn write_pages(&self, pages: &[(PageId, &[u8])]) -> io::Result<()> {
+        if let Some(dw) = &self.double_write {
+            return self.double_write_pages(dw, pages);
+        }
+        for &(page_id, page_data) in pages {
+            self.write_page(page_id, page_data)?;
+        }
+        Ok(())
+    }
+
+    /// Read a page from the database file. An allocated page past the end of
+    /// the file reads as zeros, a page past the allocated ones fails with
+    /// `DiskError::PageOutOfRange` and a page only partly in the file with
+    /// `io::ErrorKind::UnexpectedEof`. An encrypted page that does not
+    /// authenticate fails with `DiskError::PageTampered`.
+    ///
+    /// Once a few pages of a data file are read in order, the next read also
+    /// reads the pages after it, which the following reads are served from.
+    fn read_page(&self, page_id: PageId, page_data: &mut [u8]) -> io::Result<()> {
+        assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
+        let _timer = self.io_counters.reads.start();
+        let file = self.file_of(page_id)?;
+        if let Some(page_map) = &file.page_map {
+            return self.read_record(&file, page_map, page_id, page_data);
+        }
+        let page_no = page_no_of(page_id);
+        if !file.allocation.lock().unwrap().in_range(page_no) {
+            return Err(DiskError::PageOutOfRange(page_id).into());
+        }
+
+        let mut read_ahead = file.read_ahead.lock().unwrap();
+        let sequential = read_ahead.record(page_no);
+        if let Some(data) = read_ahead.get(page_no) {
+            page_data.copy_from_slice(data);
+            return Ok(());
+        }
+        if sequential && self.read_ahead_pages > 0 {
+            return self.read_ahead(&file, &mut read_ahead, page_id, page_data);
+        }
+        drop(read_ahead);
+
+        let offset = self.page_offset(page_no);
+        if let Some(key) = &self.encryption_key {
+            let mut slot = vec![0; self.slot_size()];
+            let read_count = self.read_at(&file, offset, &mut slot)?;
+            self.check_short_read(page_id, read_count)?;
+            return Self::open_page(key, page_id, &slot, page_data);
+        }
+        let read_count = self.read_at(&file, offset, page_data)?;
+        self.check_short_read(page_id, read_count)?;
+        if read_count == 0 {
+            debug!("Read a page past the end of the file");
+            page_data.fill(0);
+        }
+        Ok(())
+    }
+
+    /// Allocates a page id, reusing a deallocated one before the file is
+    /// extended.
+    ///
+    /// Fails if the header cannot be written, in which case no page id is
+    /// handed out.
+    fn allocate_page(
+        &self,
+        file_id: FileId,
+        num_instances: usize,
+        instance_index: usize,
+    ) -> io::Result<PageId> {
+        let file = self.file_of(make_page_id(file_id, 0))?;
+        let mut guard = file.allocation.lock().unwrap();
+        let mut allocation = guard.clone();
+        let page_id = allocation.allocate(file_id, num_instances, instance_index)?;
+        self.write_header(&file, &allocation)?;
+        *guard = allocation;
+        Ok(page_id)
+    }
+
+    /// Deallocates a page id so a later allocate_page can reuse it. Ids that
+    /// are not allocated are ignored.
+    fn deallocate_page(&self, page_id: PageId) -> io::Result<()> {
+        let file = self.file_of(page_id)?;
+        let page_no = page_no_of(page_id);
+        let mut allocation = file.allocation.lock().unwrap();
+        if allocation.deallocate(page_no) {
+            if let Err(e) = self.write_header(&file, &allocation) {
+                allocation.free_pages.remove(&page_no);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the next data file, `<name>.<file id>.db` next to the db file.
+    fn create_file(&self) -> io::Result<FileId> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "database is opened read-only",
+            ));
+        }
+        let mut files = self.files.write().unwrap();
+        let file_id = files.keys().max().map_or(MAIN_FILE_ID, |&max| max + 1);
+        if file_id > MAX_FILE_ID {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                "no data file ids left",
+            ));
+        }
+        let path = self.data_file_path(file_id);
+        let io = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        files.insert(file_id, Arc::new(self.load_file(io, path)?));
+        Ok(file_id)
+    }
+
+    /// Removes the data file and its page map from the disk.
+    fn delete_file(&self, file_id: FileId) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "database is opened read-only",
+            ));
+        }
+        if file_id == MAIN_FILE_ID {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the main data file cannot be deleted",
+            ));
+        }
+        let file = self.file_of(make_page_id(file_id, 0))?;
+        std::fs::remove_file(&file.path)?;
+        if file.page_map.is_some() {
+            std::fs::remove_file(file.path.with_extension("map"))?;
+        }
+        self.files.write().unwrap().remove(&file_id);
+        Ok(())
+    }
+
+    /// Write the contents of the log into disk file
+    /// Only return when sync is done, and only perform sequence write
+    fn write_log(&self, log_data: &[u8]) -> io::Result<()> {
+        if log_data.is_empty() {
+            // no effect on num_flushes_ if log buffer is empty
+            return Ok(());
+        }
+
+        let _timer = self.io_counters.log_flushes.start();
+        let mut log = self.log.lock().unwrap();
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        // sequence write
+        log.write_all(log_data)?;
+        // needs to flush to keep disk file in sync
+        log.flush()
+    }
+
+    /// Read the contents of the log into the given memory area
+    /// Always read from the beginning and perform sequence read
+    /// @return: false means already reach the end
+    fn read_log(&self, log_data: &mut [u8], offset: usize) -> io::Result<bool> {
+        let mut log = self.log.lock().unwrap();
+        let file_size = log.metadata()?.len() as usize;
+        if offset >= file_size {
+            debug!("Read past end of log file");
+            debug!("file size is {}", file_size);
+            return Ok(false);
+        }
+        log.seek(SeekFrom::Start(offset as u64))?;
+        let read_count = log.read(log_data)?;
+        // if file ends before filling the buffer
+        if read_count < log_data.len() {
+            debug!("Read less than requested");
+            // fill the rest of the buffer with 0
+            log_data[read_count..].fill(0);
+        }
+        Ok(true)
+    }
+
+    /// Syncs the data files, their page map files if any, and the log file.
+    fn sync(&self) -> io::Result<()> {
+        let files: Vec<_> = self.files.read().unwrap().values().cloned().collect();
+        for file in files {
+            file.io.lock().unwrap().sync_all()?;
+            if let Some(page_map) = &file.page_map {
+                page_map.lock().unwrap().io.sync_all()?;
+            }
+        }
+        self.log.lock().unwrap().sync_all()?;
+        // Writers take last_sync while holding the file locks, so it must
+        // not be held across the syncs above.
+        *self.last_sync.lock().unwrap() = Instant::now();
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the number of disk flushes.
+    fn get_num_flushes(&self) -> i32 {
+        self.num_flushes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of disk writes.
+    fn get_num_writes(&self) -> i32 {
+        self.num_writes.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many data pages the data files have room for. With
+    /// compression, that is the number of pages that have a record.
+    fn allocated_pages(&self) -> usize {
+        let files = self.files.read().unwrap();
+        files
+            .values()
+            .map(|file| match &file.page_map {
+                Some(page_map) => page_map.lock().unwrap().locations.len(),
+                None => file.allocation.lock().unwrap().allocated_pages,
+            })
+            .sum()
+    }
+
+    fn get_io_stats(&self) -> IoStats {
+        self.io_counters.get()
+    }
+}
+
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::common::config::BUSTUB_PAGE_SIZE;
+
+    #[test]
+    fn read_write_page() {
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        let mut data = [0; BUSTUB_PAGE_SIZE];
+
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+        let test_str = b"A test string.";
+        data[..test_str.len()].copy_from_slice(test_str);
+
+        // a page that was never allocated cannot be read
+        assert!(dm.read_page(0, &mut buf).is_err());
+
+        dm.write_page(0, &data).unwrap();
+        dm.read_page(0, &mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        buf.fill(0);
+        dm.write_page(5, &data).unwrap();
+        dm.read_page(5, &mut buf).unwrap();
+        assert_eq!(buf, data);
+    }
+
+    fn assert_in_use(res: io::Result<DiskManager>) {
+        match res {
+            Err(e) => assert_eq!(
+                DiskError::from_io_error(&e),
+                Some(&DiskError::DatabaseInUse)
+            ),
+            Ok(_) => panic!("expected the database file to be locked"),
+        }
+    }
+
+    #[test]
+    fn lock_rejects_second_open() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+
+        let dm = DiskManager::new(db_file).unwrap();
+        assert_in_use(DiskManager::with_options(
+            db_file,
+            DiskManagerOptions::default(),
+        ));
+        // a reader must not sneak in next to a writer either
+        let read_only = DiskManagerOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        assert_in_use(DiskManager::with_options(db_file, read_only));
+
+        // the lock goes away together with the disk manager
+        drop(dm);
+        assert!(DiskManager::with_options(db_file, DiskManagerOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn lock_shared_between_readers() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        DiskManager::new(db_file)
+            .unwrap()
+            .write_page(0, &[1; BUSTUB_PAGE_SIZE])
+            .unwrap();
+
+        let read_only = DiskManagerOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let dm1 = DiskManager::with_options(db_file, read_only.clone()).unwrap();
+        let _dm2 = DiskManager::with_options(db_file, read_only).unwrap();
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        dm1.read_page(0, &mut buf).unwrap();
+
+        // a writer has to wait until every reader is gone
+        assert_in_use(DiskManager::with_options(
+            db_file,
+            DiskManagerOptions::default(),
+        ));
+    }
+
+    #[test]
+    fn lock_wait_with_timeout() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap().to_string();
+
+        let dm = DiskManager::new(&db_file).unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(dm);
+        });
+        let options = DiskManagerOptions {
+            lock_timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        assert!(DiskManager::with_options(&db_file, options).is_ok());
+        holder.join().unwrap();
+
+        // with a short timeout the open gives up
+        let _dm = DiskManager::new(&db_file).unwrap();
+        let options = DiskManagerOptions {
+            lock_timeout: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        assert_in_use(DiskManager::with_options(&db_file, options));
+    }
+
+    #[test]
+    fn allocate_reuses_deallocated_pages() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+
+        let dm = DiskManager::new(db_file).unwrap();
+        for i in 0..5 {
+            assert_eq!(i, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+        }
+        dm.deallocate_page(3).unwrap();
+        dm.deallocate_page(1).unwrap();
+        dm.deallocate_page(1).unwrap();
+        // never allocated, ignored
+        dm.deallocate_page(42).unwrap();
+        assert_eq!(1, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+
+        // Scenario: the free pages survive a reopen.
+        drop(dm);
+        let dm = DiskManager::new(db_file).unwrap();
+        assert_eq!(3, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+        assert_eq!(5, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+    }
+
+    #[test]
+    fn allocate_with_instances() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+
+        // ids passed over by instance 2 are kept for instances 0 and 1
+        assert_eq!(2, dm.allocate_page(MAIN_FILE_ID, 3, 2).unwrap());
+        assert_eq!(5, dm.allocate_page(MAIN_FILE_ID, 3, 2).unwrap());
+        assert_eq!(0, dm.allocate_page(MAIN_FILE_ID, 3, 0).unwrap());
+        assert_eq!(1, dm.allocate_page(MAIN_FILE_ID, 3, 1).unwrap());
+        assert_eq!(4, dm.allocate_page(MAIN_FILE_ID, 3, 1).unwrap());
+        assert_eq!(3, dm.allocate_page(MAIN_FILE_ID, 3, 0).unwrap());
+        assert_eq!(6, dm.allocate_page(MAIN_FILE_ID, 3, 0).unwrap());
+    }
+
+    #[test]
+    fn reject_invalid_header() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        std::fs::write(&db_file, [1; BUSTUB_PAGE_SIZE]).unwrap();
+
+        match DiskManager::with_options(db_file.to_str().unwrap(), Default::default()) {
+            Err(e) => assert_eq!(
+                DiskError::from_io_error(&e),
+                Some(&DiskError::InvalidHeader)
+            ),
+            Ok(_) => panic!("expected the header to be rejected"),
+        }
+    }
+
+    #[test]
+    fn read_write_log() {
+        let mut buf = [0; 14];
+
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+        let test_str = b"A test string.";
+
+        assert!(!dm.read_log(&mut buf, 0).unwrap()); // tolerate empty read
+
+        dm.write_log(test_str).unwrap();
+        assert!(dm.read_log(&mut buf, 0).unwrap());
+        assert_eq!(&buf, test_str);
+    }
+
+    #[test]
+    fn read_page_boundaries() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+        dm.write_page(0, &[2; BUSTUB_PAGE_SIZE]).unwrap();
+        dm.write_page(4, &[4; BUSTUB_PAGE_SIZE]).unwrap();
+        let allocated = dm.allocated_pages() as PageId;
+
+        // cut the file off in the middle of page 4
+        let truncated = dm.page_offset(4) + 100;
+        OpenOptions::new()
+            .write(true)
+            .open(&db_file)
+            .unwrap()
+            .set_len(truncated as u64)
+            .unwrap();
+
+        // Scenario: a page in the file reads back, an allocated page past the
+        // end of the file reads as zeros, a page past the allocated ones and a
+        // page that is cut short cannot be read.
+        let cases: [(PageId, Result<u8, io::ErrorKind>); 6] = [
+            (0, Ok(2)),
+            (1, Ok(0)),
+            (4, Err(io::ErrorKind::UnexpectedEof)),
+            (allocated - 1, Ok(0)),
+            (allocated, Err(io::ErrorKind::InvalidInput)),
+            (allocated + 1, Err(io::ErrorKind::InvalidInput)),
+        ];
+        for (page_id, expected) in cases {
+            let mut buf = [1; BUSTUB_PAGE_SIZE];
+            match (dm.read_page(page_id, &mut buf), expected) {
+                (Ok(()), Ok(value)) => assert_eq!([value; BUSTUB_PAGE_SIZE], buf),
+                (Err(e), Err(kind)) => assert_eq!(kind, e.kind(), "page {}", page_id),
+                (res, _) => panic!("page {}: unexpected {:?}", page_id, res),
+            }
+        }
+    }
+
+    #[test]
+    fn open_in_missing_directory() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("missing").join("test.db");
+
+        match DiskManager::new(db_file.to_str().unwrap()) {
+            Err(e) => assert_eq!(io::ErrorKind::NotFound, e.kind()),
+            Ok(_) => panic!("expected the open to fail"),
+        }
+        assert!(!db_file.exists());
+    }
+
+    fn compressed() -> DiskManagerOptions {
+        DiskManagerOptions {
+            compress_pages: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let dir = TempDir::new("test").unwrap();
+        let plain_file = dir.path().join("plain.db");
+        let compressed_file = dir.path().join("compressed.db");
+        let plain = DiskManager::new(plain_file.to_str().unwrap()).unwrap();
+        let dm =
+            DiskManager::with_options(compressed_file.to_str().unwrap(), compressed()).unwrap();
+
+        // Scenario: repetitive pages take far less space than in a plain file,
+        // random ones about the same.
+        let mut rng = StdRng::seed_from_u64(0);
+        let pages: Vec<[u8; BUSTUB_PAGE_SIZE]> = (0..20)
+            .map(|i| {
+                let mut data = [0; BUSTUB_PAGE_SIZE];
+                if i % 2 == 0 {
+                    data.iter_mut()
+                        .enumerate()
+                        .for_each(|(j, b)| *b = b"wide tuple;"[j % 11]);
+                } else {
+                    rng.fill(&mut data[..]);
+                }
+                data
+            })
+            .collect();
+        for (i, data) in pages.iter().enumerate() {
+            plain.write_page(i as PageId, data).unwrap();
+            dm.write_page(i as PageId, data).unwrap();
+        }
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for (i, data) in pages.iter().enumerate() {
+            dm.read_page(i as PageId, &mut buf).unwrap();
+            assert_eq!(data, &buf);
+        }
+        let plain_len = std::fs::metadata(&plain_file).unwrap().len();
+        let compressed_len = std::fs::metadata(&compressed_file).unwrap().len();
+        assert!(compressed_len < plain_len * 6 / 10);
+
+        // Scenario: a page that was neither written nor allocated cannot be
+        // read.
+        let err = dm.read_page(100, &mut buf).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        // Scenario: a rewrite that no longer fits in place moves the page, and
+        // the page map survives a reopen.
+        dm.write_page(0, &pages[1]).unwrap();
+        dm.write_page(1, &pages[0]).unwrap();
+        drop(dm);
+        let dm =
+            DiskManager::with_options(compressed_file.to_str().unwrap(), compressed()).unwrap();
+        dm.read_page(0, &mut buf).unwrap();
+        assert_eq!(pages[1], buf);
+        dm.read_page(1, &mut buf).unwrap();
+        assert_eq!(pages[0], buf);
+        for (i, data) in pages.iter().enumerate().skip(2) {
+            dm.read_page(i as PageId, &mut buf).unwrap();
+            assert_eq!(data, &buf);
+        }
+    }
+
+    #[test]
+    fn compressed_detects_damage() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let dm = DiskManager::with_options(db_file, compressed()).unwrap();
+        dm.write_page(0, &[1; BUSTUB_PAGE_SIZE]).unwrap();
+        drop(dm);
+
+        // Scenario: the compression setting has to match the file.
+        match DiskManager::new(db_file) {
+            Err(e) => assert_eq!(
+                DiskError::from_io_error(&e),
+                Some(&DiskError::InvalidHeader)
+            ),
+            Ok(_) => panic!("expected the header to be rejected"),
+        }
+
+        // Scenario: a record cut short is a read error, not a short page.
+        let len = std::fs::metadata(db_file).unwrap().len();
+        let file = OpenOptions::new().write(true).open(db_file).unwrap();
+        file.set_len(len - 1).unwrap();
+        drop(file);
+        let dm = DiskManager::with_options(db_file, compressed()).unwrap();
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        let err = dm.read_page(0, &mut buf).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    fn encrypted(key: u8) -> DiskManagerOptions {
+        DiskManagerOptions {
+            encryption_key: Some(EncryptionKey::new([key; KEY_SIZE])),
+            ..Default::default()
+        }
+    }
+
+    fn assert_open_error(res: io::Result<DiskManager>, expected: DiskError) {
+        match res {
+            Err(e) => assert_eq!(DiskError::from_io_error(&e), Some(&expected)),
+            Ok(_) => panic!("expected the open to fail with {:?}", expected),
+        }
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let secret = b"the secret tuple";
+        let page = |page_id: PageId| {
+            let mut page = [page_id as u8; BUSTUB_PAGE_SIZE];
+            page[100..100 + secret.len()].copy_from_slice(secret);
+            page
+        };
+
+        // Scenario: pages read back as they were written, including through
+        // read-ahead, and allocated pages never written read as zeros.
+        let dm = DiskManager::with_options(db_file, encrypted(1)).unwrap();
+        for page_id in 0..20 {
+            dm.write_page(page_id, &page(page_id)).unwrap();
+        }
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for page_id in 0..20 {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!(page(page_id), buf);
+        }
+        assert!(dm.get_num_reads() < 20);
+        dm.read_page(30, &mut buf).unwrap();
+        assert_eq!([0; BUSTUB_PAGE_SIZE], buf);
+        assert_eq!(DEFAULT_EXTENT_PAGES, dm.allocated_pages());
+        drop(dm);
+
+        // Scenario: the file does not contain the plaintext.
+        let raw = std::fs::read(db_file).unwrap();
+        assert!(!raw.windows(secret.len()).any(|w| w == secret));
+
+        // Scenario: the same key opens the file again.
+        let dm = DiskManager::with_options(db_file, encrypted(1)).unwrap();
+        assert_eq!(DEFAULT_EXTENT_PAGES, dm.allocated_pages());
+        dm.read_page(7, &mut buf).unwrap();
+        assert_eq!(page(7), buf);
+    }
+
+    #[test]
+    fn encrypted_rejects_wrong_key() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let plain_file = dir.path().join("plain.db");
+        let plain_file = plain_file.to_str().unwrap();
+        let dm = DiskManager::with_options(db_file, encrypted(1)).unwrap();
+        dm.write_page(0, &[1; BUSTUB_PAGE_SIZE]).unwrap();
+        drop(dm);
+        drop(DiskManager::new(plain_file).unwrap());
+
+        // Scenario: the key has to be the one the file was created with, and
+        // a key is needed exactly when the file is encrypted.
+        assert_open_error(
+            DiskManager::with_options(db_file, encrypted(2)),
+            DiskError::WrongKey,
+        );
+        assert_open_error(DiskManager::new(db_file), DiskError::EncryptionMismatch);
+        assert_open_error(
+            DiskManager::with_options(plain_file, encrypted(1)),
+            DiskError::EncryptionMismatch,
+        );
+
+        // Scenario: encryption does not go with compression.
+        let options = DiskManagerOptions {
+            compress_pages: true,
+            ..encrypted(1)
+        };
+        let err = DiskManager::with_options(db_file, options).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            "EncryptionKey(..)",
+            format!("{:?}", EncryptionKey::new([1; 32]))
+        );
+    }
+
+    #[test]
+    fn encrypted_detects_tampering() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let dm = DiskManager::with_options(db_file, encrypted(1)).unwrap();
+        for page_id in 0..3 {
+            dm.write_page(page_id, &[page_id as u8 + 1; BUSTUB_PAGE_SIZE])
+                .unwrap();
+        }
+        drop(dm);
+
+        let slot_size = SEAL_SIZE + BUSTUB_PAGE_SIZE;
+        let slot_offset = |page_no: usize| BUSTUB_PAGE_SIZE + page_no * slot_size;
+        let mut raw = std::fs::read(db_file).unwrap();
+        // Scenario: a flipped bit in the encrypted data of page 1
+        raw[slot_offset(1) + SEAL_SIZE + 10] ^= 1;
+        // Scenario: page 0 replaced with a copy of page 2, which is
+        // authentic, but not for page 0
+        raw.copy_within(slot_offset(2)..slot_offset(3), slot_offset(0));
+        std::fs::write(db_file, &raw).unwrap();
+
+        let options = DiskManagerOptions {
+            read_ahead_pages: 0,
+            ..encrypted(1)
+        };
+        let dm = DiskManager::with_options(db_file, options).unwrap();
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for page_id in [0, 1] {
+            let err = dm.read_page(page_id, &mut buf).unwrap_err();
+            assert_eq!(
+                DiskError::from_io_error(&err),
+                Some(&DiskError::PageTampered(page_id))
+            );
+        }
+        dm.read_page(2, &mut buf).unwrap();
+        assert_eq!([3; BUSTUB_PAGE_SIZE], buf);
+        drop(dm);
+
+        // Scenario: a changed header fails like a wrong key.
+        raw[OFFSET_HEADER_NEXT_PAGE_ID] ^= 1;
+        std::fs::write(db_file, &raw).unwrap();
+        assert_open_error(
+            DiskManager::with_options(db_file, encrypted(1)),
+            DiskError::WrongKey,
+        );
+    }
+
+    fn double_write() -> DiskManagerOptions {
+        DiskManagerOptions {
+            double_write: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn double_write_repairs_torn_page() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dw_file = dir.path().join("test.dw");
+        let db_file = db_file.to_str().unwrap();
+        let dm = DiskManager::with_options(db_file, double_write()).unwrap();
+        for page_id in 0..4 {
+            dm.write_page(page_id, &[page_id as u8 + 1; BUSTUB_PAGE_SIZE])
+                .unwrap();
+        }
+        // Scenario: nothing is left in the double-write file after a write.
+        assert_eq!(0, std::fs::metadata(&dw_file).unwrap().len());
+        drop(dm);
+
+        // Scenario: a crash in the middle of a batch rewriting pages 1, 3 and
+        // 2. The records of pages 1 and 3 made it to the double-write file,
+        // the one of page 2 is cut short. The write of page 3 was torn, the
+        // data file ends halfway through it.
+        let record = |page_id: PageId, value: u8| {
+            let slot = [value; BUSTUB_PAGE_SIZE];
+            let mut record = page_id.to_le_bytes().to_vec();
+            record.extend_from_slice(&crc32fast::hash(&slot).to_le_bytes());
+            record.extend_from_slice(&slot);
+            record
+        };
+        let mut dw = record(1, 11);
+        dw.extend(record(3, 13));
+        dw.extend(&record(2, 12)[..100]);
+        std::fs::write(&dw_file, &dw).unwrap();
+        let mut file = OpenOptions::new().write(true).open(db_file).unwrap();
+        let torn_at = 4 * BUSTUB_PAGE_SIZE + BUSTUB_PAGE_SIZE / 2;
+        file.seek(SeekFrom::Start(4 * BUSTUB_PAGE_SIZE as u64))
+            .unwrap();
+        file.write_all(&[13; BUSTUB_PAGE_SIZE / 2]).unwrap();
+        file.set_len(torn_at as u64).unwrap();
+        drop(file);
+
+        // Scenario: the next open repairs the pages from their intact records,
+        // even with double writes off, and removes the double-write file.
+        let dm = DiskManager::new(db_file).unwrap();
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for (page_id, expected) in [(0, 1), (1, 11), (2, 3), (3, 13)] {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!([expected; BUSTUB_PAGE_SIZE], buf, "page {}", page_id);
+        }
+        assert!(!dw_file.exists());
+    }
+
+    #[test]
+    fn double_write_batch() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let dm = DiskManager::with_options(db_file, double_write()).unwrap();
+
+        // Scenario: a batch is synced once as a whole, not page by page.
+        let pages: Vec<_> = (0..10u8).map(|i| [i + 1; BUSTUB_PAGE_SIZE]).collect();
+        let batch: Vec<_> = pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| (i as PageId, &page[..]))
+            .collect();
+        let flushes = dm.get_num_flushes();
+        dm.write_pages(&batch).unwrap();
+        assert_eq!(flushes + 1, dm.get_num_flushes());
+        assert_eq!(10, dm.get_num_writes());
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for (page_id, page) in batch {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!(page, buf);
+        }
+        drop(dm);
+
+        // Scenario: encrypted pages go through the double-write file too.
+        let options = DiskManagerOptions {
+            double_write: true,
+            ..encrypted(1)
+        };
+        let encrypted_file = dir.path().join("encrypted.db");
+        let encrypted_file = encrypted_file.to_str().unwrap();
+        let dm = DiskManager::with_options(encrypted_file, options.clone()).unwrap();
+        dm.write_page(2, &[5; BUSTUB_PAGE_SIZE]).unwrap();
+        drop(dm);
+        let dm = DiskManager::with_options(encrypted_file, options).unwrap();
+        dm.read_page(2, &mut buf).unwrap();
+        assert_eq!([5; BUSTUB_PAGE_SIZE], buf);
+
+        // Scenario: double writes do not go with compression.
+        let options = DiskManagerOptions {
+            compress_pages: true,
+            ..double_write()
+        };
+        let err = DiskManager::with_options(db_file, options).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn sync_policies() {
+        let dir = TempDir::new("test").unwrap();
+        // the first write also grows the file and writes the header
+        for (i, (sync_policy, expected)) in [
+            (SyncPolicy::EveryWrite, 11),
+            (SyncPolicy::OnFlushCall, 0),
+            (SyncPolicy::Periodic(Duration::from_secs(3600)), 0),
+            (SyncPolicy::Periodic(Duration::ZERO), 11),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let db_file = dir.path().join(format!("test{}.db", i));
+            let db_file = db_file.to_str().unwrap();
+            let options = DiskManagerOptions {
+                sync_policy,
+                ..Default::default()
+            };
+            let dm = DiskManager::with_options(db_file, options.clone()).unwrap();
+
+            // Scenario: 10 page writes sync as often as the policy says, an
+            // explicit sync always does.
+            let flushes_before = dm.get_num_flushes();
+            for page_id in 0..10 {
+                dm.write_page(page_id, &[page_id as u8 + 1; BUSTUB_PAGE_SIZE])
+                    .unwrap();
+            }
+            assert_eq!(
+                expected,
+                dm.get_num_flushes() - flushes_before,
+                "{:?}",
+                sync_policy
+            );
+            dm.sync().unwrap();
+            assert_eq!(
+                expected + 1,
+                dm.get_num_flushes() - flushes_before,
+                "{:?}",
+                sync_policy
+            );
+
+            // Scenario: the pages survive a reopen.
+            drop(dm);
+            let dm = DiskManager::with_options(db_file, options).unwrap();
+            let mut buf = [0; BUSTUB_PAGE_SIZE];
+            for page_id in 0..10 {
+                dm.read_page(page_id, &mut buf).unwrap();
+                assert_eq!([page_id as u8 + 1; BUSTUB_PAGE_SIZE], buf);
+            }
+        }
+    }
+
+    #[test]
+    fn sync_while_writing() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let options = DiskManagerOptions {
+            sync_policy: SyncPolicy::Periodic(Duration::from_nanos(1)),
+            ..Default::default()
+        };
+        let dm = DiskManager::with_options(db_file.to_str().unwrap(), options).unwrap();
+
+        // Scenario: writes sync under the file lock while explicit syncs run
+        // alongside, and neither side waits on the other forever.
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..200 {
+                    dm.write_page(i % 10, &[i as u8; BUSTUB_PAGE_SIZE]).unwrap();
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..200 {
+                    dm.sync().unwrap();
+                }
+            });
+        });
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        dm.read_page(9, &mut buf).unwrap();
+        assert_eq!([199; BUSTUB_PAGE_SIZE], buf);
+    }
+
+    #[test]
+    fn grow_in_extents() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let options = DiskManagerOptions {
+            extent_pages: 64,
+            ..Default::default()
+        };
+        let file_len = || std::fs::metadata(db_file).unwrap().len() as usize;
+
+        let dm = DiskManager::with_options(db_file, options.clone()).unwrap();
+        assert_eq!(0, dm.allocated_pages());
+        assert_eq!(BUSTUB_PAGE_SIZE, file_len());
+
+        // Scenario: the file grows a whole extent at a time, the header page
+        // comes on top.
+        let data = [7; BUSTUB_PAGE_SIZE];
+        dm.write_page(0, &data).unwrap();
+        assert_eq!(64, dm.allocated_pages());
+        assert_eq!(65 * BUSTUB_PAGE_SIZE, file_len());
+        dm.write_page(63, &data).unwrap();
+        assert_eq!(64, dm.allocated_pages());
+        assert_eq!(65 * BUSTUB_PAGE_SIZE, file_len());
+        dm.write_page(64, &data).unwrap();
+        assert_eq!(128, dm.allocated_pages());
+        assert_eq!(129 * BUSTUB_PAGE_SIZE, file_len());
+
+        // Scenario: allocated pages that were never written read as zeros.
+        let mut buf = [1; BUSTUB_PAGE_SIZE];
+        for page_id in [1, 62, 65, 127] {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!([0; BUSTUB_PAGE_SIZE], buf);
+        }
+        dm.read_page(63, &mut buf).unwrap();
+        assert_eq!(data, buf);
+
+        // Scenario: the allocated size survives a reopen.
+        drop(dm);
+        let dm = DiskManager::with_options(db_file, options).unwrap();
+        assert_eq!(128, dm.allocated_pages());
+        dm.read_page(64, &mut buf).unwrap();
+        assert_eq!(data, buf);
+    }
+
+    #[test]
+    fn io_stats() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+        assert_eq!(IoStats::default(), dm.get_io_stats());
+
+        // Scenario: every operation is counted and timed, an empty log write
+        // is not an operation.
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for page_id in 0..3 {
+            dm.write_page(page_id, &buf).unwrap();
+        }
+        for page_id in 0..5 {
+            dm.read_page(page_id, &mut buf).unwrap();
+        }
+        dm.write_log(b"log").unwrap();
+        dm.write_log(b"log").unwrap();
+        dm.write_log(b"").unwrap();
+        let stats = dm.get_io_stats();
+        assert_eq!(5, stats.reads.count);
+        assert_eq!(3, stats.writes.count);
+        assert_eq!(2, stats.log_flushes.count);
+        assert!(stats.writes.total_time > Duration::ZERO);
+        assert!(stats.writes.mean_time().unwrap() <= stats.writes.total_time);
+
+        // Scenario: a sample is a copy, later operations only show up in the
+        // next one.
+        let sample = stats;
+        dm.read_page(0, &mut buf).unwrap();
+        assert_eq!(stats, sample);
+        assert_eq!(6, dm.get_io_stats().reads.count);
+        assert_eq!(None, OpStats::default().mean_time());
+    }
+
+    #[test]
+    fn read_ahead() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+        for page_id in 0..100 {
+            dm.write_page(page_id, &[page_id as u8 + 1; BUSTUB_PAGE_SIZE])
+                .unwrap();
+        }
+
+        // Scenario: a sequential scan is served by a few large reads.
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for page_id in 0..100 {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!([page_id as u8 + 1; BUSTUB_PAGE_SIZE], buf);
+        }
+        assert!(dm.get_num_reads() <= 15, "{} reads", dm.get_num_reads());
+        assert_eq!(100, dm.get_io_stats().reads.count);
+
+        // Scenario: a write to a page that was read ahead is not hidden by
+        // the copy read before it.
+        for page_id in 0..4 {
+            dm.read_page(page_id, &mut buf).unwrap();
+        }
+        dm.write_page(5, &[42; BUSTUB_PAGE_SIZE]).unwrap();
+        dm.read_page(4, &mut buf).unwrap();
+        assert_eq!([5; BUSTUB_PAGE_SIZE], buf);
+        dm.read_page(5, &mut buf).unwrap();
+        assert_eq!([42; BUSTUB_PAGE_SIZE], buf);
+
+        // Scenario: random reads do not read ahead.
+        let reads = dm.get_num_reads();
+        for page_id in [50, 20, 70, 30, 90] {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!([page_id as u8 + 1; BUSTUB_PAGE_SIZE], buf);
+        }
+        assert_eq!(reads + 5, dm.get_num_reads());
+
+        // Scenario: without read-ahead every page is read on its own.
+        drop(dm);
+        let options = DiskManagerOptions {
+            read_ahead_pages: 0,
+            ..Default::default()
+        };
+        let dm = DiskManager::with_options(db_file.to_str().unwrap(), options).unwrap();
+        for page_id in 0..100 {
+            dm.read_page(page_id, &mut buf).unwrap();
+        }
+        assert_eq!(100, dm.get_num_reads());
+    }
+
+    #[test]
+    fn multiple_files() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        let dm = DiskManager::new(db_file).unwrap();
+        dm.write_page(0, &[1; BUSTUB_PAGE_SIZE]).unwrap();
+
+        // Scenario: two new files next to the db file, with pages allocated
+        // and written in turns.
+        let first = dm.create_file().unwrap();
+        let second = dm.create_file().unwrap();
+        assert_eq!((1, 2), (first, second));
+        let first_path = dir.path().join("test.1.db");
+        assert!(first_path.exists());
+        assert!(dir.path().join("test.2.db").exists());
+        let mut pages = Vec::new();
+        for i in 0..3 {
+            for file_id in [first, second] {
+                let page_id = dm.allocate_page(file_id, 1, 0).unwrap();
+                assert_eq!((file_id, i), (file_id_of(page_id), page_no_of(page_id)));
+                let value = (file_id * 10 + i) as u8;
+                dm.write_page(page_id, &[value; BUSTUB_PAGE_SIZE]).unwrap();
+                pages.push((page_id, value));
+            }
+        }
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for &(page_id, value) in pages.iter() {
+            dm.read_page(page_id, &mut buf).unwrap();
+            assert_eq!([value; BUSTUB_PAGE_SIZE], buf);
+        }
+
+        // Scenario: deleting the first file releases its space and leaves the
+        // other files alone.
+        let allocated = dm.allocated_pages();
+        dm.delete_file(first).unwrap();
+        assert!(!first_path.exists());
+        assert_eq!(allocated - DEFAULT_EXTENT_PAGES, dm.allocated_pages());
+        for &(page_id, value) in pages.iter() {
+            let res = dm.read_page(page_id, &mut buf);
+            if file_id_of(page_id) == first {
+                assert_eq!(io::ErrorKind::NotFound, res.unwrap_err().kind());
+            } else {
+                res.unwrap();
+                assert_eq!([value; BUSTUB_PAGE_SIZE], buf);
+            }
+        }
+        let res = dm.write_page(make_page_id(first, 0), &buf);
+        assert_eq!(io::ErrorKind::NotFound, res.unwrap_err().kind());
+        let res = dm.delete_file(MAIN_FILE_ID);
+        assert_eq!(io::ErrorKind::InvalidInput, res.unwrap_err().kind());
+
+        // Scenario: the remaining files are found again on reopen.
+        drop(dm);
+        let dm = DiskManager::new(db_file).unwrap();
+        dm.read_page(0, &mut buf).unwrap();
+        assert_eq!([1; BUSTUB_PAGE_SIZE], buf);
+        dm.read_page(make_page_id(second, 2), &mut buf).unwrap();
+        assert_eq!([22; BUSTUB_PAGE_SIZE], buf);
+        assert_eq!(
+            make_page_id(second, 3),
+            dm.allocate_page(second, 1, 0).unwrap()
+        );
+        assert_eq!(3, dm.create_file().unwrap());
+
+        // Scenario: a read-only handle cannot delete a file.
+        drop(dm);
+        let read_only = DiskManagerOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let dm = DiskManager::with_options(db_file, read_only).unwrap();
+        let res = dm.delete_file(second);
+        assert_eq!(io::ErrorKind::PermissionDenied, res.unwrap_err().kind());
+        assert!(dir.path().join("test.2.db").exists());
+    }
+
+    #[test]
+    fn allocate_in_full_file() {
+        // Scenario: a file out of page numbers fails the allocation instead
+        // of handing out a page id of the next file.
+        let mut allocation = PageAllocation {
+            next_page_id: (1 << PAGE_NO_BITS) - 1,
+            ..Default::default()
+        };
+        let last_page_id = make_page_id(1, (1 << PAGE_NO_BITS) - 1);
+        assert_eq!(last_page_id, allocation.allocate(1, 1, 0).unwrap());
+        let err = allocation.allocate(1, 1, 0).unwrap_err();
+        assert_eq!(io::ErrorKind::StorageFull, err.kind());
+
+        // Scenario: a freed page can still be handed out again.
+        assert!(allocation.deallocate(3));
+        assert_eq!(make_page_id(1, 3), allocation.allocate(1, 1, 0).unwrap());
     }
 }
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use super::disk_manager::{DiskError, DiskManagerTrait, IoCounters, IoStats, PageAllocation};
+use crate::common::config::{
+    file_id_of, page_no_of, FileId, PageId, BUSTUB_PAGE_SIZE, MAIN_FILE_ID, MAX_FILE_ID,
+};
+
+/// DiskManagerMemory keeps the pages and the log in memory instead of in a
+/// file, so that tests of the layers above the disk don't pay for file I/O.
+/// Nothing survives dropping it.
+///
+/// The pages of each data file are stored densely by page number, the page
+/// vector grows to the largest page number written so far.
+pub struct DiskManagerMemory {
+    // The data files by id, the main file included
+    files: RwLock<HashMap<FileId, MemoryFile>>,
+    // The log written so far
+    log: Mutex<Vec<u8>>,
+    // Number of log flushes and syncs
+    num_flushes: AtomicI32,
+    // Number of page writes
+    num_writes: AtomicI32,
+    // Count and duration of reads, writes and log flushes
+    io_counters: IoCounters,
+}
+
+/// One data file of a DiskManagerMemory.
+#[derive(Default)]
+struct MemoryFile {
+    // The pages written so far, indexed by page number
+    pages: Vec<[u8; BUSTUB_PAGE_SIZE]>,
+    // Page allocation state
+    allocation: PageAllocation,
+}
+
+impl Default for DiskManagerMemory {
+    fn default() -> Self {
+        Self {
+            files: RwLock::new(HashMap::from([(MAIN_FILE_ID, MemoryFile::default())])),
+            log: Mutex::default(),
+            num_flushes: AtomicI32::default(),
+            num_writes: AtomicI32::default(),
+            io_counters: IoCounters::default(),
+        }
+    }
+}
+
+impl DiskManagerMemory {
+    /// Creates a new disk manager without any pages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The error for a request about a data file that does not exist.
+fn no_such_file(file_id: FileId) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("data file {} does not exist", file_id),
+    )
+}
+
+impl DiskManagerTrait for DiskManagerMemory {
+    /// Write a page, growing the page vector if page_id is past its end.
+    fn write_page(&self, page_id: PageId, page_data: &[u8]) -> io::Result<()> {
+        assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
+        self.num_writes.fetch_add(1, Ordering::Relaxed);
+        let _timer = self.io_counters.writes.start();
+
+        let mut files = self.files.write().unwrap();
+        let file_id = file_id_of(page_id);
+        let pages = &mut files
+            .get_mut(&file_id)
+            .ok_or_else(|| no_such_file(file_id))?
+            .pages;
+        let index = page_no_of(page_id) as usize;
+        if index >= pages.len() {
+            pages.resize(index + 1, [0; BUSTUB_PAGE_SIZE]);
+        }
+        pages[index].copy_from_slice(page_data);
+        Ok(())
+    }
+
+    /// Read a page, which is all zeros if it was allocated but never written.
+    /// A page that was neither fails with `DiskError::PageOutOfRange`.
+    fn read_page(&self, page_id: PageId, page_data: &mut [u8]) -> io::Result<()> {
+        assert_eq!(page_data.len(), BUSTUB_PAGE_SIZE);
+        let _timer = self.io_counters.reads.start();
+        let files = self.files.read().unwrap();
+        let file_id = file_id_of(page_id);
+        let file = files.get(&file_id).ok_or_else(|| no_such_file(file_id))?;
+        let page_no = page_no_of(page_id);
+        match file.pages.get(page_no as usize) {
+            Some(page) => page_data.copy_from_slice(page),
+            None if file.allocation.in_range(page_no) => page_data.fill(0),
+            None => return Err(DiskError::PageOutOfRange(page_id).into()),
+        }
+        Ok(())
+    }
+
+    fn allocate_page(
+        &self,
+        file_id: FileId,
+        num_instances: usize,
+        instance_index: usize,
+    ) -> io::Result<PageId> {
+        let mut files = self.files.write().unwrap();
+        let file = files
+            .get_mut(&file_id)
+            .ok_or_else(|| no_such_file(file_id))?;
+        file.allocation
+            .allocate(file_id, num_instances, instance_index)
+    }
+
+    fn deallocate_page(&self, page_id: PageId) -> io::Result<()> {
+        let mut files = self.files.write().unwrap();
+        let file_id = file_id_of(page_id);
+        let file = files
+            .get_mut(&file_id)
+            .ok_or_else(|| no_such_file(file_id))?;
+        file.allocation.deallocate(page_no_of(page_id));
+        Ok(())
+    }
+
+    fn write_log(&self, log_data: &[u8]) -> io::Result<()> {
+        if log_data.is_empty() {
+            return Ok(());
+        }
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        let _timer = self.io_counters.log_flushes.start();
+        self.log.lock().unwrap().extend_from_slice(log_data);
+        Ok(())
+    }
+
+    fn read_log(&self, log_data: &mut [u8], offset: usize) -> io::Result<bool> {
+        let log = self.log.lock().unwrap();
+        if offset >= log.len() {
+            return Ok(false);
+        }
+        let read_count = log_data.len().min(log.len() - offset);
+        log_data[..read_count].copy_from_slice(&log[offset..offset + read_count]);
+        log_data[read_count..].fill(0);
+        Ok(true)
+    }
+
+    /// Nothing to make durable, only counted.
+    fn sync(&self) -> io::Result<()> {
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn get_num_flushes(&self) -> i32 {
+        self.num_flushes.load(Ordering::Relaxed)
+    }
+
+    fn get_num_writes(&self) -> i32 {
+        self.num_writes.load(Ordering::Relaxed)
+    }
+
+    fn create_file(&self) -> io::Result<FileId> {
+        let mut files = self.files.write().unwrap();
+        let file_id = files.keys().max().map_or(MAIN_FILE_ID, |&max| max + 1);
+        if file_id > MAX_FILE_ID {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                "no data file ids left",
+            ));
+        }
+        files.insert(file_id, MemoryFile::default());
+        Ok(file_id)
+    }
+
+    fn delete_file(&self, file_id: FileId) -> io::Result<()> {
+        if file_id == MAIN_FILE_ID {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the main data file cannot be deleted",
+            ));
+        }
+        self.files
+            .write()
+            .unwrap()
+            .remove(&file_id)
+            .map(|_| ())
+            .ok_or_else(|| no_such_file(file_id))
+    }
+
+    /// The page vectors have no spare room, this is their total length.
+    fn allocated_pages(&self) -> usize {
+        let files = self.files.read().unwrap();
+        files.values().map(|file| file.pages.len()).sum()
+    }
+
+    fn get_io_stats(&self) -> IoStats {
+        self.io_counters.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write_page() {
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        let mut data = [0; BUSTUB_PAGE_SIZE];
+
+        let dm = DiskManagerMemory::new();
+        let test_str = b"A test string.";
+        data[..test_str.len()].copy_from_slice(test_str);
+
+        // Scenario: allocated pages that were never written read as zeros,
+        // pages that were not allocated cannot be read.
+        let err = dm.read_page(0, &mut buf).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        let page_id = dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap();
+        assert_eq!(0, page_id);
+        buf.fill(1);
+        dm.read_page(0, &mut buf).unwrap();
+        assert_eq!(buf, [0; BUSTUB_PAGE_SIZE]);
+
+        dm.write_page(0, &data).unwrap();
+        dm.read_page(0, &mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        // Scenario: writing past the end leaves a zeroed gap.
+        dm.write_page(5, &data).unwrap();
+        dm.read_page(5, &mut buf).unwrap();
+        assert_eq!(buf, data);
+        dm.read_page(3, &mut buf).unwrap();
+        assert_eq!(buf, [0; BUSTUB_PAGE_SIZE]);
+        assert_eq!(2, dm.get_num_writes());
+    }
+
+    #[test]
+    fn read_write_log() {
+        let mut buf = [1; 20];
+
+        let dm = DiskManagerMemory::new();
+        let test_str = b"A test string.";
+
+        assert!(!dm.read_log(&mut buf, 0).unwrap());
+        dm.write_log(test_str).unwrap();
+        assert!(dm.read_log(&mut buf, 0).unwrap());
+        assert_eq!(&buf[..test_str.len()], test_str);
+        assert_eq!(&buf[test_str.len()..], &[0; 6]);
+        assert!(dm.read_log(&mut buf, 7).unwrap());
+        assert_eq!(&buf[..7], b"string.");
+        assert_eq!(1, dm.get_num_flushes());
+    }
+
+    #[test]
+    fn allocate_reuses_deallocated_pages() {
+        let dm = DiskManagerMemory::new();
+        for i in 0..3 {
+            assert_eq!(i, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+        }
+        dm.deallocate_page(1).unwrap();
+        // never allocated, ignored
+        dm.deallocate_page(42).unwrap();
+        assert_eq!(1, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+        assert_eq!(3, dm.allocate_page(MAIN_FILE_ID, 1, 0).unwrap());
+
+        // ids passed over by instance 1 are kept for instance 0
+        assert_eq!(5, dm.allocate_page(MAIN_FILE_ID, 2, 1).unwrap());
+        assert_eq!(4, dm.allocate_page(MAIN_FILE_ID, 2, 0).unwrap());
+    }
+}
@@ -1,54 +1,503 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, Weak};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use log::warn;
 use tokio::sync::oneshot;
 
-use crate::storage::disk::disk_manager::DiskManager;
-use crate::storage::page::page::Page;
+use crate::common::config::{FileId, PageId, BUSTUB_PAGE_SIZE};
+use crate::storage::disk::disk_manager::{DiskError, DiskManagerTrait};
 
-/// @brief Represents a Write or Read request for the DiskManager to execute.
+/// The data of a page on its way to or from the disk, owned by the request so
+/// that nothing has to stay latched while the I/O is done.
+pub type PageBuf = Box<[u8; BUSTUB_PAGE_SIZE]>;
+
+/// @brief Represents a request for the DiskManager to execute: reading or
+/// writing a page or a batch of pages, allocating or deallocating a page id, creating or deleting
+/// a data file, or appending to or reading the log.
 pub enum DiskRequest {
     Read {
-        /// The page being read from disk.
-        page: Page,
-        /// Callback used to signal to the request issuer when the request has
-        /// been completed.
-        callback: oneshot::Sender<()>,
+        /// The id of the page being read from disk, which tells its data file.
+        page_id: PageId,
+        /// Callback used to hand the page's data to the request issuer when
+        /// the request has been completed, or the I/O error if it failed.
+        callback: oneshot::Sender<io::Result<PageBuf>>,
     },
     Write {
-        /// The page being written out ot disk.
-        page: Page,
+        /// The id of the page being written out to disk, which tells its data
+        /// file.
+        page_id: PageId,
+        /// The data being written.
+        data: PageBuf,
+        /// Callback used to signal to the request issuer when the request has
+        /// been completed, carrying the I/O error if it failed.
+        callback: oneshot::Sender<io::Result<()>>,
+    },
+    /// Writes several pages with one DiskManagerTrait::write_pages call, e.g.
+    /// for a checkpoint.
+    WriteBatch {
+        /// The ids of the pages being written out to disk, the data being
+        /// written, and the callbacks used to signal to the request issuer
+        /// when each page has been written. If the batch fails, every page
+        /// gets a copy of the I/O error.
+        pages: Vec<(PageId, PageBuf, oneshot::Sender<io::Result<()>>)>,
+    },
+    Allocate {
+        /// The data file the page id is allocated in.
+        file_id: FileId,
+        /// Number of buffer pool instances sharing the disk.
+        num_instances: usize,
+        /// Index of the instance the page id is allocated for.
+        instance_index: usize,
+        /// Callback used to hand the allocated page id, or the I/O error that
+        /// prevented allocating one, to the request issuer.
+        callback: oneshot::Sender<io::Result<PageId>>,
+    },
+    Deallocate {
+        /// The page id being deallocated.
+        page_id: PageId,
         /// Callback used to signal to the request issuer when the request has
-        /// been completed.
-        callback: oneshot::Sender<()>,
+        /// been completed, carrying the I/O error if it failed.
+        callback: oneshot::Sender<io::Result<()>>,
+    },
+    CreateFile {
+        /// Callback used to hand the id of the new data file, or the I/O error
+        /// that prevented creating it, to the request issuer.
+        callback: oneshot::Sender<io::Result<FileId>>,
     },
+    DeleteFile {
+        /// The data file being deleted.
+        file_id: FileId,
+        /// Callback used to signal to the request issuer when the request has
+        /// been completed, carrying the I/O error if it failed.
+        callback: oneshot::Sender<io::Result<()>>,
+    },
+    /// Makes the requests that completed before it durable.
+    Sync {
+        /// Callback used to signal to the request issuer when the request has
+        /// been completed, carrying the I/O error if it failed.
+        callback: oneshot::Sender<io::Result<()>>,
+    },
+    /// Appends to the log. Appends are written in the order they were
+    /// scheduled.
+    WriteLog {
+        /// The log records being appended.
+        data: Vec<u8>,
+        /// Callback used to signal to the request issuer when the data has
+        /// been written, carrying the I/O error if it failed.
+        callback: oneshot::Sender<io::Result<()>>,
+    },
+    /// Reads the log, after the appends scheduled before it were written.
+    ReadLog {
+        /// Where in the log to start reading.
+        offset: usize,
+        /// Number of bytes to read.
+        len: usize,
+        /// Callback used to hand the bytes read to the request issuer, or the
+        /// I/O error if it failed. A read starting past the end of the log
+        /// gets no bytes, one running past it is padded with zeros, as with
+        /// DiskManagerTrait::read_log.
+        callback: oneshot::Sender<io::Result<Vec<u8>>>,
+    },
+}
+
+impl DiskRequest {
+    /// @brief Returns the page the request is about, if it is about one.
+    fn page_id(&self) -> Option<PageId> {
+        match self {
+            DiskRequest::Read { page_id, .. }
+            | DiskRequest::Write { page_id, .. }
+            | DiskRequest::Deallocate { page_id, .. } => Some(*page_id),
+            DiskRequest::WriteBatch { .. }
+            | DiskRequest::Allocate { .. }
+            | DiskRequest::CreateFile { .. }
+            | DiskRequest::DeleteFile { .. }
+            | DiskRequest::Sync { .. }
+            | DiskRequest::WriteLog { .. }
+            | DiskRequest::ReadLog { .. } => None,
+        }
+    }
+
+    /// @brief Returns whether the request goes to the log worker.
+    fn is_log(&self) -> bool {
+        matches!(
+            self,
+            DiskRequest::WriteLog { .. } | DiskRequest::ReadLog { .. }
+        )
+    }
+
+    /// @brief Completes the request with DiskError::SchedulerClosed instead of
+    /// executing it.
+    fn fail(self) {
+        match self {
+            DiskRequest::Read { callback, .. } => fail(callback),
+            DiskRequest::Allocate { callback, .. } => fail(callback),
+            DiskRequest::CreateFile { callback } => fail(callback),
+            DiskRequest::ReadLog { callback, .. } => fail(callback),
+            DiskRequest::WriteBatch { pages } => {
+                for (_, _, callback) in pages {
+                    fail(callback);
+                }
+            }
+            DiskRequest::Write { callback, .. }
+            | DiskRequest::Deallocate { callback, .. }
+            | DiskRequest::DeleteFile { callback, .. }
+            | DiskRequest::Sync { callback }
+            | DiskRequest::WriteLog { callback, .. } => fail(callback),
+        }
+    }
+}
+
+/// @brief Completes callback with DiskError::SchedulerClosed. An issuer that
+/// stopped waiting is not an error.
+fn fail<T>(callback: oneshot::Sender<io::Result<T>>) {
+    let _ = callback.send(Err(DiskError::SchedulerClosed.into()));
+}
+
+/// A write that was queued but not started yet. Later writes to the same page
+/// replace its payload, see DiskScheduler::schedule.
+type WriteSlot = Mutex<Option<(PageId, PageBuf, oneshot::Sender<io::Result<()>>)>>;
+
+/// What the workers find in their queues.
+enum QueuedRequest {
+    /// A request executed as it was scheduled.
+    Request(DiskRequest),
+    /// A write whose payload may still be replaced until the worker takes it.
+    Write(Arc<WriteSlot>),
+}
+
+impl QueuedRequest {
+    /// @brief Returns the pages the request reads or writes.
+    fn page_ids(&self) -> Vec<PageId> {
+        match self {
+            QueuedRequest::Request(DiskRequest::WriteBatch { pages }) => {
+                pages.iter().map(|page| page.0).collect()
+            }
+            QueuedRequest::Request(r) => r.page_id().into_iter().collect(),
+            QueuedRequest::Write(slot) => slot
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|write| write.0)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// @brief Returns whether the request reads or writes page page_id.
+    fn is_about(&self, page_id: PageId) -> bool {
+        match self {
+            QueuedRequest::Request(DiskRequest::WriteBatch { pages }) => {
+                pages.iter().any(|page| page.0 == page_id)
+            }
+            QueuedRequest::Request(r) => r.page_id() == Some(page_id),
+            QueuedRequest::Write(slot) => slot
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|write| write.0 == page_id),
+        }
+    }
+
+    /// @brief Completes the request with DiskError::SchedulerClosed instead of
+    /// executing it.
+    fn fail(self) {
+        match self {
+            QueuedRequest::Request(r) => r.fail(),
+            QueuedRequest::Write(slot) => {
+                if let Some((_, _, callback)) = slot.lock().unwrap().take() {
+                    fail(callback);
+                }
+            }
+        }
+    }
+}
+
+/// How soon a request is executed relative to the others in its worker's
+/// queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Priority {
+    /// Somebody is waiting for the request, e.g. a query for the page it
+    /// reads. Executed before the background requests.
+    Urgent,
+    /// Executed in the order the requests were scheduled.
+    Background,
+}
+
+/// How long a background request waits behind urgent ones at most. Once the
+/// oldest background request waited longer, it is executed next. Used by all
+/// but the tests, which need the order of the requests to be predictable.
+const BACKGROUND_MAX_WAIT: Duration = Duration::from_millis(50);
+
+/// What is put into a worker's queue: a request along with its priority and
+/// when it was scheduled, or `None` to stop the worker.
+type QueueItem = Option<(QueuedRequest, Priority, Instant)>;
+
+/// A worker's end of its queue. The requests sent to the worker are sorted
+/// into an urgent and a background queue before the worker picks the next
+/// one. However the worker exits, a panic included, the requests still in the
+/// queues are failed rather than dropped, so their issuers do not wait for an
+/// answer that never comes.
+struct WorkerQueue {
+    rx: mpsc::Receiver<QueueItem>,
+    urgent: VecDeque<(QueuedRequest, Instant)>,
+    background: VecDeque<(QueuedRequest, Instant)>,
+    // Whether the worker was told to stop. The requests sent before that are
+    // still executed, later ones are failed on drop.
+    stopped: bool,
+    // How long a background request waits behind urgent ones at most
+    background_max_wait: Duration,
+}
+
+impl WorkerQueue {
+    fn new(rx: mpsc::Receiver<QueueItem>, background_max_wait: Duration) -> Self {
+        Self {
+            rx,
+            urgent: VecDeque::new(),
+            background: VecDeque::new(),
+            stopped: false,
+            background_max_wait,
+        }
+    }
+
+    /// @brief Returns the request to execute next, waiting for one if none
+    /// is queued, or `None` once the worker is to stop.
+    ///
+    /// Urgent requests go first, unless the oldest background request waited
+    /// longer than background_max_wait. That never reorders the requests for
+    /// a page, as push keeps those in one queue.
+    fn pop(&mut self) -> Option<(QueuedRequest, Instant)> {
+        if !self.stopped && self.urgent.is_empty() && self.background.is_empty() {
+            match self.rx.recv() {
+                Ok(item) => self.push(item),
+                Err(_) => return None,
+            }
+        }
+        while !self.stopped {
+            match self.rx.try_recv() {
+                Ok(item) => self.push(item),
+                Err(_) => break,
+            }
+        }
+        let starving = self
+            .background
+            .front()
+            .is_some_and(|(_, scheduled_at)| scheduled_at.elapsed() > self.background_max_wait);
+        if starving {
+            return self.background.pop_front();
+        }
+        self.urgent
+            .pop_front()
+            .or_else(|| self.background.pop_front())
+    }
+
+    /// @brief Sorts a request sent to the worker into its queue, such that
+    /// the requests for a page are still executed in the order they were
+    /// scheduled:
+    /// - An urgent request only overtakes the background requests if none of
+    ///   them is about its page. Urgent requests that are not about a page are
+    ///   not reordered at all.
+    /// - A background request about the page of a queued urgent request goes
+    ///   into the urgent queue behind it, so that it is not executed first
+    ///   once it waited too long.
+    fn push(&mut self, item: QueueItem) {
+        let Some((queued, priority, scheduled_at)) = item else {
+            self.stopped = true;
+            return;
+        };
+        let page_ids = queued.page_ids();
+        let any_about = |queue: &VecDeque<(QueuedRequest, Instant)>| {
+            queue
+                .iter()
+                .any(|(queued, _)| page_ids.iter().any(|&page_id| queued.is_about(page_id)))
+        };
+        let urgent = match priority {
+            Priority::Urgent => !page_ids.is_empty() && !any_about(&self.background),
+            Priority::Background => any_about(&self.urgent),
+        };
+        if urgent {
+            self.urgent.push_back((queued, scheduled_at));
+        } else {
+            self.background.push_back((queued, scheduled_at));
+        }
+    }
+}
+
+impl Drop for WorkerQueue {
+    fn drop(&mut self) {
+        for (queued, _) in self.urgent.drain(..).chain(self.background.drain(..)) {
+            queued.fail();
+        }
+        while let Ok(queued) = self.rx.try_recv() {
+            if let Some((queued, _, _)) = queued {
+                queued.fail();
+            }
+        }
+    }
+}
+
+/// @brief Statistics about the queues of a DiskScheduler since it was
+/// created. A plain copy, cheap to take periodically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedulerStats {
+    /// Number of requests the workers took from their queues. Writes
+    /// coalesced into a queued one are not queued themselves.
+    pub num_requests: u64,
+    /// The most requests that were waiting in the queues at once.
+    pub max_queue_depth: usize,
+    /// Time the requests spent in the queues before a worker took them,
+    /// summed up.
+    pub queued_time: Duration,
+}
+
+/// The counters behind SchedulerStats, shared with the workers.
+#[derive(Default)]
+struct QueueCounters {
+    // Requests currently waiting in the queues
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    num_requests: AtomicU64,
+    queued_nanos: AtomicU64,
 }
 
 /// @brief The DiskScheduler schedules disk read and write operations.
 ///
 /// A request is scheduled by calling DiskScheduler::Schedule() with an
-/// appropriate DiskRequest object. The scheduler maintains a background worker
-/// thread that processes the scheduled requests using the disk manager. The
-/// background thread is created in the DiskScheduler constructor and joined in
-/// its destructor.
+/// appropriate DiskRequest object. The scheduler maintains background worker
+/// threads that process the scheduled requests using the disk manager. The
+/// background threads are created in the DiskScheduler constructor and joined
+/// in its destructor, after they executed every request scheduled before.
+///
+/// Each worker has its own queue, and all requests for a page go to the same
+/// worker, `page_id % num_workers`. Requests for one page are therefore
+/// processed in the order they were scheduled, while requests for different
+/// pages can run in parallel. Allocations, file requests and syncs are not
+/// about a page and always go to the first worker.
+///
+/// Log requests have a worker and a queue of their own, so appending to the
+/// log never waits behind page writes, and appends hit the log in the order
+/// they were scheduled.
+///
+/// Writes to a page that already has a write waiting in the queue are
+/// coalesced into it, so only the newest data hits the disk. A batch of writes
+/// is split by worker, each worker writes its part of the batch at once.
+///
+/// Requests scheduled with DiskScheduler::schedule_urgent, e.g. the read of a
+/// page a query waits for, are executed before the background requests queued
+/// for the same worker, such as the write-backs of a flush. A background
+/// request that waited too long is executed next anyway, so a stream of
+/// urgent requests cannot hold it back forever.
 pub struct DiskScheduler {
-    /// A shared queue to concurrently schedule and process requests. When the
-    /// DiskScheduler's destructor is called, `None` is put into the queue
-    /// to signal to the background thread to stop execution.
-    request_queue: std::sync::mpsc::Sender<Option<DiskRequest>>,
-
-    /// The background thread responsible for issuing scheduled requests to the
-    /// disk manager.
-    background_thread: Option<thread::JoinHandle<()>>,
+    /// One queue per worker to concurrently schedule and process requests.
+    /// On shutdown, `None` is put into every queue to signal to the
+    /// background threads to stop execution.
+    request_queues: Vec<mpsc::Sender<QueueItem>>,
+
+    /// The queue of the log worker, stopped the same way.
+    log_queue: mpsc::Sender<QueueItem>,
+
+    /// Set on shutdown, from then on requests are failed instead of queued.
+    closed: AtomicBool,
+
+    /// The queued writes that later writes to the same page can still be
+    /// coalesced into, by page id. Shared with the background threads, which
+    /// empty the slot when they start the write. Only the queue owns a slot,
+    /// so a write dropped along with a dead worker's queue is gone from here
+    /// too.
+    pending_writes: Arc<Mutex<HashMap<PageId, Weak<WriteSlot>>>>,
+
+    /// The disk manager the requests are executed on, shared with the
+    /// background thread.
+    disk_manager: Arc<dyn DiskManagerTrait>,
+
+    /// Queue statistics, shared with the background threads.
+    queue_counters: Arc<QueueCounters>,
+
+    /// The background threads responsible for issuing scheduled requests to
+    /// the disk manager, one per queue, the log worker last.
+    background_threads: Vec<thread::JoinHandle<()>>,
 }
 
 impl DiskScheduler {
-    pub fn new(disk_manager: DiskManager) -> Self {
-        let (tx, rx) = std::sync::mpsc::channel();
+    /// @brief Creates a DiskScheduler with a single worker that executes
+    /// requests on the given disk manager, backed by a file or by memory.
+    pub fn new(disk_manager: Box<dyn DiskManagerTrait>) -> Self {
+        Self::with_workers(disk_manager, 1)
+    }
+
+    /// @brief Creates a DiskScheduler with num_workers worker threads.
+    /// @param disk_manager the disk manager requests are executed on
+    /// @param num_workers number of worker threads, at least 1
+    pub fn with_workers(disk_manager: Box<dyn DiskManagerTrait>, num_workers: usize) -> Self {
+        Self::with_background_max_wait(disk_manager, num_workers, BACKGROUND_MAX_WAIT)
+    }
+
+    /// @brief Creates a DiskScheduler whose background requests wait behind
+    /// urgent ones for at most background_max_wait, see with_workers.
+    fn with_background_max_wait(
+        disk_manager: Box<dyn DiskManagerTrait>,
+        num_workers: usize,
+        background_max_wait: Duration,
+    ) -> Self {
+        assert!(num_workers > 0, "a DiskScheduler needs at least one worker");
+        let disk_manager: Arc<dyn DiskManagerTrait> = Arc::from(disk_manager);
+        let pending_writes = Arc::new(Mutex::new(HashMap::new()));
+        let queue_counters = Arc::new(QueueCounters::default());
+        let mut request_queues = Vec::with_capacity(num_workers + 1);
+        let mut background_threads = Vec::with_capacity(num_workers + 1);
+        // one more queue and worker for the log
+        for _ in 0..=num_workers {
+            let (tx, rx) = mpsc::channel();
+            let worker_disk_manager = disk_manager.clone();
+            let worker_pending_writes = pending_writes.clone();
+            let worker_queue_counters = queue_counters.clone();
+            request_queues.push(tx);
+            background_threads.push(thread::spawn(move || {
+                Self::start_worker_thread(
+                    rx,
+                    worker_disk_manager,
+                    worker_pending_writes,
+                    worker_queue_counters,
+                    background_max_wait,
+                )
+            }));
+        }
+        let log_queue = request_queues.pop().unwrap();
         Self {
-            request_queue: tx,
-            background_thread: Some(thread::spawn(move || {
-                Self::start_worker_thread(rx, disk_manager)
-            })),
+            request_queues,
+            log_queue,
+            closed: AtomicBool::new(false),
+            pending_writes,
+            disk_manager,
+            queue_counters,
+            background_threads,
+        }
+    }
+
+    /// @brief Returns the number of worker threads for page requests. The
+    /// log worker is not counted.
+    pub fn get_num_workers(&self) -> usize {
+        self.request_queues.len()
+    }
+
+    /// @brief Returns the disk manager requests are executed on, e.g. to
+    /// inspect its statistics.
+    pub fn get_disk_manager(&self) -> &dyn DiskManagerTrait {
+        self.disk_manager.as_ref()
+    }
+
+    /// @brief Returns statistics about the request queues. The I/O statistics
+    /// are kept by the disk manager, see DiskManagerTrait::get_io_stats.
+    pub fn get_stats(&self) -> SchedulerStats {
+        let counters = &self.queue_counters;
+        SchedulerStats {
+            num_requests: counters.num_requests.load(Ordering::Relaxed),
+            max_queue_depth: counters.max_depth.load(Ordering::Relaxed),
+            queued_time: Duration::from_nanos(counters.queued_nanos.load(Ordering::Relaxed)),
         }
     }
 
@@ -57,8 +506,235 @@ impl DiskScheduler {
     /// @brief Schedules a request for the DiskManager to execute.
     ///
     /// @param r The request to be scheduled.
+    ///
+    /// The request is queued for the worker that owns its page, or for the
+    /// log worker if it is a log request. If the
+    /// scheduler is shut down or that worker is gone, the request completes
+    /// with DiskError::SchedulerClosed right away.
+    ///
+    /// A write to a page whose previous write is still queued replaces that
+    /// write's payload instead of being queued itself. The superseded request
+    /// is completed right away, the newer data is written in its place. Any
+    /// other request for the page closes the queued write to coalescing, so a
+    /// read never sees data that was written after it was scheduled.
+    ///
+    /// The request is executed in the background, after the urgent requests
+    /// of its worker.
     pub fn schedule(&self, r: DiskRequest) {
-        self.request_queue.send(Some(r)).unwrap();
+        self.schedule_with(r, Priority::Background);
+    }
+
+    /// @brief Schedules a request that somebody is waiting for, ahead of the
+    /// background requests of its worker. It does not overtake requests for
+    /// the same page, and requests that are not about a page are executed in
+    /// order like background ones.
+    ///
+    /// @param r The request to be scheduled.
+    pub fn schedule_urgent(&self, r: DiskRequest) {
+        self.schedule_with(r, Priority::Urgent);
+    }
+
+    /// @brief Schedules a request with the given priority, see schedule.
+    fn schedule_with(&self, r: DiskRequest, priority: Priority) {
+        if self.closed.load(Ordering::Acquire) {
+            r.fail();
+            return;
+        }
+        if let DiskRequest::WriteBatch { pages } = r {
+            self.schedule_batch(pages);
+            return;
+        }
+        let page_id = r.page_id();
+        let queue = if r.is_log() {
+            &self.log_queue
+        } else {
+            let worker = page_id.map_or(0, |page_id| page_id as usize % self.request_queues.len());
+            &self.request_queues[worker]
+        };
+        let queued = match r {
+            DiskRequest::Write {
+                page_id,
+                data,
+                callback,
+            } => {
+                let mut pending_writes = self.pending_writes.lock().unwrap();
+                if let Some(slot) = pending_writes.get(&page_id).and_then(Weak::upgrade) {
+                    if let Some((_, queued_data, queued_callback)) = slot.lock().unwrap().as_mut() {
+                        *queued_data = data;
+                        let superseded = std::mem::replace(queued_callback, callback);
+                        let _ = superseded.send(Ok(()));
+                        return;
+                    }
+                }
+                let slot = Arc::new(Mutex::new(Some((page_id, data, callback))));
+                pending_writes.insert(page_id, Arc::downgrade(&slot));
+                QueuedRequest::Write(slot)
+            }
+            r => {
+                if let Some(page_id) = page_id {
+                    self.pending_writes.lock().unwrap().remove(&page_id);
+                }
+                QueuedRequest::Request(r)
+            }
+        };
+        self.enqueue(queue, queued, priority);
+    }
+
+    /// @brief Splits a batch of writes by the worker owning the pages, and
+    /// queues each part as one background request. The batch closes queued
+    /// writes of its pages to coalescing.
+    fn schedule_batch(&self, pages: Vec<(PageId, PageBuf, oneshot::Sender<io::Result<()>>)>) {
+        let num_workers = self.request_queues.len();
+        let mut parts: Vec<Vec<_>> = (0..num_workers).map(|_| Vec::new()).collect();
+        let mut pending_writes = self.pending_writes.lock().unwrap();
+        for page in pages {
+            pending_writes.remove(&page.0);
+            parts[page.0 as usize % num_workers].push(page);
+        }
+        drop(pending_writes);
+        for (worker, pages) in parts.into_iter().enumerate() {
+            if !pages.is_empty() {
+                let queued = QueuedRequest::Request(DiskRequest::WriteBatch { pages });
+                self.enqueue(&self.request_queues[worker], queued, Priority::Background);
+            }
+        }
+    }
+
+    /// @brief Puts a request into a worker's queue, or fails it if the
+    /// worker is gone.
+    fn enqueue(&self, queue: &mpsc::Sender<QueueItem>, queued: QueuedRequest, priority: Priority) {
+        let counters = &self.queue_counters;
+        let depth = counters.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        counters.max_depth.fetch_max(depth, Ordering::Relaxed);
+        let item = Some((queued, priority, Instant::now()));
+        if let Err(mpsc::SendError(Some((queued, _, _)))) = queue.send(item) {
+            counters.depth.fetch_sub(1, Ordering::Relaxed);
+            queued.fail();
+        }
+    }
+
+    /// @brief Stops accepting requests. The requests scheduled so far are
+    /// still executed, later ones complete with DiskError::SchedulerClosed.
+    /// Dropping the scheduler shuts it down and waits for the workers to
+    /// finish.
+    pub fn shutdown(&self) {
+        if self.closed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        // A worker may already be dead (e.g. the disk manager panicked), so
+        // the send is allowed to fail.
+        for queue in self.request_queues.iter().chain([&self.log_queue]) {
+            let _ = queue.send(None);
+        }
+    }
+
+    /// @brief Schedules reading a page from disk.
+    ///
+    /// @param page_id The id of the page to read.
+    /// @return a receiver that completes once the read is done, with the
+    /// page's data or the I/O error if it failed. It is closed without a value
+    /// if the worker is gone.
+    pub fn schedule_read(&self, page_id: PageId) -> oneshot::Receiver<io::Result<PageBuf>> {
+        let (callback, rx) = Self::create_promise();
+        self.schedule(DiskRequest::Read { page_id, callback });
+        rx
+    }
+
+    /// @brief Schedules reading a page from disk ahead of the background
+    /// requests, see schedule_urgent.
+    ///
+    /// @param page_id The id of the page to read.
+    /// @return a receiver that completes once the read is done, with the
+    /// page's data or the I/O error if it failed.
+    pub fn schedule_urgent_read(&self, page_id: PageId) -> oneshot::Receiver<io::Result<PageBuf>> {
+        let (callback, rx) = Self::create_promise();
+        self.schedule_urgent(DiskRequest::Read { page_id, callback });
+        rx
+    }
+
+    /// @brief Schedules writing a page to disk.
+    ///
+    /// @param page_id The id of the page to write.
+    /// @param data The data to write.
+    /// @return a receiver that completes once the write is done, with the I/O
+    /// error if it failed. It is closed without a value if the worker is gone.
+    pub fn schedule_write(
+        &self,
+        page_id: PageId,
+        data: PageBuf,
+    ) -> oneshot::Receiver<io::Result<()>> {
+        let (callback, rx) = Self::create_promise();
+        self.schedule(DiskRequest::Write {
+            page_id,
+            data,
+            callback,
+        });
+        rx
+    }
+
+    /// @brief Schedules writing a batch of pages to disk, e.g. for a
+    /// checkpoint.
+    ///
+    /// @param pages The ids of the pages to write along with their data.
+    /// @return a receiver per page, in the order of pages, that completes once
+    /// the page is written, with the I/O error if it failed.
+    pub fn schedule_write_batch(
+        &self,
+        pages: Vec<(PageId, PageBuf)>,
+    ) -> Vec<oneshot::Receiver<io::Result<()>>> {
+        let mut receivers = Vec::with_capacity(pages.len());
+        let pages = pages
+            .into_iter()
+            .map(|(page_id, data)| {
+                let (callback, rx) = Self::create_promise();
+                receivers.push(rx);
+                (page_id, data, callback)
+            })
+            .collect();
+        self.schedule(DiskRequest::WriteBatch { pages });
+        receivers
+    }
+
+    /// @brief Schedules making the writes that completed so far durable,
+    /// whatever the sync policy of the disk manager.
+    ///
+    /// @return a receiver that completes once the sync is done, with the I/O
+    /// error if it failed. It is closed without a value if the worker is gone.
+    pub fn schedule_sync(&self) -> oneshot::Receiver<io::Result<()>> {
+        let (callback, rx) = Self::create_promise();
+        self.schedule(DiskRequest::Sync { callback });
+        rx
+    }
+
+    /// @brief Schedules appending to the log.
+    ///
+    /// @param data The log records to append.
+    /// @return a receiver that completes once the data is written, with the
+    /// I/O error if it failed.
+    pub fn schedule_write_log(&self, data: Vec<u8>) -> oneshot::Receiver<io::Result<()>> {
+        let (callback, rx) = Self::create_promise();
+        self.schedule(DiskRequest::WriteLog { data, callback });
+        rx
+    }
+
+    /// @brief Schedules reading the log.
+    ///
+    /// @param offset Where in the log to start reading.
+    /// @param len Number of bytes to read.
+    /// @return a receiver that completes once the read is done, with the
+    /// bytes read or the I/O error if it failed.
+    pub fn schedule_read_log(
+        &self,
+        offset: usize,
+        len: usize,
+    ) -> oneshot::Receiver<io::Result<Vec<u8>>> {
+        let (callback, rx) = Self::create_promise();
+        self.schedule(DiskRequest::ReadLog {
+            offset,
+            len,
+            callback,
+        });
+        rx
     }
 
     /// TODO(P1): Add implementation
@@ -70,19 +746,115 @@ impl DiskScheduler {
     /// exists, i.e., this function should not return until ~DiskScheduler()
     /// is called. At that point you need to make sure that the function does
     /// return.
+    ///
+    /// I/O errors are handed to the issuer through the request's callback. An
+    /// issuer that stopped waiting is not an error. Requests left in the
+    /// queue when the worker exits are failed, see WorkerQueue.
     fn start_worker_thread(
-        rx: std::sync::mpsc::Receiver<Option<DiskRequest>>,
-        mut disk_manager: DiskManager,
+        rx: mpsc::Receiver<QueueItem>,
+        disk_manager: Arc<dyn DiskManagerTrait>,
+        pending_writes: Arc<Mutex<HashMap<PageId, Weak<WriteSlot>>>>,
+        queue_counters: Arc<QueueCounters>,
+        background_max_wait: Duration,
     ) {
-        while let Ok(r) = rx.recv() {
+        let mut queue = WorkerQueue::new(rx, background_max_wait);
+        loop {
+            let queued = queue.pop().map(|(queued, scheduled_at)| {
+                let nanos = scheduled_at.elapsed().as_nanos() as u64;
+                queue_counters.depth.fetch_sub(1, Ordering::Relaxed);
+                queue_counters.num_requests.fetch_add(1, Ordering::Relaxed);
+                queue_counters
+                    .queued_nanos
+                    .fetch_add(nanos, Ordering::Relaxed);
+                queued
+            });
+            let r = match queued {
+                Some(QueuedRequest::Request(r)) => Some(r),
+                Some(QueuedRequest::Write(slot)) => {
+                    // from here on later writes to the page are queued on
+                    // their own
+                    let (page_id, data, callback) = slot.lock().unwrap().take().unwrap();
+                    let mut pending_writes = pending_writes.lock().unwrap();
+                    if pending_writes
+                        .get(&page_id)
+                        .is_some_and(|pending| pending.as_ptr() == Arc::as_ptr(&slot))
+                    {
+                        pending_writes.remove(&page_id);
+                    }
+                    Some(DiskRequest::Write {
+                        page_id,
+                        data,
+                        callback,
+                    })
+                }
+                None => None,
+            };
             match r {
-                Some(DiskRequest::Read { page, callback }) => {
-                    disk_manager.read_page(page.get_page_id().unwrap(), &mut *page.get_data_mut());
-                    callback.send(()).unwrap();
+                Some(DiskRequest::Read { page_id, callback }) => {
+                    let mut data = Box::new([0; BUSTUB_PAGE_SIZE]);
+                    let res = disk_manager.read_page(page_id, &mut *data).map(|_| data);
+                    let _ = callback.send(res);
+                }
+                Some(DiskRequest::Write {
+                    page_id,
+                    data,
+                    callback,
+                }) => {
+                    let res = disk_manager.write_page(page_id, &*data);
+                    let _ = callback.send(res);
+                }
+                Some(DiskRequest::WriteBatch { pages }) => {
+                    let writes: Vec<_> = pages
+                        .iter()
+                        .map(|(page_id, data, _)| (*page_id, &data[..]))
+                        .collect();
+                    let res = disk_manager.write_pages(&writes);
+                    for (_, _, callback) in pages {
+                        let res = match &res {
+                            Ok(()) => Ok(()),
+                            Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+                        };
+                        let _ = callback.send(res);
+                    }
+                }
+                Some(DiskRequest::Allocate {
+                    file_id,
+                    num_instances,
+                    instance_index,
+                    callback,
+                }) => {
+                    let res = disk_manager.allocate_page(file_id, num_instances, instance_index);
+                    let _ = callback.send(res);
+                }
+                Some(DiskRequest::CreateFile { callback }) => {
+                    let _ = callback.send(disk_manager.create_file());
                 }
-                Some(DiskRequest::Write { page, callback }) => {
-                    disk_manager.write_page(page.get_page_id().unwrap(), &*page.get_data());
-                    callback.send(()).unwrap();
+                Some(DiskRequest::DeleteFile { file_id, callback }) => {
+                    let _ = callback.send(disk_manager.delete_file(file_id));
+                }
+                Some(DiskRequest::Deallocate { page_id, callback }) => {
+                    let res = disk_manager.deallocate_page(page_id);
+                    let _ = callback.send(res);
+                }
+                Some(DiskRequest::Sync { callback }) => {
+                    let _ = callback.send(disk_manager.sync());
+                }
+                Some(DiskRequest::WriteLog { data, callback }) => {
+                    let _ = callback.send(disk_manager.write_log(&data));
+                }
+                Some(DiskRequest::ReadLog {
+                    offset,
+                    len,
+                    callback,
+                }) => {
+                    let mut data = vec![0; len];
+                    let res = disk_manager.read_log(&mut data, offset).map(|read| {
+                        if !read {
+                            data.clear();
+                        }
+                        data
+                    });
+                    let _ = callback.send(res);
                 }
                 None => break,
             }
@@ -93,16 +865,680 @@ impl DiskScheduler {
     /// version of promise, you can change this function so that our test
     /// cases can use your promise implementation.
     ///
-    /// @return std::promise<bool>
-    fn create_promise() -> oneshot::Sender<()> {
-        unimplemented!()
+    /// @return the callback to put into a DiskRequest, and the receiver that
+    /// completes once the request is done
+    pub fn create_promise<T>() -> (oneshot::Sender<T>, oneshot::Receiver<T>) {
+        oneshot::channel()
     }
 }
 
 impl Drop for DiskScheduler {
     fn drop(&mut self) {
-        // Put a `std::nullopt` in the queue to signal to exit the loop
-        self.request_queue.send(None).unwrap();
-        self.background_thread.take().unwrap().join().unwrap();
+        // Put a `None` behind the queued requests to signal to exit the loop.
+        // A dead worker must not make the drop panic, neither on the send nor
+        // on the join.
+        self.shutdown();
+        for handle in self.background_threads.drain(..) {
+            if handle.join().is_err() {
+                warn!("disk scheduler worker exited with a panic");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::common::config::{BUSTUB_PAGE_SIZE, MAIN_FILE_ID};
+    use crate::storage::disk::disk_manager::{DiskManager, DiskManagerOptions, IoStats};
+    use crate::storage::disk::disk_manager_memory::DiskManagerMemory;
+
+    #[test]
+    fn drop_with_dead_worker() {
+        let gate = Arc::new(Mutex::new(()));
+        let scheduler = DiskScheduler::new(Box::new(GatedDiskManager {
+            inner: DiskManagerMemory::new(),
+            gate: gate.clone(),
+            executed: Default::default(),
+        }));
+
+        // Scenario: the worker is stuck on a write behind the gate, with
+        // reads queued after it. Then the gate's holder panics, which poisons
+        // the gate and makes the disk manager panic, killing the worker.
+        let (held_tx, held_rx) = mpsc::channel();
+        let poison = gate.clone();
+        let holder = thread::spawn(move || {
+            let _held = poison.lock().unwrap();
+            held_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            panic!("poisoning the gate");
+        });
+        held_rx.recv().unwrap();
+        let write = schedule_write(&scheduler, 0, 1);
+        let reads: Vec<_> = (1..5).map(|i| scheduler.schedule_read(i)).collect();
+        assert!(holder.join().is_err());
+
+        // the write the worker died on is lost, the queued reads are failed
+        assert!(write.blocking_recv().is_err());
+        let closed = |res: io::Result<_>| {
+            DiskError::from_io_error(&res.unwrap_err()) == Some(&DiskError::SchedulerClosed)
+        };
+        for rx in reads {
+            assert!(closed(rx.blocking_recv().unwrap()));
+        }
+
+        // requests scheduled afterwards are failed instead of panicking
+        assert!(closed(scheduler.schedule_read(0).blocking_recv().unwrap()));
+
+        drop(scheduler);
+    }
+
+    #[test]
+    fn drop_processes_queued_requests() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let scheduler = DiskScheduler::new(Box::new(
+            DiskManager::new(db_file.to_str().unwrap()).unwrap(),
+        ));
+
+        let callbacks: Vec<_> = (0..50)
+            .map(|i| schedule_write(&scheduler, i, i as u8 + 1))
+            .collect();
+        drop(scheduler);
+        for rx in callbacks {
+            assert!(rx.blocking_recv().unwrap().is_ok());
+        }
+
+        let dm = DiskManager::new(db_file.to_str().unwrap()).unwrap();
+        let mut buf = [0; crate::common::config::BUSTUB_PAGE_SIZE];
+        for i in 0..50 {
+            dm.read_page(i, &mut buf).unwrap();
+            assert_eq!(buf[0], i as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn shutdown_fails_later_requests() {
+        let scheduler = DiskScheduler::with_workers(Box::new(DiskManagerMemory::new()), 2);
+        let write = schedule_write(&scheduler, 3, 1);
+
+        // Scenario: requests scheduled before the shutdown are executed,
+        // later ones fail right away.
+        scheduler.shutdown();
+        scheduler.shutdown();
+        write.blocking_recv().unwrap().unwrap();
+        let err = scheduler
+            .schedule_read(3)
+            .blocking_recv()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::BrokenPipe, err.kind());
+        assert_eq!(
+            Some(&DiskError::SchedulerClosed),
+            DiskError::from_io_error(&err)
+        );
+        assert_eq!(1, scheduler.get_disk_manager().get_num_writes());
+    }
+
+    #[test]
+    fn io_error_through_callback() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let db_file = db_file.to_str().unwrap();
+        drop(DiskManager::new(db_file).unwrap());
+        let options = DiskManagerOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let scheduler = DiskScheduler::new(Box::new(
+            DiskManager::with_options(db_file, options).unwrap(),
+        ));
+
+        // writing through a read-only disk manager fails the request
+        let rx = schedule_write(&scheduler, 0, 1);
+        assert!(rx.blocking_recv().unwrap().is_err());
+
+        // the worker survives it, and a read of a page that was never
+        // allocated fails too
+        let err = scheduler.schedule_read(100).blocking_recv().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.unwrap_err().kind());
+    }
+
+    #[test]
+    fn join_concurrent_reads() {
+        let scheduler = DiskScheduler::with_workers(Box::new(DiskManagerMemory::new()), 4);
+        let writes: Vec<_> = (0..8)
+            .map(|i| scheduler.schedule_write(i, Box::new([i as u8 + 1; BUSTUB_PAGE_SIZE])))
+            .collect();
+        for rx in writes {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+
+        // Scenario: fire off 8 reads before waiting for any of them, then
+        // join them all.
+        let reads: Vec<_> = (0..8).map(|i| scheduler.schedule_read(i)).collect();
+        for (i, rx) in reads.into_iter().enumerate() {
+            let data = rx.blocking_recv().unwrap().unwrap();
+            assert_eq!([i as u8 + 1; BUSTUB_PAGE_SIZE], *data);
+        }
+    }
+
+    #[test]
+    fn same_page_in_order() {
+        let scheduler = DiskScheduler::with_workers(Box::new(DiskManagerMemory::new()), 4);
+
+        // Scenario: write, read, write and read page 5 without waiting in
+        // between, each read sees the write scheduled right before it.
+        let mut writes = Vec::new();
+        let mut reads = Vec::new();
+        for i in 0..2 {
+            writes.push(schedule_write(&scheduler, 5, i + 1));
+            reads.push(scheduler.schedule_read(5));
+        }
+        for rx in writes {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        for (i, rx) in reads.into_iter().enumerate() {
+            assert_eq!(i as u8 + 1, rx.blocking_recv().unwrap().unwrap()[0]);
+        }
+    }
+
+    #[test]
+    fn concurrent_workers() {
+        let num_pages = 16;
+        let disk_manager = DiskManagerMemory::new();
+        for _ in 0..num_pages {
+            disk_manager.allocate_page(MAIN_FILE_ID, 1, 0).unwrap();
+        }
+        let scheduler = DiskScheduler::with_workers(Box::new(disk_manager), 4);
+        assert_eq!(4, scheduler.get_num_workers());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Scenario: 1000 reads and writes to random pages. Every read sees the
+        // last write to its page scheduled before it, whichever worker runs it.
+        let mut last_written = vec![0u8; num_pages];
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        for i in 0..1000 {
+            let page_id = rng.gen_range(0..num_pages);
+            if rng.gen_bool(0.5) {
+                let value = (i % 255) as u8 + 1;
+                last_written[page_id] = value;
+                writes.push(schedule_write(&scheduler, page_id as PageId, value));
+            } else {
+                let rx = scheduler.schedule_read(page_id as PageId);
+                reads.push((rx, last_written[page_id]));
+            }
+        }
+        for rx in writes {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        for (rx, expected) in reads {
+            assert_eq!(expected, rx.blocking_recv().unwrap().unwrap()[0]);
+        }
+        // and the disk ends up with the last write to every page
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        for (page_id, &expected) in last_written.iter().enumerate() {
+            scheduler
+                .get_disk_manager()
+                .read_page(page_id as PageId, &mut buf)
+                .unwrap();
+            assert_eq!(expected, buf[0]);
+        }
+    }
+
+    /// The page reads and writes a GatedDiskManager executed, in order.
+    type Executed = Arc<Mutex<Vec<(&'static str, PageId)>>>;
+
+    /// Holds every page write until the test releases the gate, so that
+    /// requests pile up in the queue.
+    struct GatedDiskManager {
+        inner: DiskManagerMemory,
+        gate: Arc<Mutex<()>>,
+        executed: Executed,
+    }
+
+    impl DiskManagerTrait for GatedDiskManager {
+        fn write_page(&self, page_id: PageId, page_data: &[u8]) -> io::Result<()> {
+            let _gate = self.gate.lock().unwrap();
+            self.executed.lock().unwrap().push(("write", page_id));
+            self.inner.write_page(page_id, page_data)
+        }
+
+        fn read_page(&self, page_id: PageId, page_data: &mut [u8]) -> io::Result<()> {
+            self.executed.lock().unwrap().push(("read", page_id));
+            self.inner.read_page(page_id, page_data)
+        }
+
+        fn allocate_page(
+            &self,
+            file_id: FileId,
+            num_instances: usize,
+            instance_index: usize,
+        ) -> io::Result<PageId> {
+            self.inner
+                .allocate_page(file_id, num_instances, instance_index)
+        }
+
+        fn deallocate_page(&self, page_id: PageId) -> io::Result<()> {
+            self.inner.deallocate_page(page_id)
+        }
+
+        fn write_log(&self, log_data: &[u8]) -> io::Result<()> {
+            self.inner.write_log(log_data)
+        }
+
+        fn read_log(&self, log_data: &mut [u8], offset: usize) -> io::Result<bool> {
+            self.inner.read_log(log_data, offset)
+        }
+
+        fn sync(&self) -> io::Result<()> {
+            self.inner.sync()
+        }
+
+        fn get_num_flushes(&self) -> i32 {
+            self.inner.get_num_flushes()
+        }
+
+        fn get_num_writes(&self) -> i32 {
+            self.inner.get_num_writes()
+        }
+
+        fn create_file(&self) -> io::Result<FileId> {
+            self.inner.create_file()
+        }
+
+        fn delete_file(&self, file_id: FileId) -> io::Result<()> {
+            self.inner.delete_file(file_id)
+        }
+
+        fn allocated_pages(&self) -> usize {
+            self.inner.allocated_pages()
+        }
+
+        fn get_io_stats(&self) -> IoStats {
+            self.inner.get_io_stats()
+        }
+    }
+
+    fn schedule_write(
+        scheduler: &DiskScheduler,
+        page_id: PageId,
+        value: u8,
+    ) -> oneshot::Receiver<io::Result<()>> {
+        let mut data = Box::new([0; BUSTUB_PAGE_SIZE]);
+        data[0] = value;
+        scheduler.schedule_write(page_id, data)
+    }
+
+    #[test]
+    fn coalesce_queued_writes() {
+        let gate = Arc::new(Mutex::new(()));
+        let scheduler = DiskScheduler::new(Box::new(GatedDiskManager {
+            inner: DiskManagerMemory::new(),
+            gate: gate.clone(),
+            executed: Default::default(),
+        }));
+
+        // Scenario: 10 writes to one page are scheduled while the worker is
+        // stuck. At most the first one has been started, the others are
+        // folded into one write, and the superseded ones complete right away.
+        let held = gate.lock().unwrap();
+        let mut callbacks: Vec<_> = (1..=10).map(|i| schedule_write(&scheduler, 3, i)).collect();
+        let last = callbacks.pop().unwrap();
+        for rx in callbacks.into_iter().skip(1) {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        drop(held);
+        last.blocking_recv().unwrap().unwrap();
+
+        assert!(scheduler.get_disk_manager().get_num_writes() <= 2);
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        scheduler.get_disk_manager().read_page(3, &mut buf).unwrap();
+        assert_eq!(10, buf[0]);
+    }
+
+    #[test]
+    fn coalesce_not_past_read() {
+        let gate = Arc::new(Mutex::new(()));
+        let scheduler = DiskScheduler::new(Box::new(GatedDiskManager {
+            inner: DiskManagerMemory::new(),
+            gate: gate.clone(),
+            executed: Default::default(),
+        }));
+
+        // Scenario: keep the worker busy with page 0, then write, read and
+        // write page 3. The read sits between the writes, so they are not
+        // coalesced and the read sees the first one.
+        let held = gate.lock().unwrap();
+        let busy = schedule_write(&scheduler, 0, 1);
+        let first = schedule_write(&scheduler, 3, 1);
+        let read = scheduler.schedule_read(3);
+        let second = schedule_write(&scheduler, 3, 2);
+        // Scenario: a third write can still be folded into the second one.
+        let third = schedule_write(&scheduler, 3, 3);
+        second.blocking_recv().unwrap().unwrap();
+        drop(held);
+
+        for rx in [busy, first, third] {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        assert_eq!(1, read.blocking_recv().unwrap().unwrap()[0]);
+        assert_eq!(3, scheduler.get_disk_manager().get_num_writes());
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        scheduler.get_disk_manager().read_page(3, &mut buf).unwrap();
+        assert_eq!(3, buf[0]);
+    }
+
+    #[test]
+    fn queue_stats() {
+        let gate = Arc::new(Mutex::new(()));
+        let scheduler = DiskScheduler::new(Box::new(GatedDiskManager {
+            inner: DiskManagerMemory::new(),
+            gate: gate.clone(),
+            executed: Default::default(),
+        }));
+        assert_eq!(SchedulerStats::default(), scheduler.get_stats());
+
+        // Scenario: 5 writes to different pages pile up behind the stuck
+        // worker, which took at most the first one from the queue. They all
+        // wait in the queue until the gate opens.
+        let held = gate.lock().unwrap();
+        let callbacks: Vec<_> = (0..5).map(|i| schedule_write(&scheduler, i, 1)).collect();
+        thread::sleep(Duration::from_millis(50));
+        drop(held);
+        for rx in callbacks {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+
+        let stats = scheduler.get_stats();
+        assert_eq!(5, stats.num_requests);
+        assert!(stats.max_queue_depth >= 4, "{:?}", stats);
+        assert!(
+            stats.queued_time >= Duration::from_millis(150),
+            "{:?}",
+            stats
+        );
+        assert_eq!(5, scheduler.get_disk_manager().get_io_stats().writes.count);
+    }
+
+    fn page_buf(value: u8) -> PageBuf {
+        let mut data = Box::new([0; BUSTUB_PAGE_SIZE]);
+        data[0] = value;
+        data
+    }
+
+    #[test]
+    fn write_batch() {
+        let scheduler = DiskScheduler::with_workers(Box::new(DiskManagerMemory::new()), 3);
+
+        // Scenario: a batch over the pages of every worker is split into one
+        // request per worker, and completes page by page. A read scheduled
+        // after the batch sees it.
+        let batch = (0..10).map(|i| (i, page_buf(i as u8 + 1))).collect();
+        let receivers = scheduler.schedule_write_batch(batch);
+        let read = scheduler.schedule_read(7);
+        assert_eq!(10, receivers.len());
+        for rx in receivers {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        assert_eq!(8, read.blocking_recv().unwrap().unwrap()[0]);
+        assert_eq!(4, scheduler.get_stats().num_requests);
+
+        // Scenario: after a shutdown every page of a batch fails.
+        scheduler.shutdown();
+        for rx in scheduler.schedule_write_batch(vec![(1, page_buf(1)), (2, page_buf(2))]) {
+            let err = rx.blocking_recv().unwrap().unwrap_err();
+            assert_eq!(
+                Some(&DiskError::SchedulerClosed),
+                DiskError::from_io_error(&err)
+            );
+        }
+    }
+
+    #[test]
+    fn write_batch_not_coalesced() {
+        let gate = Arc::new(Mutex::new(()));
+        let scheduler = DiskScheduler::new(Box::new(GatedDiskManager {
+            inner: DiskManagerMemory::new(),
+            gate: gate.clone(),
+            executed: Default::default(),
+        }));
+
+        // Scenario: keep the worker busy with page 0, then write page 3, write
+        // it in a batch, and write it again. The last write is not folded into
+        // the first one past the batch, so the batch does not win.
+        let held = gate.lock().unwrap();
+        let busy = schedule_write(&scheduler, 0, 1);
+        let first = schedule_write(&scheduler, 3, 1);
+        let batch = scheduler.schedule_write_batch(vec![(3, page_buf(2))]);
+        let last = schedule_write(&scheduler, 3, 3);
+        drop(held);
+
+        for rx in [busy, first, last].into_iter().chain(batch) {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        assert_eq!(4, scheduler.get_disk_manager().get_num_writes());
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        scheduler.get_disk_manager().read_page(3, &mut buf).unwrap();
+        assert_eq!(3, buf[0]);
+    }
+
+    #[test]
+    fn log_appends_in_order() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let scheduler = Arc::new(DiskScheduler::with_workers(
+            Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap()),
+            2,
+        ));
+
+        // Scenario: two threads interleave page writes and log appends. The
+        // log ends up with the appends in the order they were scheduled, which
+        // the threads record under a lock.
+        let submitted = Arc::new(Mutex::new(Vec::new()));
+        let threads: Vec<_> = (0..2u8)
+            .map(|t| {
+                let scheduler = scheduler.clone();
+                let submitted = submitted.clone();
+                thread::spawn(move || {
+                    let mut callbacks = Vec::new();
+                    for i in 0..100u8 {
+                        let page_id = (t as PageId) * 100 + i as PageId;
+                        callbacks.push(schedule_write(&scheduler, page_id, i + 1));
+                        let record = vec![t + 1, i, i.wrapping_mul(7), t ^ i];
+                        let mut submitted = submitted.lock().unwrap();
+                        callbacks.push(scheduler.schedule_write_log(record.clone()));
+                        submitted.extend(record);
+                    }
+                    for rx in callbacks {
+                        rx.blocking_recv().unwrap().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let submitted = submitted.lock().unwrap();
+        assert_eq!(
+            *submitted,
+            std::fs::read(db_file.with_extension("log")).unwrap()
+        );
+        let log = scheduler.schedule_read_log(4, 8).blocking_recv().unwrap();
+        assert_eq!(submitted[4..12], log.unwrap()[..]);
+        // Scenario: a read past the end of the log gets no bytes, one running
+        // past it is padded with zeros.
+        let log = scheduler.schedule_read_log(submitted.len(), 8);
+        assert!(log.blocking_recv().unwrap().unwrap().is_empty());
+        let log = scheduler.schedule_read_log(submitted.len() - 2, 4);
+        let log = log.blocking_recv().unwrap().unwrap();
+        assert_eq!(submitted[submitted.len() - 2..], log[..2]);
+        assert_eq!([0, 0], log[2..]);
+    }
+
+    #[test]
+    fn log_not_behind_page_writes() {
+        let gate = Arc::new(Mutex::new(()));
+        let scheduler = DiskScheduler::new(Box::new(GatedDiskManager {
+            inner: DiskManagerMemory::new(),
+            gate: gate.clone(),
+            executed: Default::default(),
+        }));
+
+        // Scenario: the only page worker is stuck on a write, log requests
+        // still go through.
+        let held = gate.lock().unwrap();
+        let write = schedule_write(&scheduler, 0, 1);
+        let append = scheduler.schedule_write_log(b"record".to_vec());
+        append.blocking_recv().unwrap().unwrap();
+        let log = scheduler.schedule_read_log(0, 6).blocking_recv().unwrap();
+        assert_eq!(b"record", &log.unwrap()[..]);
+        drop(held);
+        write.blocking_recv().unwrap().unwrap();
+
+        // Scenario: after a shutdown log requests fail like the others.
+        scheduler.shutdown();
+        let err = scheduler.schedule_write_log(b"late".to_vec());
+        let err = err.blocking_recv().unwrap().unwrap_err();
+        assert_eq!(
+            Some(&DiskError::SchedulerClosed),
+            DiskError::from_io_error(&err)
+        );
+        assert_eq!(1, scheduler.get_disk_manager().get_num_flushes());
+    }
+
+    /// A GatedDiskManager behind gate with num_pages pages allocated, along
+    /// with the order it executes page reads and writes in.
+    fn gated_disk(gate: &Arc<Mutex<()>>, num_pages: usize) -> (Box<GatedDiskManager>, Executed) {
+        let inner = DiskManagerMemory::new();
+        for _ in 0..num_pages {
+            inner.allocate_page(MAIN_FILE_ID, 1, 0).unwrap();
+        }
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let disk = Box::new(GatedDiskManager {
+            inner,
+            gate: gate.clone(),
+            executed: executed.clone(),
+        });
+        (disk, executed)
+    }
+
+    /// Returns where the first read or write of page_id is in executed.
+    fn position(executed: &Executed, op: &str, page_id: PageId) -> usize {
+        let executed = executed.lock().unwrap();
+        executed
+            .iter()
+            .position(|&(executed_op, executed_page_id)| {
+                executed_op == op && executed_page_id == page_id
+            })
+            .unwrap()
+    }
+
+    /// Schedules a write of page_id and waits for the worker to start it, so
+    /// that the worker is stuck on it while gate is held.
+    fn schedule_busy_write(
+        scheduler: &DiskScheduler,
+        page_id: PageId,
+    ) -> oneshot::Receiver<io::Result<()>> {
+        let busy = schedule_write(scheduler, page_id, 1);
+        while scheduler.get_stats().num_requests == 0 {
+            thread::yield_now();
+        }
+        busy
+    }
+
+    #[test]
+    fn urgent_read_ahead_of_background_writes() {
+        let gate = Arc::new(Mutex::new(()));
+        let (disk, executed) = gated_disk(&gate, 101);
+        // background requests never wait too long here
+        let scheduler = DiskScheduler::with_background_max_wait(disk, 1, Duration::MAX);
+
+        // Scenario: while the worker is stuck on a write, 99 background writes
+        // and then an urgent read of another page queue up. The read is
+        // executed right after the write at hand.
+        let held = gate.lock().unwrap();
+        let mut writes = vec![schedule_busy_write(&scheduler, 1)];
+        writes.extend((2..=100).map(|i| schedule_write(&scheduler, i, 1)));
+        let read = scheduler.schedule_urgent_read(0);
+        drop(held);
+        assert_eq!(
+            [0; BUSTUB_PAGE_SIZE],
+            *read.blocking_recv().unwrap().unwrap()
+        );
+        for rx in writes {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        assert_eq!(1, position(&executed, "read", 0));
+        assert_eq!(101, executed.lock().unwrap().len());
+
+        // Scenario: an urgent read does not overtake a write of its page.
+        let held = gate.lock().unwrap();
+        let busy = schedule_write(&scheduler, 1, 2);
+        let write = schedule_write(&scheduler, 5, 2);
+        let read = scheduler.schedule_urgent_read(5);
+        drop(held);
+        assert_eq!(2, read.blocking_recv().unwrap().unwrap()[0]);
+        for rx in [busy, write] {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn background_write_not_starved() {
+        let gate = Arc::new(Mutex::new(()));
+        let (disk, executed) = gated_disk(&gate, 102);
+        let scheduler = DiskScheduler::new(disk);
+
+        // Scenario: while the worker is stuck on a write, a background write
+        // queues up behind 100 urgent reads. Once it waited for
+        // BACKGROUND_MAX_WAIT, it is executed before all of them.
+        let held = gate.lock().unwrap();
+        let busy = schedule_busy_write(&scheduler, 0);
+        let reads: Vec<_> = (1..=100)
+            .map(|i| scheduler.schedule_urgent_read(i))
+            .collect();
+        let write = schedule_write(&scheduler, 101, 1);
+        thread::sleep(BACKGROUND_MAX_WAIT * 2);
+        drop(held);
+        for rx in [busy, write] {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        for rx in reads {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        assert_eq!(1, position(&executed, "write", 101));
+    }
+
+    #[test]
+    fn starved_write_not_ahead_of_urgent_read() {
+        let gate = Arc::new(Mutex::new(()));
+        let (disk, executed) = gated_disk(&gate, 6);
+        let scheduler = DiskScheduler::new(disk);
+
+        // Scenario: an urgent read of page 5 is queued, then a background
+        // write of page 5, and the write waits for longer than
+        // BACKGROUND_MAX_WAIT. It is still executed after the read, which
+        // sees the data from before the write.
+        let held = gate.lock().unwrap();
+        let busy = schedule_busy_write(&scheduler, 0);
+        let read = scheduler.schedule_urgent_read(5);
+        let write = schedule_write(&scheduler, 5, 2);
+        thread::sleep(BACKGROUND_MAX_WAIT * 2);
+        drop(held);
+        assert_eq!(0, read.blocking_recv().unwrap().unwrap()[0]);
+        for rx in [busy, write] {
+            rx.blocking_recv().unwrap().unwrap();
+        }
+        assert!(position(&executed, "read", 5) < position(&executed, "write", 5));
+        let mut buf = [0; BUSTUB_PAGE_SIZE];
+        scheduler.get_disk_manager().read_page(5, &mut buf).unwrap();
+        assert_eq!(2, buf[0]);
     }
 }
@@ -1,2 +1,3 @@
 pub mod disk_manager;
+pub mod disk_manager_memory;
 pub mod disk_scheduler;
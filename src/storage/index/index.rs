@@ -818,7 +818,7 @@ mod tests {
             ]),
             vec![0, 1],
         );
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = buffer_pool_manager::BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut index = BPlusTreeIndex::new(index_metadata, buffer_pool_manager, 2, 3);
 
@@ -879,7 +879,7 @@ mod tests {
             ]),
             vec![0, 1],
         );
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = buffer_pool_manager::BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut index = BPlusTreeIndex::new(index_metadata, buffer_pool_manager, 4, 5);
 
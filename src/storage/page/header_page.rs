@@ -0,0 +1,213 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::common::config::{PageId, BUSTUB_PAGE_SIZE};
+use crate::storage::page::page::{PageType, SIZE_PAGE_HEADER};
+use crate::storage::page::page_view::{PageLayout, TypedView};
+
+/// The longest name a record of the header page can have, in bytes.
+pub const MAX_NAME_SIZE: usize = 32;
+
+// The body of the header page:
+// | record count (4) | record 0 | record 1 | ... |
+// Each record is:
+// | name (MAX_NAME_SIZE, zero padded) | root page id (4) |
+const OFFSET_RECORD_COUNT: usize = 0;
+const OFFSET_RECORDS: usize = 4;
+const SIZE_RECORD: usize = MAX_NAME_SIZE + 4;
+
+/// The most records the header page has room for.
+pub const MAX_RECORDS: usize = (BUSTUB_PAGE_SIZE - SIZE_PAGE_HEADER - OFFSET_RECORDS) / SIZE_RECORD;
+
+/// The header page maps names, e.g. of tables and indexes, to their root page
+/// ids, so that they can be found again after a restart. Names are 1 to
+/// MAX_NAME_SIZE bytes long and contain no NUL.
+pub struct HeaderPage;
+
+impl PageLayout for HeaderPage {
+    const PAGE_TYPE: PageType = PageType::Header;
+}
+
+impl<G: Deref<Target = [u8; BUSTUB_PAGE_SIZE]>> TypedView<HeaderPage, G> {
+    /// @return the number of records, at most MAX_RECORDS even if a damaged
+    /// page claims more
+    pub fn get_record_count(&self) -> usize {
+        (self.read_u32(OFFSET_RECORD_COUNT) as usize).min(MAX_RECORDS)
+    }
+
+    /// @return the root page id recorded for name, or None if there is no
+    /// record of name
+    pub fn get_root_id(&self, name: &str) -> Option<PageId> {
+        let index = self.find_record(name)?;
+        Some(self.read_u32(Self::record_offset(index) + MAX_NAME_SIZE))
+    }
+
+    /// @return the names of all records, in the order they were inserted
+    pub fn get_names(&self) -> Vec<String> {
+        (0..self.get_record_count())
+            .map(|index| {
+                let offset = Self::record_offset(index);
+                let name = &self.body()[offset..offset + MAX_NAME_SIZE];
+                let len = name.iter().position(|&b| b == 0).unwrap_or(MAX_NAME_SIZE);
+                String::from_utf8_lossy(&name[..len]).into_owned()
+            })
+            .collect()
+    }
+
+    fn record_offset(index: usize) -> usize {
+        OFFSET_RECORDS + index * SIZE_RECORD
+    }
+
+    /// @return the index of the record of name
+    fn find_record(&self, name: &str) -> Option<usize> {
+        let name = Self::padded_name(name)?;
+        (0..self.get_record_count()).find(|&index| {
+            let offset = Self::record_offset(index);
+            self.body()[offset..offset + MAX_NAME_SIZE] == name
+        })
+    }
+
+    /// @return name as stored in a record, or None if it is not a valid name
+    fn padded_name(name: &str) -> Option<[u8; MAX_NAME_SIZE]> {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() || bytes.len() > MAX_NAME_SIZE || bytes.contains(&0) {
+            return None;
+        }
+        let mut padded = [0; MAX_NAME_SIZE];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        Some(padded)
+    }
+}
+
+impl<G: DerefMut<Target = [u8; BUSTUB_PAGE_SIZE]>> TypedView<HeaderPage, G> {
+    /// Adds a record of name.
+    /// @return false if name already has a record, is not a valid name, or
+    /// the page is full
+    pub fn insert_record(&mut self, name: &str, root_id: PageId) -> bool {
+        let count = self.get_record_count();
+        let Some(padded) = Self::padded_name(name) else {
+            return false;
+        };
+        if count == MAX_RECORDS || self.find_record(name).is_some() {
+            return false;
+        }
+        let offset = Self::record_offset(count);
+        self.body_mut()[offset..offset + MAX_NAME_SIZE].copy_from_slice(&padded);
+        self.write_u32(offset + MAX_NAME_SIZE, root_id);
+        self.write_u32(OFFSET_RECORD_COUNT, count as u32 + 1);
+        true
+    }
+
+    /// Changes the root page id recorded for name.
+    /// @return false if there is no record of name
+    pub fn update_record(&mut self, name: &str, root_id: PageId) -> bool {
+        let Some(index) = self.find_record(name) else {
+            return false;
+        };
+        self.write_u32(Self::record_offset(index) + MAX_NAME_SIZE, root_id);
+        true
+    }
+
+    /// Removes the record of name. The records after it move up.
+    /// @return false if there is no record of name
+    pub fn delete_record(&mut self, name: &str) -> bool {
+        let Some(index) = self.find_record(name) else {
+            return false;
+        };
+        let count = self.get_record_count();
+        let start = Self::record_offset(index);
+        let end = Self::record_offset(count);
+        self.body_mut().copy_within(start + SIZE_RECORD..end, start);
+        self.body_mut()[end - SIZE_RECORD..end].fill(0);
+        self.write_u32(OFFSET_RECORD_COUNT, count as u32 - 1);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::replacer::{AccessType, ReplacerType};
+    use crate::storage::disk::disk_manager_memory::DiskManagerMemory;
+    use crate::storage::page::page::Page;
+    use crate::storage::page::page_view::{PageView, PageViewMut};
+
+    #[test]
+    fn records() {
+        let page = Page::new();
+        let mut header = PageViewMut::<HeaderPage>::init_page(&page);
+
+        // Scenario: records are found by name, and each name has one record.
+        assert!(header.insert_record("orders", 3));
+        assert!(header.insert_record("orders_pk", 4));
+        assert!(!header.insert_record("orders", 5));
+        assert_eq!(Some(3), header.get_root_id("orders"));
+        assert_eq!(Some(4), header.get_root_id("orders_pk"));
+        assert_eq!(None, header.get_root_id("order"));
+
+        // Scenario: invalid names are rejected.
+        assert!(!header.insert_record("", 1));
+        assert!(!header.insert_record(&"x".repeat(MAX_NAME_SIZE + 1), 1));
+        assert!(header.insert_record(&"x".repeat(MAX_NAME_SIZE), 1));
+
+        // Scenario: updating and deleting, the later records move up.
+        assert!(header.update_record("orders", 6));
+        assert!(!header.update_record("customers", 6));
+        assert!(header.delete_record("orders"));
+        assert!(!header.delete_record("orders"));
+        assert_eq!(
+            vec!["orders_pk".to_string(), "x".repeat(MAX_NAME_SIZE)],
+            header.get_names()
+        );
+        assert_eq!(Some(4), header.get_root_id("orders_pk"));
+
+        // Scenario: the page fills up.
+        for i in header.get_record_count()..MAX_RECORDS {
+            assert!(header.insert_record(&format!("t{}", i), i as PageId));
+        }
+        assert!(!header.insert_record("one_more", 1));
+        assert_eq!(
+            Some(MAX_RECORDS as PageId - 1),
+            header.get_root_id(&format!("t{}", MAX_RECORDS - 1))
+        );
+    }
+
+    #[test]
+    fn record_count_out_of_range() {
+        let page = Page::new();
+        let mut header = PageViewMut::<HeaderPage>::init_page(&page);
+        assert!(header.insert_record("orders", 3));
+
+        // Scenario: a count past the end of the page is cut to the records
+        // the page has room for, instead of reading past the body.
+        header.write_u32(OFFSET_RECORD_COUNT, u32::MAX);
+        assert_eq!(MAX_RECORDS, header.get_record_count());
+        assert_eq!(MAX_RECORDS, header.get_names().len());
+        assert_eq!(Some(3), header.get_root_id("orders"));
+        assert_eq!(None, header.get_root_id("customers"));
+        assert!(!header.insert_record("customers", 4));
+        assert!(header.delete_record("orders"));
+        assert_eq!(MAX_RECORDS - 1, header.get_record_count());
+    }
+
+    #[test]
+    fn survives_write_back() {
+        let disk_manager = Box::new(DiskManagerMemory::new());
+        let bpm = BufferPoolManager::new(1, disk_manager, ReplacerType::LruK(2));
+
+        // Scenario: the header page reads back after it was evicted, and
+        // does not view as another layout.
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id().unwrap();
+        PageViewMut::<HeaderPage>::init_page(&page).insert_record("orders", 3);
+        bpm.unpin_page(page_id, true).unwrap();
+        let other = bpm.new_page().unwrap();
+        assert!(PageView::<HeaderPage>::of(&other).is_none());
+        bpm.unpin_page(other.get_page_id().unwrap(), false).unwrap();
+
+        let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+        let header = PageView::<HeaderPage>::of(&page).unwrap();
+        assert_eq!(Some(3), header.get_root_id("orders"));
+        assert_eq!(1, header.get_record_count());
+    }
+}
@@ -1,2 +1,4 @@
+pub mod header_page;
 pub mod page;
 pub mod page_guard;
+pub mod page_view;
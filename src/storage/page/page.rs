@@ -1,30 +1,94 @@
 use std::sync::Arc;
 
+use parking_lot::lock_api::{
+    ArcRwLockReadGuard, ArcRwLockUpgradableReadGuard, ArcRwLockWriteGuard,
+};
 use parking_lot::{
-    MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RawRwLock, RwLock, RwLockReadGuard,
+    RwLockWriteGuard,
 };
 
 use crate::common::config::{Lsn, PageId, BUSTUB_PAGE_SIZE};
 
-pub type RefPageData<'a> = MappedRwLockReadGuard<'a, [u8; BUSTUB_PAGE_SIZE]>;
-pub type MutRefPageData<'a> = MappedRwLockWriteGuard<'a, [u8; BUSTUB_PAGE_SIZE]>;
+pub type RefPageData<'a> = RwLockReadGuard<'a, [u8; BUSTUB_PAGE_SIZE]>;
+pub type MutRefPageData<'a> = RwLockWriteGuard<'a, [u8; BUSTUB_PAGE_SIZE]>;
+/// A part of the data of a page, e.g. its header, read latched.
+pub type RefPageBytes<'a> = MappedRwLockReadGuard<'a, [u8]>;
+/// A part of the data of a page, write latched.
+pub type MutRefPageBytes<'a> = MappedRwLockWriteGuard<'a, [u8]>;
+
+/// The read latch of a page, held until dropped.
+pub type PageReadLatch = ArcRwLockReadGuard<RawRwLock, [u8; BUSTUB_PAGE_SIZE]>;
+/// The write latch of a page, held until dropped.
+pub type PageWriteLatch = ArcRwLockWriteGuard<RawRwLock, [u8; BUSTUB_PAGE_SIZE]>;
+/// A read latch that can be upgraded to the write latch. Only one thread at a
+/// time may hold it, next to any number of plain read latches.
+pub type PageUpgradableLatch = ArcRwLockUpgradableReadGuard<RawRwLock, [u8; BUSTUB_PAGE_SIZE]>;
+
+// The header at the start of every page:
+// | page type (4) | checksum (4) | LSN (8) |
+// Integers are stored little endian. What follows is the body, which belongs
+// to the page format the page type names.
+pub const SIZE_PAGE_HEADER: usize = 16;
+const OFFSET_PAGE_TYPE: usize = 0;
+const OFFSET_CHECKSUM: usize = 4;
+const OFFSET_LSN: usize = 8;
+
+/// What a page holds, recorded in its header so that a page can be told apart
+/// given nothing but its id, e.g. by recovery or debugging tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum PageType {
+    /// A page that was never given a type, e.g. one that is all zeros.
+    Invalid = 0,
+    /// The header page, which maps names to the root pages of tables and
+    /// indexes.
+    Header = 1,
+    /// A page of tuples of a table heap.
+    Table = 2,
+    /// An internal page of a B+ tree index.
+    BPlusTreeInternal = 3,
+    /// A leaf page of a B+ tree index.
+    BPlusTreeLeaf = 4,
+    /// A page of catalog data.
+    Catalog = 5,
+}
 
-const SIZE_PAGE_HEADER: usize = 8;
-const OFFSET_PAGE_START: usize = 0;
-const OFFSET_LSN: usize = 4;
+impl PageType {
+    /// @return the page type stored as tag, or None if the tag is unknown,
+    /// e.g. because the page is corrupt
+    pub fn from_tag(tag: u32) -> Option<PageType> {
+        match tag {
+            0 => Some(PageType::Invalid),
+            1 => Some(PageType::Header),
+            2 => Some(PageType::Table),
+            3 => Some(PageType::BPlusTreeInternal),
+            4 => Some(PageType::BPlusTreeLeaf),
+            5 => Some(PageType::Catalog),
+            _ => None,
+        }
+    }
+}
 
 /// Page is the basic unit of storage within the database system. Page provides
 /// a wrapper for actual data pages being held in main memory. Page also
 /// contains book-keeping information that is used by the buffer pool manager,
 /// e.g. pin count, dirty flag, page id, etc.
 #[derive(Debug, Clone)]
-pub struct Page(Arc<RwLock<PageInner>>);
+pub struct Page(Arc<PageInner>);
 
 #[derive(Debug)]
 struct PageInner {
-    // The actual data that is stored within a page.
-    data: [u8; BUSTUB_PAGE_SIZE],
+    // The actual data that is stored within a page. Its lock doubles as the
+    // page latch, so it is kept apart from the book-keeping below: pinning or
+    // unpinning a page must not wait for whoever holds the latch.
+    data: Arc<RwLock<[u8; BUSTUB_PAGE_SIZE]>>,
+
+    meta: Mutex<PageMeta>,
+}
 
+#[derive(Debug)]
+struct PageMeta {
     // The ID of this page.
     page_id: Option<PageId>,
 
@@ -35,81 +99,510 @@ struct PageInner {
     is_dirty: bool,
 }
 
+/// @return the LSN in the header of the page data, e.g. of a snapshot
+pub fn lsn_of(data: &[u8; BUSTUB_PAGE_SIZE]) -> Lsn {
+    Lsn::from_le_bytes(
+        data[OFFSET_LSN..OFFSET_LSN + std::mem::size_of::<Lsn>()]
+            .try_into()
+            .unwrap(),
+    )
+}
+
+/// @return the page type in the header of the page data, or None if it is
+/// an unknown one
+pub fn page_type_of(data: &[u8; BUSTUB_PAGE_SIZE]) -> Option<PageType> {
+    let tag = u32::from_le_bytes(
+        data[OFFSET_PAGE_TYPE..OFFSET_PAGE_TYPE + 4]
+            .try_into()
+            .unwrap(),
+    );
+    PageType::from_tag(tag)
+}
+
+/// Sets the page type in the header of the page data.
+pub fn set_page_type_of(data: &mut [u8; BUSTUB_PAGE_SIZE], page_type: PageType) {
+    data[OFFSET_PAGE_TYPE..OFFSET_PAGE_TYPE + 4].copy_from_slice(&(page_type as u32).to_le_bytes());
+}
+
+/// Sets the LSN in the header of the page data.
+pub fn set_lsn_of(data: &mut [u8; BUSTUB_PAGE_SIZE], lsn: Lsn) {
+    data[OFFSET_LSN..OFFSET_LSN + std::mem::size_of::<Lsn>()].copy_from_slice(&lsn.to_le_bytes());
+}
+
 impl Page {
     /// Constructor. Zeros out the page data.
     pub fn new() -> Page {
         let inner = PageInner {
-            data: [0; BUSTUB_PAGE_SIZE],
-            page_id: None,
-            pin_count: 0,
-            is_dirty: false,
+            data: Arc::new(RwLock::new([0; BUSTUB_PAGE_SIZE])),
+            meta: Mutex::new(PageMeta {
+                page_id: None,
+                pin_count: 0,
+                is_dirty: false,
+            }),
         };
-        Page(Arc::new(RwLock::new(inner)))
+        Page(Arc::new(inner))
     }
 
     pub fn reset(&self) {
-        let mut p = self.0.write();
-        p.data.fill(0);
-        p.page_id = None;
-        p.pin_count = 0;
-        p.is_dirty = false;
+        self.0.data.write().fill(0);
+        let mut meta = self.0.meta.lock();
+        meta.page_id = None;
+        meta.pin_count = 0;
+        meta.is_dirty = false;
     }
 
     /// @return the actual data contained within this page
     pub fn get_data(&self) -> RefPageData {
-        RwLockReadGuard::map(self.0.read(), |i| &i.data)
+        self.0.data.read()
     }
     pub fn get_data_mut(&self) -> MutRefPageData {
-        RwLockWriteGuard::map(self.0.write(), |i| &mut i.data)
+        self.0.data.write()
+    }
+
+    /// Copies the data of this page under the read latch, which is released
+    /// again as soon as the copy is done. The copy is consistent: a writer
+    /// either finished all of its changes before it or makes none of them in
+    /// it. Use it to write a page out without latching it during the I/O.
+    pub fn snapshot(&self) -> Box<[u8; BUSTUB_PAGE_SIZE]> {
+        Box::new(*self.0.data.read())
+    }
+
+    /// @return the header of this page, see SIZE_PAGE_HEADER. It is only
+    /// changed through the setters of its fields.
+    pub fn page_header(&self) -> RefPageBytes<'_> {
+        RwLockReadGuard::map(self.0.data.read(), |data| &data[..SIZE_PAGE_HEADER])
+    }
+
+    /// @return the body of this page, everything after the header. Page
+    /// formats lay their data out in the body, offset 0 of the body is the
+    /// first byte after the header.
+    pub fn body(&self) -> RefPageBytes<'_> {
+        RwLockReadGuard::map(self.0.data.read(), |data| &data[SIZE_PAGE_HEADER..])
+    }
+
+    /// @return the body of this page for writing. Page formats write through
+    /// it, so they cannot overwrite the header.
+    pub fn body_mut(&self) -> MutRefPageBytes<'_> {
+        RwLockWriteGuard::map(self.0.data.write(), |data| &mut data[SIZE_PAGE_HEADER..])
+    }
+
+    /// Acquires the read latch of this page. Unlike `get_data`, the returned
+    /// latch does not borrow the page, so it can be stored next to it.
+    pub fn rlatch(&self) -> PageReadLatch {
+        self.0.data.read_arc()
+    }
+
+    /// Acquires the write latch of this page.
+    pub fn wlatch(&self) -> PageWriteLatch {
+        self.0.data.write_arc()
+    }
+
+    /// Tries to acquire the upgradable read latch of this page without
+    /// blocking. Fails if a writer or another upgradable reader holds it.
+    pub fn try_upgradable_latch(&self) -> Option<PageUpgradableLatch> {
+        self.0.data.try_upgradable_read_arc()
     }
 
     pub fn set_page_id(&self, page_id: PageId) {
-        self.0.write().page_id = Some(page_id);
+        self.0.meta.lock().page_id = Some(page_id);
     }
 
     /// @return the page id of this page
     pub fn get_page_id(&self) -> Option<PageId> {
-        self.0.read().page_id
+        self.0.meta.lock().page_id
     }
 
     /// @return the pin count of this page
     pub fn get_pin_count(&self) -> i32 {
-        self.0.read().pin_count
+        self.0.meta.lock().pin_count
     }
 
     pub fn pin(&self) {
-        self.0.write().pin_count += 1;
+        self.0.meta.lock().pin_count += 1;
     }
 
     pub fn unpin(&self) {
-        self.0.write().pin_count -= 1;
+        self.0.meta.lock().pin_count -= 1;
     }
 
     /// @return true if the page in memory has been modified from the page on
     /// disk, false otherwise
     pub fn is_dirty(&self) -> bool {
-        self.0.read().is_dirty
+        self.0.meta.lock().is_dirty
     }
 
     pub fn set_dirty(&self, is_dirty: bool) {
-        self.0.write().is_dirty = is_dirty;
+        self.0.meta.lock().is_dirty = is_dirty;
     }
 
-    /// @return the page LSN.
-    // This method assumes that LSN is stored at a certain offset in the data.
-    pub fn get_lsn(&self) -> Lsn {
-        let inner = self.0.read();
-        Lsn::from_ne_bytes(
-            inner.data[OFFSET_LSN..OFFSET_LSN + std::mem::size_of::<Lsn>()]
+    /// @return the page type, or None if the header holds an unknown one
+    pub fn get_page_type(&self) -> Option<PageType> {
+        page_type_of(&self.0.data.read())
+    }
+
+    /// Sets the page type.
+    pub fn set_page_type(&self, page_type: PageType) {
+        set_page_type_of(&mut self.0.data.write(), page_type);
+    }
+
+    /// @return the page checksum stored in the header
+    pub fn get_checksum(&self) -> u32 {
+        let data = self.0.data.read();
+        u32::from_le_bytes(
+            data[OFFSET_CHECKSUM..OFFSET_CHECKSUM + 4]
                 .try_into()
                 .unwrap(),
         )
     }
 
+    /// Sets the page checksum. It is up to whoever writes the page out to
+    /// keep it up to date.
+    pub fn set_checksum(&self, checksum: u32) {
+        let mut data = self.0.data.write();
+        data[OFFSET_CHECKSUM..OFFSET_CHECKSUM + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /// @return the page LSN.
+    pub fn get_lsn(&self) -> Lsn {
+        lsn_of(&self.0.data.read())
+    }
+
     /// Sets the page LSN.
     pub fn set_lsn(&self, lsn: Lsn) {
-        let mut inner = self.0.write();
-        let lsn_bytes = lsn.to_ne_bytes();
-        inner.data[OFFSET_LSN..OFFSET_LSN + std::mem::size_of::<Lsn>()].copy_from_slice(&lsn_bytes);
+        set_lsn_of(&mut self.0.data.write(), lsn);
+    }
+
+    /// Acquires the read latch of this page, for code that reads the page
+    /// over several operations, e.g. while latch crabbing down a B+ tree.
+    /// Unlike the page guards of the buffer pool, the guard does not pin the
+    /// page, the caller has to keep it pinned.
+    pub fn read_guard(&self) -> PageReadGuard {
+        PageReadGuard {
+            page: self.clone(),
+            latch: self.rlatch(),
+        }
+    }
+
+    /// Acquires the write latch of this page, see read_guard.
+    pub fn write_guard(&self) -> PageWriteGuard {
+        PageWriteGuard {
+            page: self.clone(),
+            latch: self.wlatch(),
+        }
+    }
+
+    /// Tries to acquire the write latch of this page without blocking, e.g.
+    /// to back off instead of waiting against the usual latch order.
+    /// @return None if another thread holds the read or write latch
+    pub fn try_write_guard(&self) -> Option<PageWriteGuard> {
+        let latch = self.0.data.try_write_arc()?;
+        Some(PageWriteGuard {
+            page: self.clone(),
+            latch,
+        })
+    }
+}
+
+/// A page with its read latch held, see Page::read_guard. The header fields
+/// are read from the latched data, so they stay consistent with it.
+pub struct PageReadGuard {
+    page: Page,
+    latch: PageReadLatch,
+}
+
+impl PageReadGuard {
+    /// @return the page id of the page
+    pub fn page_id(&self) -> Option<PageId> {
+        self.page.get_page_id()
+    }
+
+    /// @return the pin count of the page
+    pub fn get_pin_count(&self) -> i32 {
+        self.page.get_pin_count()
+    }
+
+    /// @return the page LSN
+    pub fn get_lsn(&self) -> Lsn {
+        lsn_of(&self.latch)
+    }
+
+    /// @return the page type, see Page::get_page_type
+    pub fn get_page_type(&self) -> Option<PageType> {
+        page_type_of(&self.latch)
+    }
+
+    /// @return the data of the page
+    pub fn data(&self) -> &[u8; BUSTUB_PAGE_SIZE] {
+        &self.latch
+    }
+
+    /// @return the body of the page, see Page::body
+    pub fn body(&self) -> &[u8] {
+        &self.latch[SIZE_PAGE_HEADER..]
+    }
+
+    /// Swaps the read latch for the write latch without ever releasing it,
+    /// so no other writer can get in between.
+    ///
+    /// The upgrade never blocks: it fails if any other thread holds a read
+    /// latch on the page, or is itself trying to upgrade, since waiting for
+    /// it could deadlock with a reader waiting for us.
+    ///
+    /// @return the write guard, or this guard, still holding the read latch,
+    /// if the upgrade failed
+    pub fn try_upgrade(self) -> Result<PageWriteGuard, PageReadGuard> {
+        let Some(upgradable) = self.page.try_upgradable_latch() else {
+            return Err(self);
+        };
+        // the upgradable latch keeps writers out, so the read latch is no
+        // longer needed
+        let page = self.page;
+        drop(self.latch);
+        match ArcRwLockUpgradableReadGuard::try_upgrade(upgradable) {
+            Ok(latch) => Ok(PageWriteGuard { page, latch }),
+            Err(upgradable) => Err(PageReadGuard {
+                page,
+                latch: ArcRwLockUpgradableReadGuard::downgrade(upgradable),
+            }),
+        }
+    }
+}
+
+/// A page with its write latch held, see Page::write_guard.
+pub struct PageWriteGuard {
+    page: Page,
+    latch: PageWriteLatch,
+}
+
+impl PageWriteGuard {
+    /// @return the page id of the page
+    pub fn page_id(&self) -> Option<PageId> {
+        self.page.get_page_id()
+    }
+
+    /// @return the pin count of the page
+    pub fn get_pin_count(&self) -> i32 {
+        self.page.get_pin_count()
+    }
+
+    /// @return the page LSN
+    pub fn get_lsn(&self) -> Lsn {
+        lsn_of(&self.latch)
+    }
+
+    /// Sets the page LSN.
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        set_lsn_of(&mut self.latch, lsn);
+    }
+
+    /// @return the page type, see Page::get_page_type
+    pub fn get_page_type(&self) -> Option<PageType> {
+        page_type_of(&self.latch)
+    }
+
+    /// Sets the page type.
+    pub fn set_page_type(&mut self, page_type: PageType) {
+        set_page_type_of(&mut self.latch, page_type);
+    }
+
+    /// @return the data of the page
+    pub fn data(&self) -> &[u8; BUSTUB_PAGE_SIZE] {
+        &self.latch
+    }
+
+    /// @return the data of the page for writing
+    pub fn data_mut(&mut self) -> &mut [u8; BUSTUB_PAGE_SIZE] {
+        &mut self.latch
+    }
+
+    /// @return the body of the page, see Page::body
+    pub fn body(&self) -> &[u8] {
+        &self.latch[SIZE_PAGE_HEADER..]
+    }
+
+    /// @return the body of the page for writing
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        &mut self.latch[SIZE_PAGE_HEADER..]
+    }
+
+    /// Swaps the write latch for a read latch atomically, so no other writer
+    /// can get in between.
+    pub fn downgrade(self) -> PageReadGuard {
+        PageReadGuard {
+            page: self.page,
+            latch: ArcRwLockWriteGuard::downgrade(self.latch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::replacer::{AccessType, ReplacerType};
+    use crate::storage::disk::disk_manager_memory::DiskManagerMemory;
+
+    #[test]
+    fn header_round_trip() {
+        let disk_manager = Box::new(DiskManagerMemory::new());
+        let bpm = BufferPoolManager::new(1, disk_manager, ReplacerType::LruK(2));
+
+        // Scenario: the page type and the LSN survive the page being written
+        // out and read back, and writing the body leaves them alone.
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id().unwrap();
+        assert_eq!(Some(PageType::Invalid), page.get_page_type());
+        page.set_page_type(PageType::BPlusTreeLeaf);
+        page.set_lsn(0x0102_0304_0506_0708);
+        page.body_mut().fill(0xff);
+        bpm.unpin_page(page_id, true).unwrap();
+        let other = bpm.new_page().unwrap();
+        bpm.unpin_page(other.get_page_id().unwrap(), false).unwrap();
+
+        let page = bpm.fetch_page(page_id, AccessType::Unknown).unwrap();
+        assert_eq!(Some(PageType::BPlusTreeLeaf), page.get_page_type());
+        assert_eq!(0x0102_0304_0506_0708, page.get_lsn());
+        assert!(page.body().iter().all(|&b| b == 0xff));
+
+        // Scenario: the header and body split at a fixed offset, with the
+        // fields at fixed places in the header.
+        assert_eq!(SIZE_PAGE_HEADER, page.page_header().len());
+        assert_eq!(BUSTUB_PAGE_SIZE - SIZE_PAGE_HEADER, page.body().len());
+        assert_eq!(
+            [4, 0, 0, 0, 0, 0, 0, 0, 8, 7, 6, 5, 4, 3, 2, 1],
+            *page.page_header()
+        );
+        assert_eq!(0xff, page.get_data()[SIZE_PAGE_HEADER]);
+
+        // Scenario: an unknown page type is reported as such.
+        page.get_data_mut()[OFFSET_PAGE_TYPE] = 0xee;
+        assert_eq!(None, page.get_page_type());
+    }
+
+    #[test]
+    fn body_does_not_overlap_header() {
+        let page = Page::new();
+
+        // Scenario: filling the whole body leaves every header field alone.
+        page.set_page_type(PageType::Table);
+        page.set_checksum(0xdead_beef);
+        page.set_lsn(42);
+        page.body_mut().fill(0xff);
+        assert_eq!(Some(PageType::Table), page.get_page_type());
+        assert_eq!(0xdead_beef, page.get_checksum());
+        assert_eq!(42, page.get_lsn());
+
+        // Scenario: and setting the header fields leaves the body alone.
+        page.set_lsn(Lsn::MAX);
+        page.set_checksum(0);
+        assert!(page.body().iter().all(|&b| b == 0xff));
+    }
+
+    fn read_count(body: &[u8]) -> u64 {
+        u64::from_le_bytes(body[..8].try_into().unwrap())
+    }
+
+    #[test]
+    fn latch_crabbing() {
+        let pages: Arc<Vec<Page>> = Arc::new((0..3).map(|_| Page::new()).collect());
+        let rounds = 200;
+
+        // Scenario: writers crab down p0 -> p1 -> p2, taking the next latch
+        // before releasing the previous one, and bump a counter in every
+        // page. Readers crab the same way. As nobody can overtake anybody on
+        // the way down, readers always see the counters in descending order,
+        // and since everybody latches in the same order nobody deadlocks.
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let pages = pages.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..rounds {
+                        let mut parent = pages[0].write_guard();
+                        for page in pages[1..].iter() {
+                            let count = read_count(parent.body());
+                            parent.body_mut()[..8].copy_from_slice(&(count + 1).to_le_bytes());
+                            let child = page.write_guard();
+                            parent = child;
+                        }
+                        let count = read_count(parent.body());
+                        parent.body_mut()[..8].copy_from_slice(&(count + 1).to_le_bytes());
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let pages = pages.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..rounds {
+                        let mut parent = pages[0].read_guard();
+                        for page in pages[1..].iter() {
+                            let child = page.read_guard();
+                            assert!(read_count(parent.body()) >= read_count(child.body()));
+                            parent = child;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+        for page in pages.iter() {
+            assert_eq!(4 * rounds, read_count(&page.body()));
+        }
+
+        // Scenario: a writer going against the latch order backs off instead
+        // of waiting, while a reader holds the page.
+        let reader = pages[2].read_guard();
+        assert!(pages[2].try_write_guard().is_none());
+        assert!(pages[1].try_write_guard().is_some());
+        drop(reader);
+        let writer = pages[2].try_write_guard().unwrap();
+        assert!(pages[2].try_write_guard().is_none());
+
+        // Scenario: a downgraded guard keeps the latch, so no writer gets in
+        // between, and an upgraded one keeps it too.
+        let reader = writer.downgrade();
+        assert!(pages[2].try_write_guard().is_none());
+        let other = pages[2].read_guard();
+        let reader = reader.try_upgrade().err().unwrap();
+        drop(other);
+        let writer = reader.try_upgrade().ok().unwrap();
+        assert_eq!(4 * rounds, read_count(writer.body()));
+    }
+
+    #[test]
+    fn guards_expose_page_state() {
+        let disk_manager = Box::new(DiskManagerMemory::new());
+        let bpm = BufferPoolManager::new(1, disk_manager, ReplacerType::LruK(2));
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id();
+
+        // Scenario: a write guard sees and changes the page id, pin count,
+        // header and data of the page it latches, all at once.
+        let mut writer = page.write_guard();
+        assert_eq!(page_id, writer.page_id());
+        assert_eq!(1, writer.get_pin_count());
+        writer.set_page_type(PageType::Table);
+        writer.set_lsn(9);
+        writer.body_mut()[0] = 1;
+        assert_eq!(Some(PageType::Table), writer.get_page_type());
+        assert_eq!(9, writer.get_lsn());
+        assert_eq!(1, writer.data()[SIZE_PAGE_HEADER]);
+        writer.data_mut()[SIZE_PAGE_HEADER] = 2;
+
+        // Scenario: the read guard it downgrades to reads the header from its
+        // own latch. The guards do not pin the page themselves.
+        let reader = writer.downgrade();
+        assert_eq!(page_id, reader.page_id());
+        assert_eq!(1, reader.get_pin_count());
+        assert_eq!(Some(PageType::Table), reader.get_page_type());
+        assert_eq!(9, reader.get_lsn());
+        assert_eq!(2, reader.body()[0]);
+        assert_eq!(reader.body(), &reader.data()[SIZE_PAGE_HEADER..]);
+        drop(reader);
+        bpm.unpin_page(page_id.unwrap(), true).unwrap();
+        assert_eq!(0, page.read_guard().get_pin_count());
     }
 }
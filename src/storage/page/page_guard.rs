@@ -1,13 +1,17 @@
 use std::sync::Arc;
 
 use crate::buffer::buffer_pool_manager::BufferPoolManager;
-use crate::common::config::PageId;
-use crate::storage::page::page::{MutRefPageData, Page, RefPageData};
+use crate::common::config::{PageId, BUSTUB_PAGE_SIZE};
+use crate::storage::page::page::{
+    MutRefPageBytes, MutRefPageData, Page, PageReadGuard, PageWriteGuard, RefPageBytes, RefPageData,
+};
 
 pub struct BasicPageGuard {
     bpm: Arc<BufferPoolManager>,
     page: Page,
     is_dirty: bool,
+    // Set once the page has been unpinned, so that it is unpinned only once.
+    released: bool,
 }
 
 impl BasicPageGuard {
@@ -16,23 +20,32 @@ impl BasicPageGuard {
             bpm,
             page,
             is_dirty: false,
+            released: false,
         }
     }
 
-    /// TODO(P2): Add implementation
-    ///
+    /// Tells the BPM that we are done using this page. Only the first call
+    /// has any effect.
+    fn release(&mut self) {
+        if self.released {
+            return;
+        }
+        self.released = true;
+        // the guard holds a pin on the page, so this cannot fail
+        let _ = self.bpm.unpin_page(self.page_id(), self.is_dirty);
+    }
+
     /// @brief Drop a page guard
     ///
     /// Dropping a page guard should clear all contents
     /// (so that the page guard is no longer useful), and
     /// it should tell the BPM that we are done using this page,
-    /// per the specification in the writeup.
-    pub fn drop(&self) {
-        unimplemented!()
+    /// per the specification in the writeup. Dropping it again, explicitly
+    /// or when it goes out of scope, does nothing.
+    pub fn drop(&mut self) {
+        self.release();
     }
 
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Upgrade a BasicPageGuard to a ReadPageGuard
     ///
     /// The protected page is not evicted from the buffer pool during the
@@ -40,12 +53,15 @@ impl BasicPageGuard {
     /// calling this function.
     ///
     /// @return an upgraded ReadPageGuard
-    pub fn upgrade_read(self) -> ReadPageGuard {
-        unimplemented!()
+    pub fn upgrade_read(mut self) -> ReadPageGuard {
+        self.hand_over();
+        ReadPageGuard {
+            bpm: self.bpm.clone(),
+            latch: Some(self.page.read_guard()),
+            is_dirty: self.is_dirty,
+        }
     }
 
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Upgrade a BasicPageGuard to a WritePageGuard
     ///
     /// The protected page is not evicted from the buffer pool during the
@@ -53,8 +69,19 @@ impl BasicPageGuard {
     /// calling this function.
     ///
     /// @return an upgraded WritePageGuard
-    pub fn upgrade_write(self) -> WritePageGuard {
-        unimplemented!()
+    pub fn upgrade_write(mut self) -> WritePageGuard {
+        self.hand_over();
+        WritePageGuard {
+            bpm: self.bpm.clone(),
+            latch: Some(self.page.write_guard()),
+        }
+    }
+
+    /// Invalidates this guard for the pin it holds to move into a new guard,
+    /// so the pin count never drops in between.
+    fn hand_over(&mut self) {
+        assert!(!self.released, "page guard already dropped");
+        self.released = true;
     }
 
     pub fn page_id(&self) -> PageId {
@@ -69,11 +96,19 @@ impl BasicPageGuard {
         self.is_dirty = true;
         self.page.get_data_mut()
     }
+
+    /// Retrieves the body of the page, see Page::body
+    pub fn body(&self) -> RefPageBytes<'_> {
+        self.page.body()
+    }
+
+    pub fn body_mut(&mut self) -> MutRefPageBytes<'_> {
+        self.is_dirty = true;
+        self.page.body_mut()
+    }
 }
 
 impl Drop for BasicPageGuard {
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Drop a page guard
     ///
     /// Dropping a page guard should clear all contents
@@ -81,109 +116,193 @@ impl Drop for BasicPageGuard {
     /// it should tell the BPM that we are done using this page,
     /// per the specification in the writeup.
     fn drop(&mut self) {
-        unimplemented!()
+        self.release();
     }
 }
 
+/// A pinned page with its read latch held. The latch is a Page::read_guard.
 pub struct ReadPageGuard {
-    // You may choose to get rid of this and add your own private variables.
-    guard: BasicPageGuard,
+    bpm: Arc<BufferPoolManager>,
+    // None once the guard was dropped
+    latch: Option<PageReadGuard>,
+    // Whether the page is unpinned as dirty, i.e. it was downgraded from a
+    // WritePageGuard
+    is_dirty: bool,
 }
 
 impl ReadPageGuard {
+    /// Takes the read latch of an already pinned page. Blocks while another
+    /// thread holds the write latch.
     pub fn new(bpm: Arc<BufferPoolManager>, page: Page) -> Self {
         Self {
-            guard: BasicPageGuard::new(bpm, page),
+            bpm,
+            latch: Some(page.read_guard()),
+            is_dirty: false,
         }
     }
 
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Drop a ReadPageGuard
     ///
-    /// ReadPageGuard's Drop should behave similarly to BasicPageGuard,
-    /// except that ReadPageGuard has an additional resource - the latch!
-    /// However, you should think VERY carefully about in which order you
-    /// want to release these resources.
+    /// The latch is released before the page is unpinned: once unpinned the
+    /// frame may be evicted and reused, and nobody may still be latching it
+    /// by then.
     pub fn drop(&mut self) {
-        unimplemented!()
+        if let Some(latch) = self.latch.take() {
+            let page_id = latch.page_id().unwrap();
+            drop(latch);
+            // the guard holds a pin on the page, so this cannot fail
+            let _ = self.bpm.unpin_page(page_id, self.is_dirty);
+        }
+    }
+
+    /// @brief Upgrade a ReadPageGuard to a WritePageGuard without ever
+    /// releasing the read latch, see PageReadGuard::try_upgrade.
+    ///
+    /// @return the upgraded WritePageGuard, or this guard, still holding the
+    /// read latch, if the upgrade failed
+    pub fn try_upgrade(mut self) -> Result<WritePageGuard, ReadPageGuard> {
+        let latch = self.latch.take().expect("page guard already dropped");
+        match latch.try_upgrade() {
+            Ok(latch) => Ok(WritePageGuard {
+                bpm: self.bpm.clone(),
+                latch: Some(latch),
+            }),
+            Err(latch) => {
+                self.latch = Some(latch);
+                Err(self)
+            }
+        }
+    }
+
+    fn latch(&self) -> &PageReadGuard {
+        self.latch.as_ref().expect("page guard already dropped")
     }
 
     pub fn page_id(&self) -> PageId {
-        self.guard.page_id()
+        self.latch().page_id().unwrap()
     }
 
     /// Retrieves the data from the page
-    pub fn get_data(&self) -> RefPageData {
-        self.guard.get_data()
+    pub fn get_data(&self) -> &[u8; BUSTUB_PAGE_SIZE] {
+        self.latch().data()
+    }
+
+    /// Retrieves the body of the page, see Page::body
+    pub fn body(&self) -> &[u8] {
+        self.latch().body()
     }
 }
 
 impl Drop for ReadPageGuard {
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Destructor for ReadPageGuard
     ///
     /// Just like with BasicPageGuard, this should behave
     /// as if you were dropping the guard.
     fn drop(&mut self) {
-        unimplemented!()
+        ReadPageGuard::drop(self);
     }
 }
 
+/// A pinned page with its write latch held. The latch is a
+/// Page::write_guard. The page is always unpinned as dirty.
 pub struct WritePageGuard {
-    // You may choose to get rid of this and add your own private variables.
-    guard: BasicPageGuard,
+    bpm: Arc<BufferPoolManager>,
+    // None once the guard was dropped
+    latch: Option<PageWriteGuard>,
 }
 
 impl WritePageGuard {
+    /// Takes the write latch of an already pinned page. Blocks while any
+    /// other thread holds the read or write latch.
     pub fn new(bpm: Arc<BufferPoolManager>, page: Page) -> Self {
-        WritePageGuard {
-            guard: BasicPageGuard::new(bpm, page),
+        Self {
+            bpm,
+            latch: Some(page.write_guard()),
         }
     }
 
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Drop a WritePageGuard
     ///
-    /// WritePageGuard's Drop should behave similarly to BasicPageGuard,
-    /// except that WritePageGuard has an additional resource - the latch!
+    /// Releases the latch and then unpins the page as dirty, in the same
+    /// order as ReadPageGuard.
     pub fn drop(&mut self) {
-        unimplemented!()
+        if let Some(latch) = self.latch.take() {
+            let page_id = latch.page_id().unwrap();
+            drop(latch);
+            // Anybody asking for the write latch is going to modify the page.
+            let _ = self.bpm.unpin_page(page_id, true);
+        }
+    }
+
+    /// @brief Downgrade a WritePageGuard to a ReadPageGuard
+    ///
+    /// The write latch is swapped for a read latch atomically and the pin
+    /// moves into the new guard, so neither another writer nor an eviction
+    /// can get in between. The page is still unpinned as dirty.
+    ///
+    /// @return the downgraded ReadPageGuard
+    pub fn downgrade(mut self) -> ReadPageGuard {
+        let latch = self.latch.take().expect("page guard already dropped");
+        ReadPageGuard {
+            bpm: self.bpm.clone(),
+            latch: Some(latch.downgrade()),
+            is_dirty: true,
+        }
+    }
+
+    fn latch(&self) -> &PageWriteGuard {
+        self.latch.as_ref().expect("page guard already dropped")
+    }
+
+    fn latch_mut(&mut self) -> &mut PageWriteGuard {
+        self.latch.as_mut().expect("page guard already dropped")
     }
 
     pub fn page_id(&self) -> PageId {
-        self.guard.page_id()
+        self.latch().page_id().unwrap()
     }
 
-    pub fn get_data(&self) -> RefPageData {
-        self.guard.get_data()
+    pub fn get_data(&self) -> &[u8; BUSTUB_PAGE_SIZE] {
+        self.latch().data()
     }
 
-    pub fn get_data_mut(&mut self) -> MutRefPageData {
-        self.guard.get_data_mut()
+    pub fn get_data_mut(&mut self) -> &mut [u8; BUSTUB_PAGE_SIZE] {
+        self.latch_mut().data_mut()
+    }
+
+    /// Retrieves the body of the page, see Page::body
+    pub fn body(&self) -> &[u8] {
+        self.latch().body()
+    }
+
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        self.latch_mut().body_mut()
     }
 }
 
 impl Drop for WritePageGuard {
-    /// TODO(P2): Add implementation
-    ///
     /// @brief Destructor for WritePageGuard
     ///
     /// Just like with BasicPageGuard, this should behave
     /// as if you were dropping the guard.
     fn drop(&mut self) {
-        unimplemented!()
+        WritePageGuard::drop(self);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Barrier};
+    use std::thread;
+
     use tempdir::TempDir;
 
     use super::*;
+    use crate::buffer::buffer_pool_manager::BufferError;
+    use crate::buffer::replacer::ReplacerType;
     use crate::storage::disk::disk_manager::DiskManager;
+    use crate::storage::page::page::SIZE_PAGE_HEADER;
     #[test]
     fn test_page_guard_sample() {
         let buffer_pool_size = 5;
@@ -191,12 +310,16 @@ mod tests {
 
         let dir = TempDir::new("test").unwrap();
         let db_file = dir.path().join("test.db");
-        let disk_manager = DiskManager::new(db_file.to_str().unwrap());
-        let bpm = Arc::new(BufferPoolManager::new(buffer_pool_size, disk_manager, k));
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            buffer_pool_size,
+            disk_manager,
+            ReplacerType::LruK(k),
+        ));
 
         let page0 = bpm.new_page().unwrap();
 
-        let guarded_page = BasicPageGuard::new(bpm.clone(), page0.clone());
+        let mut guarded_page = BasicPageGuard::new(bpm.clone(), page0.clone());
 
         assert_eq!(*page0.get_data(), *guarded_page.get_data());
         assert_eq!(page0.get_page_id(), Some(guarded_page.page_id()));
@@ -215,4 +338,305 @@ mod tests {
         // Shutdown the disk manager and remove the temporary file we created.
         drop(bpm);
     }
+
+    #[test]
+    fn test_page_guard_concurrent_read_and_write() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            5,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+
+        let page0 = bpm.new_page().unwrap();
+        let page_id = page0.get_page_id().unwrap();
+        assert!(bpm.unpin_page(page_id, false).is_ok());
+
+        // two readers latch the page at the same time
+        let barrier = Arc::new(Barrier::new(3));
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Arc::new(std::sync::Mutex::new(release_rx));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let bpm = bpm.clone();
+                let barrier = barrier.clone();
+                let release_rx = release_rx.clone();
+                thread::spawn(move || {
+                    let guard = bpm.fetch_page_read(page_id).unwrap();
+                    barrier.wait();
+                    release_rx.lock().unwrap().recv().unwrap();
+                    drop(guard);
+                })
+            })
+            .collect();
+        barrier.wait();
+        assert_eq!(2, page0.get_pin_count());
+
+        // a writer has to wait until both readers are gone
+        assert!(page0.try_write_guard().is_none());
+
+        release_tx.send(()).unwrap();
+        release_tx.send(()).unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert!(page0.try_write_guard().is_some());
+        bpm.clone().fetch_page_write(page_id).unwrap().body_mut()[0] = 42;
+        assert_eq!(0, page0.get_pin_count());
+        assert!(page0.is_dirty());
+        assert_eq!(42, bpm.fetch_page_read(page_id).unwrap().body()[0]);
+    }
+
+    #[test]
+    fn test_page_guard_fetch_after_eviction() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            1,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+
+        let page_id = bpm.new_page().unwrap().get_page_id().unwrap();
+        assert!(bpm.unpin_page(page_id, false).is_ok());
+        {
+            let mut guard = bpm.clone().fetch_page_write(page_id).unwrap();
+            guard.body_mut()[..5].copy_from_slice(b"Hello");
+        }
+
+        // the only frame holds a dirty page, which has to be written back
+        // before another page can use the frame
+        let other_page_id = bpm.new_page().unwrap().get_page_id().unwrap();
+        assert!(bpm.unpin_page(other_page_id, false).is_ok());
+
+        let guard = bpm.clone().fetch_page_read(page_id).unwrap();
+        assert_eq!(b"Hello", &guard.body()[..5]);
+        assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+        drop(guard);
+        assert!(bpm.new_page().is_ok());
+    }
+
+    #[test]
+    fn test_page_guard_drop_twice() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            5,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+
+        let page0 = bpm.new_page().unwrap();
+        page0.pin();
+        assert_eq!(2, page0.get_pin_count());
+
+        // the guard owns exactly one of the two pins
+        let mut guard = BasicPageGuard::new(bpm.clone(), page0.clone());
+        guard.drop();
+        assert_eq!(1, page0.get_pin_count());
+        guard.drop();
+        assert_eq!(1, page0.get_pin_count());
+        drop(guard);
+        assert_eq!(1, page0.get_pin_count());
+
+        let mut guard = ReadPageGuard::new(bpm.clone(), page0.clone());
+        guard.drop();
+        assert_eq!(0, page0.get_pin_count());
+        drop(guard);
+        assert_eq!(0, page0.get_pin_count());
+        // the latch is gone with the first drop
+        assert!(page0.wlatch()[0] == 0);
+    }
+
+    #[test]
+    fn test_page_guard_upgrade() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            1,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+
+        let mut page_id = None;
+        let mut guard = bpm.clone().new_page_guarded(&mut page_id).unwrap();
+        let page_id = page_id.unwrap();
+        guard.body_mut()[0] = 1;
+        assert_eq!(1, guard.body()[0]);
+        let page0 = bpm.get_pages()[0].clone();
+        assert_eq!(1, page0.get_pin_count());
+
+        // the pin moves into the upgraded guard, so the only frame can never be
+        // handed out in between
+        let read_guard = guard.upgrade_read();
+        assert_eq!(1, page0.get_pin_count());
+        assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+        assert_eq!(page_id, read_guard.page_id());
+        assert_eq!(1, read_guard.body()[0]);
+        drop(read_guard);
+        assert_eq!(0, page0.get_pin_count());
+        // the dirty flag of the basic guard survives the upgrade
+        assert!(page0.is_dirty());
+
+        let guard = bpm.clone().fetch_page_basic(page_id).unwrap();
+        assert_eq!(1, page0.get_pin_count());
+        let mut write_guard = guard.upgrade_write();
+        assert_eq!(1, page0.get_pin_count());
+        assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+        assert_eq!(page_id, write_guard.page_id());
+        assert_eq!(1, write_guard.body()[0]);
+        write_guard.body_mut()[0] = 2;
+        assert_eq!(2, write_guard.get_data()[SIZE_PAGE_HEADER]);
+        write_guard.get_data_mut()[SIZE_PAGE_HEADER + 1] = 3;
+        drop(write_guard);
+        assert_eq!(0, page0.get_pin_count());
+
+        let read_guard = bpm.clone().fetch_page_read(page_id).unwrap();
+        assert_eq!(2, read_guard.body()[0]);
+        assert_eq!(3, read_guard.get_data()[SIZE_PAGE_HEADER + 1]);
+        assert_eq!(1, page0.get_pin_count());
+    }
+
+    #[test]
+    fn test_page_guard_downgrade() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            2,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+
+        let page_id = bpm.new_page().unwrap().get_page_id().unwrap();
+        assert!(bpm.unpin_page(page_id, false).is_ok());
+
+        // Scenario: other threads keep cycling pages through the pool, so any
+        // moment where our page is unpinned would get its frame evicted.
+        let stop = Arc::new(AtomicBool::new(false));
+        let pressure: Vec<_> = (0..4)
+            .map(|_| {
+                let bpm = bpm.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        if let Ok(page) = bpm.new_page() {
+                            let _ = bpm.unpin_page(page.get_page_id().unwrap(), false);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for round in 0..200u32 {
+            // the page may have been evicted since the last round, and the
+            // other frame may be pinned by a pressure thread right now
+            let mut write_guard = loop {
+                match bpm.clone().fetch_page_write(page_id) {
+                    Ok(guard) => break guard,
+                    Err(BufferError::NoFreeFrame) => thread::yield_now(),
+                    Err(e) => panic!("{}", e),
+                }
+            };
+            let page = bpm
+                .get_pages()
+                .iter()
+                .find(|page| page.get_page_id() == Some(page_id))
+                .unwrap()
+                .clone();
+            write_guard.body_mut()[..4].copy_from_slice(&round.to_ne_bytes());
+
+            let read_guard = write_guard.downgrade();
+            assert_eq!(Some(page_id), page.get_page_id());
+            assert!(page.get_pin_count() >= 1);
+            assert_eq!(round.to_ne_bytes(), read_guard.body()[..4]);
+            // a second reader can latch the page next to us
+            let other = bpm.clone().fetch_page_read(page_id).unwrap();
+            assert_eq!(round.to_ne_bytes(), other.body()[..4]);
+            drop(other);
+            drop(read_guard);
+            // the downgraded guard still unpins the page as dirty
+            assert!(page.is_dirty() || page.get_page_id() != Some(page_id));
+        }
+
+        stop.store(true, Ordering::SeqCst);
+        for handle in pressure {
+            handle.join().unwrap();
+        }
+
+        // Scenario: no writer can slip in while the write latch is being
+        // swapped for a read latch.
+        let mut write_guard = bpm.clone().fetch_page_write(page_id).unwrap();
+        write_guard.body_mut()[0] = 1;
+        let page = bpm
+            .get_pages()
+            .iter()
+            .find(|page| page.get_page_id() == Some(page_id))
+            .unwrap()
+            .clone();
+        assert!(page.try_write_guard().is_none());
+        let read_guard = write_guard.downgrade();
+        assert!(page.try_write_guard().is_none());
+        assert_eq!(1, read_guard.body()[0]);
+        drop(read_guard);
+        bpm.clone().fetch_page_write(page_id).unwrap().body_mut()[0] = 2;
+        assert_eq!(2, bpm.fetch_page_read(page_id).unwrap().body()[0]);
+    }
+
+    #[test]
+    fn test_page_guard_try_upgrade() {
+        let dir = TempDir::new("test").unwrap();
+        let db_file = dir.path().join("test.db");
+        let disk_manager = Box::new(DiskManager::new(db_file.to_str().unwrap()).unwrap());
+        let bpm = Arc::new(BufferPoolManager::new(
+            1,
+            disk_manager,
+            ReplacerType::LruK(2),
+        ));
+
+        let page_id = bpm.new_page().unwrap().get_page_id().unwrap();
+        assert!(bpm.unpin_page(page_id, false).is_ok());
+        let page0 = bpm.get_pages()[0].clone();
+
+        // Scenario: with another reader around, both upgrades fail instead of
+        // waiting for each other, and both guards keep their read latch.
+        let guard1 = bpm.clone().fetch_page_read(page_id).unwrap();
+        let guard2 = bpm.clone().fetch_page_read(page_id).unwrap();
+        let guard1 = guard1.try_upgrade().err().unwrap();
+        let guard2 = guard2.try_upgrade().err().unwrap();
+        assert_eq!(2, page0.get_pin_count());
+        assert_eq!(0, guard1.body()[0]);
+        assert_eq!(0, guard2.body()[0]);
+
+        // Scenario: the last reader upgrades, keeping its pin.
+        drop(guard2);
+        let mut write_guard = match guard1.try_upgrade() {
+            Ok(write_guard) => write_guard,
+            Err(_) => panic!("the only reader failed to upgrade"),
+        };
+        assert_eq!(1, page0.get_pin_count());
+        assert!(matches!(bpm.new_page(), Err(BufferError::NoFreeFrame)));
+        write_guard.body_mut()[0] = 1;
+        drop(write_guard);
+        assert_eq!(0, page0.get_pin_count());
+        assert!(page0.is_dirty());
+
+        // Scenario: a read guard that failed to upgrade still blocks writers.
+        let guard1 = bpm.clone().fetch_page_read(page_id).unwrap();
+        let guard2 = bpm.clone().fetch_page_read(page_id).unwrap();
+        let guard1 = guard1.try_upgrade().err().unwrap();
+        drop(guard2);
+        assert!(page0.try_write_guard().is_none());
+        assert!(page0.try_upgradable_latch().is_some());
+        assert_eq!(1, guard1.body()[0]);
+        drop(guard1);
+        bpm.clone().fetch_page_write(page_id).unwrap().body_mut()[0] = 2;
+        assert_eq!(0, page0.get_pin_count());
+        assert_eq!(2, bpm.fetch_page_read(page_id).unwrap().body()[0]);
+    }
 }
@@ -0,0 +1,181 @@
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::common::config::BUSTUB_PAGE_SIZE;
+use crate::storage::page::page::{
+    page_type_of, set_page_type_of, MutRefPageData, Page, PageType, RefPageData, SIZE_PAGE_HEADER,
+};
+
+/// A page layout says how one kind of page arranges its data in the page
+/// body. Its typed getters and setters are implemented on `TypedView<Self, G>`
+/// on top of the byte accessors of TypedView, so that page format code never
+/// deals with offsets into the whole page.
+pub trait PageLayout {
+    /// The page type a page with this layout has in its header.
+    const PAGE_TYPE: PageType;
+
+    /// Sets up the zeroed body of a page that is given this layout. By
+    /// default the body stays all zeros.
+    fn init(_body: &mut [u8]) {}
+}
+
+/// The data of a page seen through the layout T. G is what the data is
+/// borrowed through, e.g. a latch guard, which is kept alive for as long as
+/// the view is. Offsets are relative to the page body.
+pub struct TypedView<T, G> {
+    data: G,
+    _layout: PhantomData<T>,
+}
+
+/// A view of a page that holds its read latch.
+pub type PageView<'a, T> = TypedView<T, RefPageData<'a>>;
+
+/// A view of a page that holds its write latch.
+pub type PageViewMut<'a, T> = TypedView<T, MutRefPageData<'a>>;
+
+impl<T: PageLayout, G: Deref<Target = [u8; BUSTUB_PAGE_SIZE]>> TypedView<T, G> {
+    /// Views data as a page with the layout T.
+    /// @return None if the page type in the header is not the layout's
+    pub fn new(data: G) -> Option<Self> {
+        if page_type_of(&data) != Some(T::PAGE_TYPE) {
+            return None;
+        }
+        Some(TypedView {
+            data,
+            _layout: PhantomData,
+        })
+    }
+
+    /// @return the page body
+    pub fn body(&self) -> &[u8] {
+        &self.data[SIZE_PAGE_HEADER..]
+    }
+
+    /// @return the little endian u32 at offset of the body
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.body()[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// @return the little endian u64 at offset of the body
+    pub fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.body()[offset..offset + 8].try_into().unwrap())
+    }
+}
+
+impl<T: PageLayout, G: DerefMut<Target = [u8; BUSTUB_PAGE_SIZE]>> TypedView<T, G> {
+    /// Gives data the layout T: sets the page type in the header and sets up
+    /// a fresh body, throwing away what the page held before.
+    pub fn init(mut data: G) -> Self {
+        set_page_type_of(&mut data, T::PAGE_TYPE);
+        let body = &mut data[SIZE_PAGE_HEADER..];
+        body.fill(0);
+        T::init(body);
+        TypedView {
+            data,
+            _layout: PhantomData,
+        }
+    }
+
+    /// @return the page body for writing
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        &mut self.data[SIZE_PAGE_HEADER..]
+    }
+
+    /// Stores value little endian at offset of the body.
+    pub fn write_u32(&mut self, offset: usize, value: u32) {
+        self.body_mut()[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Stores value little endian at offset of the body.
+    pub fn write_u64(&mut self, offset: usize, value: u64) {
+        self.body_mut()[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl<'a, T: PageLayout> PageView<'a, T> {
+    /// Takes the read latch of page and views it with the layout T.
+    /// @return None if the page has a different page type
+    pub fn of(page: &'a Page) -> Option<Self> {
+        Self::new(page.get_data())
+    }
+}
+
+impl<'a, T: PageLayout> PageViewMut<'a, T> {
+    /// Takes the write latch of page and views it with the layout T.
+    /// @return None if the page has a different page type
+    pub fn of(page: &'a Page) -> Option<Self> {
+        Self::new(page.get_data_mut())
+    }
+
+    /// Takes the write latch of page and gives it the layout T, see init.
+    pub fn init_page(page: &'a Page) -> Self {
+        Self::init(page.get_data_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A layout with a counter in the first 8 bytes of the body, which starts
+    /// at 1.
+    struct CounterPage;
+
+    impl PageLayout for CounterPage {
+        const PAGE_TYPE: PageType = PageType::Table;
+
+        fn init(body: &mut [u8]) {
+            body[..8].copy_from_slice(&1u64.to_le_bytes());
+        }
+    }
+
+    impl<G: Deref<Target = [u8; BUSTUB_PAGE_SIZE]>> TypedView<CounterPage, G> {
+        fn get_count(&self) -> u64 {
+            self.read_u64(0)
+        }
+    }
+
+    impl<G: DerefMut<Target = [u8; BUSTUB_PAGE_SIZE]>> TypedView<CounterPage, G> {
+        fn increment(&mut self) {
+            let count = self.get_count();
+            self.write_u64(0, count + 1);
+        }
+    }
+
+    #[test]
+    fn views_of_two_pages() {
+        let page1 = Page::new();
+        let page2 = Page::new();
+
+        // Scenario: a page only gets a view with the layout of its type.
+        assert!(PageView::<CounterPage>::of(&page1).is_none());
+        drop(PageViewMut::<CounterPage>::init_page(&page1));
+        page1.set_lsn(7);
+        assert_eq!(Some(PageType::Table), page1.get_page_type());
+
+        // Scenario: views of different pages coexist, a writing one next to
+        // a reading one. The writes go to the body only.
+        PageViewMut::<CounterPage>::init_page(&page2);
+        let mut view1 = PageViewMut::<CounterPage>::of(&page1).unwrap();
+        let view2 = PageView::<CounterPage>::of(&page2).unwrap();
+        view1.increment();
+        view1.increment();
+        assert_eq!(3, view1.get_count());
+        assert_eq!(1, view2.get_count());
+        assert_eq!(BUSTUB_PAGE_SIZE - SIZE_PAGE_HEADER, view2.body().len());
+
+        // Scenario: a view holds the page latch for as long as it lives. The
+        // view borrows the page, so `drop(page1)` does not compile here.
+        assert!(page1.try_upgradable_latch().is_none());
+        assert!(page2.try_upgradable_latch().is_some());
+        drop(view1);
+        drop(view2);
+        assert!(page1.try_upgradable_latch().is_some());
+        assert_eq!(7, page1.get_lsn());
+
+        // Scenario: a view works over latches that are held elsewhere too.
+        let latch = page1.rlatch();
+        let view = TypedView::<CounterPage, _>::new(&*latch).unwrap();
+        assert_eq!(3, view.get_count());
+    }
+}
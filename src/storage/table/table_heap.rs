@@ -217,7 +217,7 @@ mod tests {
         let db_path = "./test_table_heap_new.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(10, Arc::new(disk_manager));
         let table_heap = TableHeap::new(buffer_pool_manager);
         assert_eq!(table_heap.first_page_id, 0);
@@ -232,7 +232,7 @@ mod tests {
         let db_path = "./test_table_heap_insert_tuple.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut table_heap = TableHeap::new(buffer_pool_manager);
         let meta = super::TupleMeta {
@@ -264,7 +264,7 @@ mod tests {
         let db_path = "./test_table_heap_update_tuple_meta.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut table_heap = TableHeap::new(buffer_pool_manager);
         let meta = super::TupleMeta {
@@ -303,7 +303,7 @@ mod tests {
         let db_path = "./test_table_heap_get_tuple.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut table_heap = TableHeap::new(buffer_pool_manager);
 
@@ -354,7 +354,7 @@ mod tests {
         let db_path = "./test_table_heap_iterator.db";
         let _ = remove_file(db_path);
 
-        let disk_manager = disk_manager::DiskManager::new(db_path.to_string());
+        let disk_manager = disk_manager::DiskManager::new(db_path.to_string()).unwrap();
         let buffer_pool_manager = BufferPoolManager::new(1000, Arc::new(disk_manager));
         let mut table_heap = TableHeap::new(buffer_pool_manager);
 